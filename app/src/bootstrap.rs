@@ -1,36 +1,172 @@
-use std::num::NonZeroU8;
+use std::{ net::SocketAddr, num::NonZeroU8, path::PathBuf, time::Duration };
 
+use access_logs::access_log_service_log::AccessLogServiceLogs;
 use common::{ bmarc, types::BMArc };
-use gcp::gcp_service_log::GCPServiceLogs;
+use gcp::{
+    gcp_file_service_log::GCPFileServiceLogs,
+    gcp_service_log::{ GCPServiceLogs, GCPServiceLogsOptions },
+};
 use graph_falkor::GraphFalkor;
-use anyhow::{ Ok, Result };
-use logs_to_graph::{ LogsToGraph, service_graph::ServiceGraph, service_logs::ServiceLogs };
+use anyhow::{ Context, Ok, Result, bail };
+use jaeger::jaeger_file_service_log::JaegerFileServiceLogs;
+use otlp::otlp_service_log::OtlpServiceLogs;
+use zipkin::{
+    zipkin_collector_service_log::ZipkinCollectorServiceLogs,
+    zipkin_file_service_log::ZipkinFileServiceLogs,
+};
+use logs_to_graph::{
+    LogsToGraph,
+    history::{ GraphHistory, HistoryServiceGraph },
+    service_graph::ServiceGraph,
+    service_logs::ServiceLogs,
+};
 
 use crate::{ config::{ Config, GraphEngine, LogEngine }, fake_service_log::FakeServiceLog };
 
 pub async fn build_dependencies(config: Config) -> Result<LogsToGraph> {
     let service_graph: BMArc<dyn ServiceGraph> = match config.graph_engine {
-        GraphEngine::Falkor { max_pool, database_url, graph_name } => {
+        GraphEngine::Falkor { max_pool, database_url, graph_name, stale_after, prune_stale } => {
             let revised_max_pool = max_pool.unwrap_or(NonZeroU8::new(1).unwrap());
-            let graph = GraphFalkor::new(database_url, graph_name, revised_max_pool).await?;
+            let mut graph = GraphFalkor::new(database_url, graph_name, revised_max_pool).await?;
+            if let Some(stale_after) = stale_after {
+                let stale_after = humantime::parse_duration(&stale_after).context("Invalid stale_after")?;
+                graph = graph.with_stale_after(stale_after, prune_stale.unwrap_or(false));
+            }
             bmarc!(graph)
         }
     };
 
-    let service_logs: BMArc<dyn ServiceLogs> = match config.log_engine {
-        LogEngine::GCP { project_id, page_size, custom_log_filter } => {
-            let custom_path_normalize_patterns = config.http_config
-                .map(|http| http.request_paths.custom_normalize_patterns)
-                .or(Some(vec![]))
-                .unwrap();
-
-            let service_logs = GCPServiceLogs::new(
-                project_id,
-                page_size.unwrap_or(100),
-                custom_log_filter,
-                custom_path_normalize_patterns
-            ).await?;
+    let service_graph: BMArc<dyn ServiceGraph> = match config.history {
+        Some(history) => {
+            let stale_after = history.stale_after
+                .map(|stale_after| humantime::parse_duration(&stale_after))
+                .transpose()
+                .context("Invalid history stale_after")?;
+            let graph_history = GraphHistory::open(history.path, stale_after)?;
+            bmarc!(HistoryServiceGraph::new(graph_history, service_graph))
+        }
+        None => service_graph,
+    };
+
+    let custom_path_normalize_patterns = config.http_config
+        .map(|http| http.request_paths.custom_normalize_patterns)
+        .unwrap_or_default();
+
+    let is_single_gcp_engine = matches!(config.log_engines.as_slice(), [LogEngine::Gcp { .. }]);
+    if config.record_dir.is_some() && !is_single_gcp_engine {
+        bail!("Recording is only supported with a single gcp log engine");
+    }
+
+    let mut service_logs = vec![];
+    for log_engine in config.log_engines {
+        service_logs.push(
+            build_service_logs(
+                log_engine,
+                custom_path_normalize_patterns.clone(),
+                config.record_dir.clone()
+            ).await?
+        );
+    }
+
+    Ok(LogsToGraph::new(service_graph, service_logs))
+}
+
+async fn build_service_logs(
+    log_engine: LogEngine,
+    custom_path_normalize_patterns: Vec<String>,
+    record_dir: Option<PathBuf>
+) -> Result<BMArc<dyn ServiceLogs>> {
+    let service_logs: BMArc<dyn ServiceLogs> = match log_engine {
+        LogEngine::Gcp {
+            project_id,
+            project_ids,
+            page_size,
+            custom_log_filter,
+            follow,
+            poll_interval_secs,
+            lookback,
+            from,
+            to,
+            checkpoint_path,
+            processing,
+        } => {
+            let follow = follow
+                .unwrap_or(false)
+                .then(|| Duration::from_secs(poll_interval_secs.unwrap_or(30)));
+
+            let lookback = lookback
+                .map(|lookback| humantime::parse_duration(&lookback))
+                .transpose()
+                .context("Invalid lookback")?;
+
+            let project_ids: Vec<String> = project_id
+                .into_iter()
+                .chain(project_ids.unwrap_or_default())
+                .collect();
+
+            let service_logs = GCPServiceLogs::new(GCPServiceLogsOptions {
+                project_ids,
+                page_size: page_size.unwrap_or(100),
+                log_filter: custom_log_filter,
+                custom_path_normalize_patterns,
+                follow,
+                lookback,
+                from,
+                to,
+                checkpoint_path,
+                record_dir,
+                processing,
+            }).await?;
+
+            bmarc!(service_logs)
+        }
+        LogEngine::GcpFiles { logs, traces, processing } => {
+            let service_logs = GCPFileServiceLogs::new(
+                logs,
+                traces.unwrap_or_default(),
+                custom_path_normalize_patterns,
+                processing
+            )?;
+            bmarc!(service_logs)
+        }
+        LogEngine::GcpReplay { dir } => {
+            let service_logs = GCPServiceLogs::from_recording(dir, custom_path_normalize_patterns)?;
+            bmarc!(service_logs)
+        }
+        LogEngine::JaegerFiles { paths } => {
+            let service_logs = JaegerFileServiceLogs::new(paths, custom_path_normalize_patterns)?;
+            bmarc!(service_logs)
+        }
+        LogEngine::ZipkinFiles { paths } => {
+            let service_logs = ZipkinFileServiceLogs::new(paths, custom_path_normalize_patterns)?;
+            bmarc!(service_logs)
+        }
+        LogEngine::AccessLogs { sources, peers, follow, poll_interval_secs } => {
+            let follow = follow
+                .unwrap_or(false)
+                .then(|| Duration::from_secs(poll_interval_secs.unwrap_or(5)));
 
+            let service_logs = AccessLogServiceLogs::new(
+                sources,
+                peers,
+                follow,
+                custom_path_normalize_patterns
+            )?;
+            bmarc!(service_logs)
+        }
+        LogEngine::Zipkin { address } => {
+            let service_logs = ZipkinCollectorServiceLogs::new(
+                address.unwrap_or(SocketAddr::from(([127, 0, 0, 1], 9411))),
+                custom_path_normalize_patterns
+            )?;
+            bmarc!(service_logs)
+        }
+        LogEngine::Otlp { http_address, grpc_address } => {
+            let service_logs = OtlpServiceLogs::new(
+                http_address.unwrap_or(SocketAddr::from(([127, 0, 0, 1], 4318))),
+                grpc_address.unwrap_or(SocketAddr::from(([127, 0, 0, 1], 4317))),
+                custom_path_normalize_patterns
+            )?;
             bmarc!(service_logs)
         }
         LogEngine::Fake => {
@@ -39,5 +175,5 @@ pub async fn build_dependencies(config: Config) -> Result<LogsToGraph> {
         }
     };
 
-    Ok(LogsToGraph::new(service_graph, service_logs))
+    Ok(service_logs)
 }
@@ -14,6 +14,41 @@ pub enum Commands {
     Run {
         #[arg(long, env = "RTC_CONFIG")]
         config: Option<std::path::PathBuf>,
+        /// Captures every GCP API response into this directory, see the gcp_replay log engine.
+        #[arg(long)]
+        record: Option<std::path::PathBuf>,
     },
     Demo,
+    /// Processes a fixed historical window of logs once, then exits.
+    Backfill {
+        #[arg(long, env = "RTC_CONFIG")]
+        config: Option<std::path::PathBuf>,
+        /// RFC 3339 start of the window, e.g. 2025-01-01T00:00:00Z
+        #[arg(long)]
+        from: String,
+        /// RFC 3339 end of the window, e.g. 2025-04-01T00:00:00Z
+        #[arg(long)]
+        to: String,
+        /// Captures every GCP API response into this directory, see the gcp_replay log engine.
+        #[arg(long)]
+        record: Option<std::path::PathBuf>,
+    },
+    /// Prints, as JSON, the graph as it stood at a point in time or across a window,
+    /// rebuilt from the history recorded by `rtc run`.
+    History {
+        #[arg(long, env = "RTC_CONFIG")]
+        config: Option<std::path::PathBuf>,
+        /// The history file, defaults to the `[history]` path of the config file.
+        #[arg(long)]
+        path: Option<std::path::PathBuf>,
+        /// RFC 3339 time of the graph, e.g. 2025-01-01T00:00:00Z, defaults to now.
+        #[arg(long, conflicts_with_all = ["from", "to"])]
+        at: Option<String>,
+        /// RFC 3339 start of the window, everything seen during it is printed.
+        #[arg(long, requires = "to")]
+        from: Option<String>,
+        /// RFC 3339 end of the window.
+        #[arg(long, requires = "from")]
+        to: Option<String>,
+    },
 }
@@ -1,8 +1,11 @@
-use std::num::{ NonZeroU8 };
+use std::{ collections::HashMap, num::NonZeroU8 };
 
+use access_logs::access_log_service_log::AccessLogSource;
+use anyhow::{ Result, bail };
+use gcp::options::GcpProcessingOptions;
 use serde::Deserialize;
 
-fn empty_string_as_none<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+fn empty_string_as_none<'de, D>(deserializer: D) -> std::result::Result<Option<String>, D::Error>
     where D: serde::Deserializer<'de>
 {
     let opt = Option::<String>::deserialize(deserializer)?;
@@ -21,40 +24,164 @@ pub struct HttpConfig {
 
 #[derive(Debug, Deserialize)]
 pub enum LogEngine {
-    #[serde(rename = "gcp")] GCP {
-        project_id: String,
+    #[serde(rename = "gcp")] Gcp {
+        project_id: Option<String>,
+        /// Several projects mapped together, in addition to `project_id`.
+        project_ids: Option<Vec<String>>,
         page_size: Option<i32>,
-        #[serde(deserialize_with = "empty_string_as_none")]
+        #[serde(default, deserialize_with = "empty_string_as_none")]
         custom_log_filter: Option<String>,
+        /// Keep polling for new log entries once the last page has been reached.
+        follow: Option<bool>,
+        /// Seconds to wait between two polls when following.
+        poll_interval_secs: Option<u64>,
+        /// How far back to read when `from` is not set, e.g. "7d" or "12h".
+        lookback: Option<String>,
+        /// RFC 3339 start of the window to read.
+        from: Option<String>,
+        /// RFC 3339 end of the window to read.
+        to: Option<String>,
+        /// File in which progress is persisted, so that a restart resumes where it stopped.
+        checkpoint_path: Option<std::path::PathBuf>,
+        /// Backend services, GraphQL fields, caller rules, frontend hosts and request series.
+        #[serde(flatten)]
+        processing: GcpProcessingOptions,
+    },
+    /// Replays exported GCP log entries and traces, with no network access.
+    #[serde(rename = "gcp_files")] GcpFiles {
+        /// Files or directories of LogEntry JSON (arrays or JSON lines), "-" reads stdin.
+        logs: Vec<std::path::PathBuf>,
+        /// Files or directories of Cloud Trace JSON.
+        traces: Option<Vec<std::path::PathBuf>>,
+        /// Backend services, GraphQL fields, caller rules, frontend hosts and request series.
+        #[serde(flatten)]
+        processing: GcpProcessingOptions,
+    },
+    /// Replays a directory captured with `rtc run --record`.
+    #[serde(rename = "gcp_replay")] GcpReplay {
+        dir: std::path::PathBuf,
+    },
+    /// Rebuilds the graph from Jaeger JSON exports.
+    #[serde(rename = "jaeger_files")] JaegerFiles {
+        /// Files or directories of Jaeger JSON (`{"data": [...]}`), "-" reads stdin.
+        paths: Vec<std::path::PathBuf>,
+    },
+    /// Rebuilds the graph from Zipkin v2 JSON spans.
+    #[serde(rename = "zipkin_files")] ZipkinFiles {
+        /// Files or directories of Zipkin v2 span arrays, "-" reads stdin.
+        paths: Vec<std::path::PathBuf>,
+    },
+    /// Tails nginx, Apache and Envoy/Istio access logs.
+    #[serde(rename = "access_logs")] AccessLogs {
+        sources: Vec<AccessLogSource>,
+        /// Client addresses, hosts or Envoy clusters to service names.
+        #[serde(default)]
+        peers: HashMap<String, String>,
+        /// Keep reading lines appended to the files.
+        follow: Option<bool>,
+        /// Seconds to wait between two reads when following.
+        poll_interval_secs: Option<u64>,
+    },
+    /// Exposes a Zipkin v2 collector, `POST /api/v2/spans`.
+    #[serde(rename = "zipkin")] Zipkin {
+        /// Defaults to 127.0.0.1:9411.
+        address: Option<std::net::SocketAddr>,
+    },
+    /// Receives OpenTelemetry spans pushed over OTLP/HTTP and OTLP/gRPC.
+    #[serde(rename = "otlp")] Otlp {
+        /// Defaults to 127.0.0.1:4318.
+        http_address: Option<std::net::SocketAddr>,
+        /// Defaults to 127.0.0.1:4317.
+        grpc_address: Option<std::net::SocketAddr>,
     },
     Fake,
 }
 
+impl LogEngine {
+    /// Restricts the log engine to a fixed historical window, that is processed once.
+    pub fn into_backfill(self, window_from: String, window_to: String) -> Result<Self> {
+        match self {
+            LogEngine::Gcp {
+                project_id,
+                project_ids,
+                page_size,
+                custom_log_filter,
+                poll_interval_secs,
+                lookback,
+                processing,
+                ..
+            } =>
+                Ok(LogEngine::Gcp {
+                    project_id,
+                    project_ids,
+                    page_size,
+                    custom_log_filter,
+                    follow: Some(false),
+                    poll_interval_secs,
+                    lookback,
+                    from: Some(window_from),
+                    to: Some(window_to),
+                    // A backfill must not move the checkpoint of the daemon.
+                    checkpoint_path: None,
+                    processing,
+                }),
+            LogEngine::GcpFiles { .. } => bail!("The gcp_files log engine cannot be backfilled"),
+            LogEngine::GcpReplay { .. } => bail!("The gcp_replay log engine cannot be backfilled"),
+            LogEngine::JaegerFiles { .. } =>
+                bail!("The jaeger_files log engine cannot be backfilled"),
+            LogEngine::ZipkinFiles { .. } =>
+                bail!("The zipkin_files log engine cannot be backfilled"),
+            LogEngine::AccessLogs { .. } =>
+                bail!("The access_logs log engine cannot be backfilled"),
+            LogEngine::Zipkin { .. } => bail!("The zipkin log engine cannot be backfilled"),
+            LogEngine::Otlp { .. } => bail!("The otlp log engine cannot be backfilled"),
+            LogEngine::Fake => bail!("The fake log engine cannot be backfilled"),
+        }
+    }
+}
+
 #[derive(Debug, Deserialize)]
 pub enum GraphEngine {
     #[serde(rename = "falkor")] Falkor {
         database_url: String,
         max_pool: Option<NonZeroU8>,
         graph_name: String,
+        /// Edges and nodes not seen for this long are stale, e.g. "30d".
+        stale_after: Option<String>,
+        /// Deletes stale edges and nodes rather than flagging them with `stale = true`.
+        prune_stale: Option<bool>,
     },
 }
 
+/// Where the additions and removals of services, operations and edges are recorded.
+#[derive(Debug, Deserialize)]
+pub struct HistoryConfig {
+    pub path: std::path::PathBuf,
+    /// Elements not seen for this long are recorded as removed, e.g. "30d".
+    pub stale_after: Option<String>,
+}
+
 pub struct Config {
     pub graph_engine: GraphEngine,
-    pub log_engine: LogEngine,
+    pub log_engines: Vec<LogEngine>,
     pub http_config: Option<HttpConfig>,
+    /// Directory in which the GCP API responses are recorded.
+    pub record_dir: Option<std::path::PathBuf>,
+    pub history: Option<HistoryConfig>,
 }
 
 impl Config {
     pub fn new(
         graph_engine: GraphEngine,
-        log_engine: LogEngine,
+        log_engines: Vec<LogEngine>,
         http_config: Option<HttpConfig>
     ) -> Self {
         Self {
             graph_engine,
-            log_engine,
+            log_engines,
             http_config,
+            record_dir: None,
+            history: None,
         }
     }
 }
@@ -3,19 +3,14 @@ use std::collections::{ HashMap, HashSet };
 use async_trait::async_trait;
 use logs_to_graph::{
     service_logs::ServiceLogs,
-    service_node_graph::{ Operation, ServiceNode, ServiceNodeGraph },
+    service_node_graph::{ Operation, ServiceKind, ServiceNode, ServiceNodeGraph },
 };
 use anyhow::{ Result, Ok };
 use tokio::sync::mpsc::Sender;
 
+#[derive(Default)]
 pub struct FakeServiceLog {}
 
-impl Default for FakeServiceLog {
-    fn default() -> Self {
-        Self {}
-    }
-}
-
 #[async_trait]
 impl ServiceLogs for FakeServiceLog {
     async fn run(&self, sender: Sender<ServiceNodeGraph>) -> Result<()> {
@@ -25,6 +20,15 @@ impl ServiceLogs for FakeServiceLog {
                     "web-app".into(),
                     ServiceNode {
                         name: "web-app".into(),
+                        kind: ServiceKind::Entrypoint,
+                        consumes: HashMap::new(),
+                        metrics: HashMap::new(),
+                        invoke_metrics: HashMap::new(),
+                        seen: None,
+                        operations_seen: HashMap::new(),
+                        invokes_seen: HashMap::new(),
+                        consumes_seen: HashMap::new(),
+                        invokes_skipped_hops: HashMap::new(),
                         operations: HashMap::from_iter([]),
                         invokes: HashMap::from_iter([
                             (
@@ -38,6 +42,15 @@ impl ServiceLogs for FakeServiceLog {
                     "graphql-service".into(),
                     ServiceNode {
                         name: "graphql-service".into(),
+                        kind: ServiceKind::Service,
+                        consumes: HashMap::new(),
+                        metrics: HashMap::new(),
+                        invoke_metrics: HashMap::new(),
+                        seen: None,
+                        operations_seen: HashMap::new(),
+                        invokes_seen: HashMap::new(),
+                        consumes_seen: HashMap::new(),
+                        invokes_skipped_hops: HashMap::new(),
                         operations: HashMap::from_iter([
                             (
                                 "graphql-service_http_post_/".into(),
@@ -75,6 +88,15 @@ impl ServiceLogs for FakeServiceLog {
                     "users-service".into(),
                     ServiceNode {
                         name: "users-service".into(),
+                        kind: ServiceKind::Service,
+                        consumes: HashMap::new(),
+                        metrics: HashMap::new(),
+                        invoke_metrics: HashMap::new(),
+                        seen: None,
+                        operations_seen: HashMap::new(),
+                        invokes_seen: HashMap::new(),
+                        consumes_seen: HashMap::new(),
+                        invokes_skipped_hops: HashMap::new(),
                         operations: HashMap::from_iter([
                             (
                                 "users-service_http_post_/users".into(),
@@ -95,6 +117,15 @@ impl ServiceLogs for FakeServiceLog {
                     "books-service".into(),
                     ServiceNode {
                         name: "books-service".into(),
+                        kind: ServiceKind::Service,
+                        consumes: HashMap::new(),
+                        metrics: HashMap::new(),
+                        invoke_metrics: HashMap::new(),
+                        seen: None,
+                        operations_seen: HashMap::new(),
+                        invokes_seen: HashMap::new(),
+                        consumes_seen: HashMap::new(),
+                        invokes_skipped_hops: HashMap::new(),
                         operations: HashMap::from_iter([
                             (
                                 "books-service_http_post_/books".into(),
@@ -129,6 +160,15 @@ impl ServiceLogs for FakeServiceLog {
                     "auth-service".into(),
                     ServiceNode {
                         name: "auth-service".into(),
+                        kind: ServiceKind::Service,
+                        consumes: HashMap::new(),
+                        metrics: HashMap::new(),
+                        invoke_metrics: HashMap::new(),
+                        seen: None,
+                        operations_seen: HashMap::new(),
+                        invokes_seen: HashMap::new(),
+                        consumes_seen: HashMap::new(),
+                        invokes_skipped_hops: HashMap::new(),
                         operations: HashMap::from_iter([
                             (
                                 "auth-service_http_post_/login".into(),
@@ -150,6 +190,7 @@ impl ServiceLogs for FakeServiceLog {
                     },
                 ),
             ]),
+            observed_at: None,
         }).await?;
         Ok(())
     }
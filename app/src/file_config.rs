@@ -3,14 +3,18 @@ use std::fs;
 use anyhow::{ Context, Result };
 use serde::Deserialize;
 
-use crate::config::{ GraphEngine, HttpConfig, LogEngine };
+use crate::config::{ GraphEngine, HistoryConfig, HttpConfig, LogEngine };
 
 #[derive(Debug, Deserialize, Default)]
 pub struct FileConfig {
+    #[allow(dead_code)]
     pub schema_version: Option<u32>,
     pub log_engine: Option<LogEngine>,
+    /// Several log engines whose graphs are merged, used alongside or instead of `log_engine`.
+    pub log_engines: Option<Vec<LogEngine>>,
     pub graph_engine: Option<GraphEngine>,
     pub http: Option<HttpConfig>,
+    pub history: Option<HistoryConfig>,
 }
 
 pub fn load_file_config(path: std::path::PathBuf) -> Result<FileConfig> {
@@ -1,8 +1,10 @@
-use std::path::PathBuf;
+use std::{ path::PathBuf, time::{ SystemTime, UNIX_EPOCH } };
 
-use anyhow::{ Ok, Result, bail };
+use anyhow::{ Context, Ok, Result, bail };
 use clap::Parser;
 use common::tracing::init_tracing;
+use logs_to_graph::history::{ graph_as_of, graph_between, read_history };
+use tracing::warn;
 
 use crate::bootstrap::build_dependencies;
 use crate::cli::{ Cli, Commands };
@@ -15,6 +17,70 @@ mod config;
 mod fake_service_log;
 mod file_config;
 
+fn load_config(config: Option<PathBuf>) -> Result<Config> {
+    // TODO: make this OS agnostic
+    let path: PathBuf = config.unwrap_or("./rtc.toml".into());
+    let cfg = load_file_config(path)?;
+
+    if cfg.graph_engine.is_none() {
+        bail!("A graph engine must be specified in the config file");
+    }
+
+    let log_engines: Vec<LogEngine> = cfg.log_engine
+        .into_iter()
+        .chain(cfg.log_engines.unwrap_or_default())
+        .collect();
+
+    if log_engines.is_empty() {
+        bail!("A log engine must be specified in the config file");
+    }
+
+    let mut config = Config::new(cfg.graph_engine.unwrap(), log_engines, cfg.http);
+    config.history = cfg.history;
+    Ok(config)
+}
+
+/// Unix time in seconds of an RFC 3339 date.
+fn parse_unix_time(name: &str, value: &str) -> Result<i64> {
+    let time = humantime
+        ::parse_rfc3339_weak(value)
+        .with_context(|| format!("Invalid {}: {}", name, value))?;
+    Ok(time.duration_since(UNIX_EPOCH)?.as_secs() as i64)
+}
+
+/// The history file given on the command line, or else the one the config file records into.
+fn get_history_path(config: Option<PathBuf>, path: Option<PathBuf>) -> Result<PathBuf> {
+    if let Some(path) = path {
+        return Ok(path);
+    }
+
+    // TODO: make this OS agnostic
+    let cfg = load_file_config(config.unwrap_or("./rtc.toml".into()))?;
+    match cfg.history {
+        Some(history) => Ok(history.path),
+        None => bail!("No history file, pass --path or configure [history] in the config file"),
+    }
+}
+
+fn print_history(
+    path: PathBuf,
+    at: Option<String>,
+    from: Option<String>,
+    to: Option<String>
+) -> Result<()> {
+    let events = read_history(&path)?;
+
+    let graph = match (at, from, to) {
+        (_, Some(from), Some(to)) =>
+            graph_between(&events, parse_unix_time("from", &from)?, parse_unix_time("to", &to)?),
+        (Some(at), _, _) => graph_as_of(&events, parse_unix_time("at", &at)?),
+        _ => graph_as_of(&events, SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64),
+    };
+
+    println!("{}", serde_json::to_string_pretty(&graph)?);
+    Ok(())
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     init_tracing();
@@ -22,24 +88,35 @@ async fn main() -> Result<()> {
     let cli = Cli::parse();
 
     let config = match cli.command {
-        Commands::Run { config } => {
-            let path: PathBuf = if config.is_some() {
-                config.unwrap()
-            } else {
-                // TODO: make this OS agnostic
-                "./rtc.toml".into()
-            };
-            let cfg = load_file_config(path)?;
-
-            if cfg.graph_engine.is_none() {
-                bail!("A graph engine must be specified in the config file");
-            }
+        Commands::Run { config, record } => {
+            let mut config = load_config(config)?;
+            config.record_dir = record;
+            config
+        }
+        Commands::Backfill { config, from, to, record } => {
+            let mut config = load_config(config)?;
+            // Only the GCP engines can read a past window, the others are left out.
+            config.log_engines = config.log_engines
+                .into_iter()
+                .filter_map(|log_engine| {
+                    match log_engine.into_backfill(from.clone(), to.clone()) {
+                        Result::Ok(log_engine) => Some(log_engine),
+                        Err(error) => {
+                            warn!("Skipping log engine during the backfill: {}", error);
+                            None
+                        }
+                    }
+                })
+                .collect();
 
-            if cfg.log_engine.is_none() {
-                bail!("A log engine must be specified in the config file");
+            if config.log_engines.is_empty() {
+                bail!("None of the configured log engines can be backfilled");
             }
-
-            Config::new(cfg.graph_engine.unwrap(), cfg.log_engine.unwrap(), cfg.http)
+            config.record_dir = record;
+            config
+        }
+        Commands::History { config, path, at, from, to } => {
+            return print_history(get_history_path(config, path)?, at, from, to);
         }
         Commands::Demo => {
             // TODO: make this OS agnostic
@@ -49,7 +126,7 @@ async fn main() -> Result<()> {
                 bail!("A graph engine should be specified in the rtc.demo.toml");
             }
 
-            Config::new(cfg.graph_engine.unwrap(), LogEngine::Fake, None)
+            Config::new(cfg.graph_engine.unwrap(), vec![LogEngine::Fake], None)
         }
     };
 
@@ -0,0 +1,278 @@
+use std::{ collections::HashMap, path::PathBuf, time::Duration };
+
+use anyhow::{ Ok, Result };
+use async_trait::async_trait;
+use logs_to_graph::{
+    grpc::grpc_operation,
+    normalize::{ build_path_normalize_regexes, normalize_request_path },
+    service_logs::ServiceLogs,
+    service_node_graph::{ Operation, ServiceName, ServiceNodeGraph },
+};
+use regex::Regex;
+use serde::Deserialize;
+use tokio::sync::mpsc::Sender;
+use tracing::{ debug, error, info, warn };
+
+use crate::{ parser::{ AccessLogFormat, AccessLogRecord, parse_line }, tail::TailedFile };
+
+/// Istio prefixes its clusters with the traffic direction, e.g. `outbound|8080||users.default.svc.cluster.local`.
+const ISTIO_INBOUND: &str = "inbound";
+const ISTIO_OUTBOUND: &str = "outbound";
+/// Istio clusters that do not designate a service.
+const ISTIO_PASSTHROUGH_CLUSTER: &str = "PassthroughCluster";
+const ISTIO_BLACKHOLE_CLUSTER: &str = "BlackHoleCluster";
+
+/// An access log file and the service that writes it.
+#[derive(Debug, Clone, Deserialize)]
+pub struct AccessLogSource {
+    pub path: PathBuf,
+    pub service: ServiceName,
+    pub format: AccessLogFormat,
+}
+
+/// Maps access logs of nginx, Apache and Envoy/Istio to services.
+///
+/// Each line adds an operation to the service that handled it. Envoy lines routed
+/// to an upstream cluster or a known upstream host add an edge to that upstream
+/// instead, and callers are resolved from the client address through the `peers` mapping.
+pub struct AccessLogServiceLogs {
+    sources: Vec<AccessLogSource>,
+    /// Client addresses, hosts or cluster names to service names.
+    peers: HashMap<String, ServiceName>,
+    /// Interval at which the files are checked for new lines, `None` reads them once.
+    follow: Option<Duration>,
+    path_normalize_regexes: Vec<(String, Vec<Regex>)>,
+}
+
+impl AccessLogServiceLogs {
+    pub fn new(
+        sources: Vec<AccessLogSource>,
+        peers: HashMap<String, ServiceName>,
+        follow: Option<Duration>,
+        custom_path_normalize_patterns: Vec<String>
+    ) -> Result<Self> {
+        let path_normalize_regexes = build_path_normalize_regexes(
+            &custom_path_normalize_patterns
+        )?;
+
+        Ok(Self {
+            sources,
+            peers,
+            follow,
+            path_normalize_regexes,
+        })
+    }
+
+    /// Looks a peer up by its exact value, then without its port.
+    fn resolve_peer(&self, peer: &str) -> Option<ServiceName> {
+        self.peers
+            .get(peer)
+            .or_else(|| {
+                peer.rsplit_once(':').and_then(|(host, _)| self.peers.get(host))
+            })
+            .cloned()
+    }
+
+    /// Resolves the service an Envoy cluster routes to, `None` for inbound traffic.
+    fn resolve_cluster(&self, record: &AccessLogRecord, cluster: &str) -> Option<ServiceName> {
+        if let Some(service) = self.resolve_peer(cluster) {
+            return Some(service);
+        }
+
+        match cluster {
+            ISTIO_BLACKHOLE_CLUSTER => None,
+            ISTIO_PASSTHROUGH_CLUSTER => {
+                let authority = record.authority.as_deref()?;
+                self.resolve_peer(authority).or(
+                    Some(authority.split(':').next().unwrap_or(authority).to_string())
+                )
+            }
+            _ => {
+                let mut parts = cluster.split('|');
+                match parts.next() {
+                    Some(ISTIO_INBOUND) => None,
+                    Some(ISTIO_OUTBOUND) => {
+                        // The first label of `users.default.svc.cluster.local`.
+                        let host = parts.next_back()?;
+                        host.split('.')
+                            .next()
+                            .filter(|name| !name.is_empty())
+                            .map(String::from)
+                    }
+                    _ => Some(cluster.to_string()),
+                }
+            }
+        }
+    }
+
+    fn process_record(
+        &self,
+        service_node_graph: &mut ServiceNodeGraph,
+        service: &ServiceName,
+        record: &AccessLogRecord
+    ) {
+        let operation = grpc_operation(&record.method, &record.path, None).unwrap_or_else(||
+            Operation::Http {
+                method: record.method.clone(),
+                path: normalize_request_path(&record.path, &self.path_normalize_regexes),
+            }
+        );
+
+        service_node_graph.set_observed_at(record.timestamp);
+        service_node_graph.add_service(service.clone());
+
+        // Without a cluster, e.g. in Envoy's default format, the upstream host tells
+        // where the request was routed to.
+        let upstream = (match record.upstream_cluster.as_deref() {
+            Some(cluster) => self.resolve_cluster(record, cluster),
+            None => record.upstream_host.as_deref().and_then(|host| self.resolve_peer(host)),
+        }).filter(|upstream| upstream != service);
+
+        if let Some(upstream) = upstream {
+            service_node_graph.add_service(upstream.clone());
+            service_node_graph.add_operation_to_service(upstream.clone(), operation.clone());
+            service_node_graph.add_target_to_service(service.clone(), upstream, operation);
+            return;
+        }
+
+        service_node_graph.add_operation_to_service(service.clone(), operation.clone());
+
+        let caller = record
+            .client()
+            .and_then(|client| self.resolve_peer(client))
+            .filter(|caller| caller != service);
+
+        if let Some(caller) = caller {
+            service_node_graph.add_service(caller.clone());
+            service_node_graph.add_target_to_service(caller, service.clone(), operation);
+        }
+    }
+}
+
+#[async_trait]
+impl ServiceLogs for AccessLogServiceLogs {
+    async fn run(&self, sender: Sender<ServiceNodeGraph>) -> Result<()> {
+        let mut service_node_graph = ServiceNodeGraph::default();
+        let mut tailed_files = self.sources
+            .iter()
+            .map(|source| (source, TailedFile::new(source.path.clone())))
+            .collect::<Vec<_>>();
+
+        info!("Reading {} access logs", tailed_files.len());
+
+        loop {
+            let mut processed_lines = 0;
+
+            for (source, tailed_file) in tailed_files.iter_mut() {
+                let lines = match tailed_file.read_new_lines() {
+                    Result::Ok(lines) => lines,
+                    // A rotated or briefly missing file is picked up again on the next tick.
+                    Err(error) if self.follow.is_some() => {
+                        warn!("Failed reading {}, retrying: {:?}", tailed_file.path.display(), error);
+                        continue;
+                    }
+                    Err(error) => {
+                        return Err(error);
+                    }
+                };
+
+                for line in lines {
+                    match parse_line(source.format, &line) {
+                        Some(record) => {
+                            self.process_record(&mut service_node_graph, &source.service, &record);
+                            processed_lines += 1;
+                        }
+                        None => warn!("Skipping unparsable line in {}", tailed_file.path.display()),
+                    }
+                }
+            }
+
+            debug!("Processed {} access log lines", processed_lines);
+
+            if processed_lines > 0 && sender.send(service_node_graph.clone()).await.is_err() {
+                error!("Failed to send service node graph from the Service Logs to the Service Graph.");
+            }
+
+            let Some(follow) = self.follow else {
+                break;
+            };
+            tokio::time::sleep(follow).await;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{ collections::HashMap, fs };
+
+    use logs_to_graph::{ service_logs::ServiceLogs, service_node_graph::{ Operation, SeenRange } };
+    use tokio::sync::mpsc::channel;
+
+    use crate::{
+        access_log_service_log::{ AccessLogServiceLogs, AccessLogSource },
+        parser::AccessLogFormat,
+    };
+
+    const NGINX_LOG: &str =
+        r#"10.0.0.3 - - [10/Oct/2025:13:55:36 +0000] "GET /users/42 HTTP/1.1" 200 52 "-" "okhttp" "-"
+10.0.0.3 - - [10/Oct/2025:13:55:37 +0000] "GET /users/43 HTTP/1.1" 200 52 "-" "okhttp" "-"
+"#;
+
+    const ENVOY_LOG: &str =
+        r#"[2025-10-10T13:55:36.000Z] "POST /books HTTP/1.1" 201 - 0 120 3 2 "-" "okhttp" "b3a1" "books" "10.0.1.4:8080" outbound|8080||books.default.svc.cluster.local
+[2025-10-10T13:55:37.000Z] "GET /authors/7 HTTP/1.1" 200 - 0 80 2 1 "-" "okhttp" "b3a2" "authors" "10.0.1.8:8080"
+"#;
+
+    #[tokio::test]
+    async fn should_map_access_logs_to_services() {
+        let dir = std::env::temp_dir().join(format!("rtc-access-logs-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("users.log"), NGINX_LOG).unwrap();
+        fs::write(dir.join("graphql-envoy.log"), ENVOY_LOG).unwrap();
+
+        let service_logs = AccessLogServiceLogs::new(
+            vec![
+                AccessLogSource {
+                    path: dir.join("users.log"),
+                    service: "users-service".into(),
+                    format: AccessLogFormat::Combined,
+                },
+                AccessLogSource {
+                    path: dir.join("graphql-envoy.log"),
+                    service: "graphql-service".into(),
+                    format: AccessLogFormat::Envoy,
+                }
+            ],
+            HashMap::from([
+                ("10.0.0.3".to_string(), "graphql-service".to_string()),
+                ("10.0.1.8".to_string(), "authors-service".to_string()),
+            ]),
+            None,
+            vec![]
+        ).unwrap();
+
+        let (sender, mut receiver) = channel(32);
+        service_logs.run(sender).await.unwrap();
+        fs::remove_dir_all(dir).unwrap();
+
+        let graph = receiver.recv().await.unwrap();
+        assert_eq!(
+            graph.services["users-service"].operations.values().collect::<Vec<_>>(),
+            vec![&(Operation::Http { method: "GET".into(), path: "/users/{users_id}".into() })]
+        );
+        assert_eq!(graph.services["graphql-service"].invokes["users-service"].len(), 1);
+        assert_eq!(graph.services["graphql-service"].invokes["books"].len(), 1);
+        assert_eq!(graph.services["books"].operations.len(), 1);
+        assert_eq!(graph.services["graphql-service"].invokes["authors-service"].len(), 1);
+        assert_eq!(
+            graph.services["authors-service"].operations.values().collect::<Vec<_>>(),
+            vec![&(Operation::Http { method: "GET".into(), path: "/authors/{authors_id}".into() })]
+        );
+        assert_eq!(
+            graph.services["users-service"].seen,
+            Some(SeenRange { first_seen: 1760104536, last_seen: 1760104537 })
+        );
+    }
+}
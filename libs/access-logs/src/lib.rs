@@ -0,0 +1,3 @@
+pub mod access_log_service_log;
+pub mod parser;
+mod tail;
@@ -0,0 +1,224 @@
+use std::sync::LazyLock;
+
+use chrono::DateTime;
+use regex::Regex;
+use serde::Deserialize;
+
+/// `$remote_addr - $remote_user [$time_local] "$request" $status $body_bytes_sent`, optionally
+/// followed by `"$http_referer" "$http_user_agent"` and nginx's `"$http_x_forwarded_for"`.
+static COMBINED_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r#"^(\S+) \S+ \S+ \[([^\]]*)\] "(\S+) (\S+)[^"]*" \d{3} \S+(?: "[^"]*" "[^"]*")?(?: "([^"]*)")?"#
+    ).unwrap()
+});
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AccessLogFormat {
+    /// Apache and nginx combined log format, nginx's default `main` format included.
+    Combined,
+    /// Envoy and Istio access logs, either the default text format or JSON.
+    Envoy,
+}
+
+/// The fields of an access log line needed to map services.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AccessLogRecord {
+    pub method: String,
+    pub path: String,
+    pub remote_addr: Option<String>,
+    pub forwarded_for: Option<String>,
+    pub authority: Option<String>,
+    pub upstream_host: Option<String>,
+    pub upstream_cluster: Option<String>,
+    /// Unix time in seconds at which the request was received.
+    pub timestamp: Option<i64>,
+}
+
+impl AccessLogRecord {
+    /// The original client of the request, `X-Forwarded-For` first.
+    pub fn client(&self) -> Option<&str> {
+        self.forwarded_for
+            .as_deref()
+            .and_then(|forwarded_for| forwarded_for.split(',').next())
+            .map(str::trim)
+            .or(self.remote_addr.as_deref())
+    }
+}
+
+fn present(value: &str) -> Option<String> {
+    (!value.is_empty() && value != "-").then(|| value.to_string())
+}
+
+/// Parses nginx and Apache's `10/Oct/2025:13:55:36 +0000` as well as Envoy's RFC 3339 times.
+fn parse_timestamp(value: &str) -> Option<i64> {
+    DateTime::parse_from_str(value, "%d/%b/%Y:%H:%M:%S %z")
+        .or_else(|_| DateTime::parse_from_rfc3339(value))
+        .ok()
+        .map(|timestamp| timestamp.timestamp())
+}
+
+pub fn parse_line(format: AccessLogFormat, line: &str) -> Option<AccessLogRecord> {
+    match format {
+        AccessLogFormat::Combined => parse_combined(line),
+        AccessLogFormat::Envoy => parse_envoy(line),
+    }
+}
+
+fn parse_combined(line: &str) -> Option<AccessLogRecord> {
+    let captures = COMBINED_REGEX.captures(line)?;
+
+    Some(AccessLogRecord {
+        method: captures[3].to_string(),
+        path: captures[4].to_string(),
+        remote_addr: present(&captures[1]),
+        forwarded_for: captures.get(5).and_then(|value| present(value.as_str())),
+        timestamp: parse_timestamp(&captures[2]),
+        ..Default::default()
+    })
+}
+
+#[derive(Debug, Deserialize)]
+struct EnvoyJsonLine {
+    method: Option<String>,
+    path: Option<String>,
+    authority: Option<String>,
+    x_forwarded_for: Option<String>,
+    downstream_remote_address: Option<String>,
+    upstream_host: Option<String>,
+    upstream_cluster: Option<String>,
+    start_time: Option<String>,
+}
+
+/// Splits a line into `"quoted"`, `[bracketed]` and bare tokens.
+fn tokenize(line: &str) -> Vec<(bool, &str)> {
+    let mut tokens = vec![];
+    let mut rest = line.trim();
+
+    while !rest.is_empty() {
+        let (quoted, token, remaining) = if let Some(stripped) = rest.strip_prefix('"') {
+            let end = stripped.find('"').unwrap_or(stripped.len());
+            (true, &stripped[..end], stripped.get(end + 1..).unwrap_or(""))
+        } else if let Some(stripped) = rest.strip_prefix('[') {
+            let end = stripped.find(']').unwrap_or(stripped.len());
+            (false, &stripped[..end], stripped.get(end + 1..).unwrap_or(""))
+        } else {
+            let end = rest.find(' ').unwrap_or(rest.len());
+            (false, &rest[..end], &rest[end..])
+        };
+
+        tokens.push((quoted, token));
+        rest = remaining.trim_start();
+    }
+
+    tokens
+}
+
+fn parse_envoy(line: &str) -> Option<AccessLogRecord> {
+    if line.trim_start().starts_with('{') {
+        let json: EnvoyJsonLine = serde_json::from_str(line).ok()?;
+        return Some(AccessLogRecord {
+            method: json.method?,
+            path: json.path?,
+            remote_addr: json.downstream_remote_address.as_deref().and_then(present),
+            forwarded_for: json.x_forwarded_for.as_deref().and_then(present),
+            authority: json.authority.as_deref().and_then(present),
+            upstream_host: json.upstream_host.as_deref().and_then(present),
+            upstream_cluster: json.upstream_cluster.as_deref().and_then(present),
+            timestamp: json.start_time.as_deref().and_then(parse_timestamp),
+        });
+    }
+
+    let tokens = tokenize(line);
+    let timestamp = tokens
+        .first()
+        .filter(|(quoted, _)| !*quoted)
+        .and_then(|(_, start_time)| parse_timestamp(start_time));
+
+    // `[%START_TIME%] "%REQ(:METHOD)% %REQ(X-ENVOY-ORIGINAL-PATH?:PATH)% %PROTOCOL%"`
+    let (_, request) = tokens.get(1).filter(|(quoted, _)| *quoted)?;
+    let mut request = request.split(' ');
+    let method = request.next()?.to_string();
+    let path = request.next()?.to_string();
+
+    // Both the Envoy and the Istio formats end their quoted fields with
+    // `"%REQ(X-FORWARDED-FOR)%" "%REQ(USER-AGENT)%" "%REQ(X-REQUEST-ID)%" "%REQ(:AUTHORITY)%" "%UPSTREAM_HOST%"`,
+    // Istio appends `%UPSTREAM_CLUSTER%` and other bare fields after them.
+    let last_quoted = tokens.iter().rposition(|(quoted, _)| *quoted)?;
+    let quoted_field = |offset: usize| {
+        last_quoted
+            .checked_sub(offset)
+            .filter(|index| *index > 1)
+            .and_then(|index| present(tokens[index].1))
+    };
+
+    Some(AccessLogRecord {
+        method,
+        path,
+        remote_addr: None,
+        forwarded_for: quoted_field(4),
+        authority: quoted_field(1),
+        upstream_host: quoted_field(0),
+        upstream_cluster: tokens.get(last_quoted + 1).and_then(|(_, token)| present(token)),
+        timestamp,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use crate::parser::{ AccessLogFormat, AccessLogRecord, parse_line };
+
+    #[test]
+    fn should_parse_the_nginx_main_format() {
+        let line =
+            r#"10.0.0.7 - - [10/Oct/2025:13:55:36 +0000] "GET /users/42?full=true HTTP/1.1" 200 2326 "-" "curl/8.0" "203.0.113.9, 10.0.0.2""#;
+
+        let record = parse_line(AccessLogFormat::Combined, line).unwrap();
+        assert_eq!(record.method, "GET");
+        assert_eq!(record.path, "/users/42?full=true");
+        assert_eq!(record.client(), Some("203.0.113.9"));
+        assert_eq!(record.timestamp, Some(1760104536));
+    }
+
+    #[test]
+    fn should_parse_the_apache_common_format() {
+        let line = r#"10.0.0.7 - frank [10/Oct/2000:13:55:36 -0700] "POST /books HTTP/1.0" 201 -"#;
+
+        let record = parse_line(AccessLogFormat::Combined, line).unwrap();
+        assert_eq!(record.method, "POST");
+        assert_eq!(record.client(), Some("10.0.0.7"));
+        assert_eq!(record.timestamp, Some(971211336));
+    }
+
+    #[test]
+    fn should_parse_the_istio_text_format() {
+        let line =
+            r#"[2025-10-10T13:55:36.000Z] "GET /users/42 HTTP/1.1" 200 - via_upstream - "-" 0 120 3 2 "-" "okhttp" "b3a1" "users:8080" "10.0.1.4:8080" outbound|8080||users.default.svc.cluster.local 10.0.0.5:43210 10.96.0.10:8080 10.0.0.5:52110 - default"#;
+
+        let record = parse_line(AccessLogFormat::Envoy, line).unwrap();
+        assert_eq!(
+            record,
+            AccessLogRecord {
+                method: "GET".into(),
+                path: "/users/42".into(),
+                remote_addr: None,
+                forwarded_for: None,
+                authority: Some("users:8080".into()),
+                upstream_host: Some("10.0.1.4:8080".into()),
+                upstream_cluster: Some("outbound|8080||users.default.svc.cluster.local".into()),
+                timestamp: Some(1760104536),
+            }
+        );
+    }
+
+    #[test]
+    fn should_parse_the_envoy_json_format() {
+        let line =
+            r#"{"method":"POST","path":"/books","authority":"books","upstream_cluster":"books_service","x_forwarded_for":"10.0.0.3","upstream_host":"10.0.1.9:80","start_time":"2025-10-10T13:55:36.000Z"}"#;
+
+        let record = parse_line(AccessLogFormat::Envoy, line).unwrap();
+        assert_eq!(record.upstream_cluster, Some("books_service".into()));
+        assert_eq!(record.client(), Some("10.0.0.3"));
+        assert_eq!(record.timestamp, Some(1760104536));
+    }
+}
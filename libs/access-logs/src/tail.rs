@@ -0,0 +1,85 @@
+use std::{ fs::File, io::{ Read, Seek, SeekFrom }, path::PathBuf };
+
+use anyhow::{ Context, Ok, Result };
+
+/// Reads the lines appended to a file since the previous read.
+pub struct TailedFile {
+    pub path: PathBuf,
+    offset: u64,
+    /// A line that was still being written during the previous read, kept as bytes
+    /// since the read may have stopped in the middle of a UTF-8 sequence.
+    partial_line: Vec<u8>,
+}
+
+impl TailedFile {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path, offset: 0, partial_line: Vec::new() }
+    }
+
+    pub fn read_new_lines(&mut self) -> Result<Vec<String>> {
+        let mut file = File::open(&self.path).with_context(||
+            format!("Failed opening access log: {}", self.path.display())
+        )?;
+
+        // The file was truncated or rotated in place, start over.
+        if file.metadata()?.len() < self.offset {
+            self.offset = 0;
+            self.partial_line.clear();
+        }
+
+        file.seek(SeekFrom::Start(self.offset))?;
+        let read = file.read_to_end(&mut self.partial_line)?;
+        self.offset += read as u64;
+
+        let Some(last_newline) = self.partial_line.iter().rposition(|byte| *byte == b'\n') else {
+            return Ok(vec![]);
+        };
+
+        let remaining = self.partial_line.split_off(last_newline + 1);
+        let complete_lines = std::mem::replace(&mut self.partial_line, remaining);
+        let lines = String::from_utf8_lossy(&complete_lines)
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(String::from)
+            .collect();
+
+        Ok(lines)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{ fs, io::Write };
+
+    use crate::tail::TailedFile;
+
+    #[test]
+    fn should_only_return_complete_new_lines() {
+        let path = std::env::temp_dir().join(format!("rtc-tail-{}.log", std::process::id()));
+        fs::write(&path, "first\nsec").unwrap();
+
+        let mut tailed_file = TailedFile::new(path.clone());
+        assert_eq!(tailed_file.read_new_lines().unwrap(), vec!["first"]);
+
+        fs::OpenOptions::new().append(true).open(&path).unwrap().write_all(b"ond\n").unwrap();
+        assert_eq!(tailed_file.read_new_lines().unwrap(), vec!["second"]);
+
+        fs::write(&path, "rotated\n").unwrap();
+        assert_eq!(tailed_file.read_new_lines().unwrap(), vec!["rotated"]);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn should_keep_utf8_sequences_split_across_reads() {
+        let path = std::env::temp_dir().join(format!("rtc-tail-utf8-{}.log", std::process::id()));
+        let line = "GET /caf\u{e9}\n".as_bytes();
+        fs::write(&path, &line[..line.len() - 2]).unwrap();
+
+        let mut tailed_file = TailedFile::new(path.clone());
+        assert!(tailed_file.read_new_lines().unwrap().is_empty());
+
+        fs::OpenOptions::new().append(true).open(&path).unwrap().write_all(&line[line.len() - 2..]).unwrap();
+        assert_eq!(tailed_file.read_new_lines().unwrap(), vec!["GET /caf\u{e9}"]);
+        fs::remove_file(path).unwrap();
+    }
+}
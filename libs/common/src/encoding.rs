@@ -0,0 +1,42 @@
+use std::io::Read;
+
+use flate2::read::{ GzDecoder, ZlibDecoder };
+
+/// Largest decompressed body accepted, a small compressed body can expand a lot.
+pub const MAX_DECODED_BODY: u64 = 16 * 1024 * 1024;
+
+pub enum BodyError {
+    /// The `Content-Encoding` is neither `gzip`, `deflate` nor `identity`.
+    UnsupportedEncoding(String),
+    /// The body could not be decompressed.
+    Malformed(std::io::Error),
+    /// The body expands beyond `MAX_DECODED_BODY` once decompressed.
+    TooLarge,
+}
+
+/// Decompresses a request body according to its `Content-Encoding` header, if any.
+/// Exporters and reporters usually gzip what they send.
+pub fn decode_body(content_encoding: Option<&str>, body: &[u8]) -> Result<Vec<u8>, BodyError> {
+    let mut decoded = vec![];
+    let result = match content_encoding.map(|encoding| encoding.trim().to_lowercase()) {
+        None => {
+            return Ok(body.to_vec());
+        }
+        Some(encoding) if encoding.is_empty() || encoding == "identity" => {
+            return Ok(body.to_vec());
+        }
+        Some(encoding) if encoding == "gzip" || encoding == "x-gzip" =>
+            GzDecoder::new(body).take(MAX_DECODED_BODY + 1).read_to_end(&mut decoded),
+        Some(encoding) if encoding == "deflate" =>
+            ZlibDecoder::new(body).take(MAX_DECODED_BODY + 1).read_to_end(&mut decoded),
+        Some(encoding) => {
+            return Err(BodyError::UnsupportedEncoding(encoding));
+        }
+    };
+
+    result.map_err(BodyError::Malformed)?;
+    if (decoded.len() as u64) > MAX_DECODED_BODY {
+        return Err(BodyError::TooLarge);
+    }
+    Ok(decoded)
+}
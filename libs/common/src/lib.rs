@@ -1,3 +1,4 @@
 pub mod tracing;
 pub mod types;
 pub mod marcos;
+pub mod encoding;
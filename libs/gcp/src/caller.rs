@@ -0,0 +1,113 @@
+use anyhow::{ Context, Result };
+use regex::Regex;
+use serde::{ Deserialize, Serialize };
+
+/// Names the callers whose user agent matches `pattern`, e.g. `^ShopApp/(\d+\.\d+)` named
+/// `shop-app $1`. The name may refer to the groups captured by the pattern.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CallerRule {
+    pub pattern: String,
+    pub name: String,
+}
+
+/// The first matching rule wins, more specific agents come first, browsers last.
+/// HTTP client libraries shared by backends and apps, e.g. okhttp, are left out.
+const DEFAULT_CALLER_RULES: [(&str, &str); 7] = [
+    (r"^Google-Cloud-Scheduler", "cloud-scheduler"),
+    (r"^Google-Cloud-Tasks", "cloud-tasks"),
+    (r"GoogleStackdriverMonitoring-UptimeChecks", "uptime-check"),
+    (r"^curl/", "curl"),
+    (r"^Dart/(\d+\.\d+)", "flutter-app (dart $1)"),
+    (r"^([\w.-]+)/(\d+(?:\.\d+)*) CFNetwork/", "ios-app ($1 $2)"),
+    (r"^Mozilla/", "browser"),
+];
+
+/// Classifies the callers of entry operations, the requests no mapped service made,
+/// by their user agent.
+#[derive(Debug, Clone)]
+pub struct CallerClassifier {
+    regexes: Vec<(Regex, String)>,
+}
+
+impl CallerClassifier {
+    /// Custom rules are tried before the default ones.
+    pub fn new(custom_rules: Vec<CallerRule>) -> Result<Self> {
+        let mut regexes = custom_rules
+            .iter()
+            .map(|rule| {
+                let regex = Regex::new(&rule.pattern).with_context(||
+                    format!("Invalid caller rule pattern {}", rule.pattern)
+                )?;
+                Ok((regex, rule.name.clone()))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        for (pattern, name) in DEFAULT_CALLER_RULES {
+            regexes.push((Regex::new(pattern)?, name.to_string()));
+        }
+
+        Ok(Self { regexes })
+    }
+
+    pub fn classify(&self, user_agent: &str) -> Option<String> {
+        self.regexes.iter().find_map(|(regex, name)| {
+            let captures = regex.captures(user_agent)?;
+            let mut caller = String::new();
+            captures.expand(name, &mut caller);
+            Some(caller)
+        })
+    }
+}
+
+impl Default for CallerClassifier {
+    fn default() -> Self {
+        Self::new(vec![]).expect("Default caller rules are valid")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::caller::{ CallerClassifier, CallerRule };
+
+    #[test]
+    fn should_classify_callers_by_user_agent() {
+        let classifier = CallerClassifier::default();
+
+        assert_eq!(
+            classifier.classify("Google-Cloud-Scheduler"),
+            Some("cloud-scheduler".into())
+        );
+        assert_eq!(
+            classifier.classify("GoogleStackdriverMonitoring-UptimeChecks(https://cloud.google.com/monitoring)"),
+            Some("uptime-check".into())
+        );
+        assert_eq!(classifier.classify("curl/8.4.0"), Some("curl".into()));
+        assert_eq!(
+            classifier.classify("ShopApp/3.2.1 CFNetwork/1408.0.4 Darwin/22.5.0"),
+            Some("ios-app (ShopApp 3.2.1)".into())
+        );
+        assert_eq!(
+            classifier.classify("Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) Safari/605.1.15"),
+            Some("browser".into())
+        );
+        assert_eq!(classifier.classify("Go-http-client/1.1"), None);
+        assert_eq!(classifier.classify("okhttp/4.12.0"), None);
+    }
+
+    #[test]
+    fn should_try_custom_rules_first() {
+        let classifier = CallerClassifier::new(
+            vec![CallerRule {
+                pattern: r"^Mozilla/.*ShopWebView/(\d+)".into(),
+                name: "shop-app webview $1".into(),
+            }]
+        ).unwrap();
+
+        assert_eq!(
+            classifier.classify("Mozilla/5.0 (Linux; Android 14) ShopWebView/7"),
+            Some("shop-app webview 7".into())
+        );
+        assert_eq!(classifier.classify("Mozilla/5.0"), Some("browser".into()));
+        assert!(CallerClassifier::new(vec![CallerRule { pattern: "(".into(), name: "x".into() }]).is_err());
+    }
+}
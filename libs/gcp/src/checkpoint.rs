@@ -0,0 +1,141 @@
+use std::{ collections::HashMap, fs, path::PathBuf };
+
+use anyhow::{ Context, Ok, Result };
+use logs_to_graph::service_node_graph::ServiceNodeGraph;
+use serde::{ Deserialize, Serialize };
+
+use crate::{ cursor::LogCursor, types::Trace };
+
+/// How long processed traces are remembered, counting back from the newest log entry processed.
+/// The log entries of a trace are written within moments of each other.
+const PROCESSED_TRACES_RETENTION_SECS: i64 = 24 * 60 * 60;
+
+/// Progress of the GCP engine, persisted so that a restart resumes where it stopped.
+///
+/// The spans mapped from log entries are not persisted, after a restart the spans whose
+/// parent was logged before the checkpoint can no longer be linked to it.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// Newest log entry processed, along with the insertIds seen at its timestamp.
+    pub cursor: LogCursor,
+    /// Traces that have already been fetched and processed, with the unix time of the
+    /// newest log entry processed back then.
+    pub processed_traces: HashMap<Trace, i64>,
+    /// The graph mapped up to the cursor. Its figures are cumulative and written as is,
+    /// they carry on from there after a restart rather than starting over. Only filled in
+    /// while saving, as the graph being mapped lives in the processor.
+    #[serde(default)]
+    pub service_node_graph: ServiceNodeGraph,
+}
+
+impl Checkpoint {
+    pub fn add_processed_trace(&mut self, trace: Trace) {
+        let processed_at = self.cursor.timestamp.map(|timestamp| timestamp.seconds()).unwrap_or_default();
+        self.processed_traces.insert(trace, processed_at);
+    }
+
+    /// Unix time before which traces are forgotten, relative to the newest log entry processed.
+    pub fn oldest_retained(&self) -> Option<i64> {
+        self.cursor.timestamp.map(|timestamp| timestamp.seconds() - PROCESSED_TRACES_RETENTION_SECS)
+    }
+
+    /// Forgets the traces processed long before the newest log entry.
+    pub fn prune_processed_traces(&mut self) {
+        let Some(oldest) = self.oldest_retained() else {
+            return;
+        };
+
+        self.processed_traces.retain(|_, processed_at| *processed_at >= oldest);
+    }
+}
+
+pub trait CheckpointStore: Send + Sync {
+    fn load(&self) -> Result<Option<Checkpoint>>;
+    fn save(&self, checkpoint: &Checkpoint) -> Result<()>;
+}
+
+/// Stores the checkpoint as JSON in a local file.
+pub struct FileCheckpointStore {
+    path: PathBuf,
+}
+
+impl FileCheckpointStore {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+}
+
+impl CheckpointStore for FileCheckpointStore {
+    fn load(&self) -> Result<Option<Checkpoint>> {
+        if !self.path.exists() {
+            return Ok(None);
+        }
+
+        let data = fs
+            ::read_to_string(&self.path)
+            .with_context(|| format!("Failed reading checkpoint: {}", self.path.display()))?;
+
+        let checkpoint: Checkpoint = serde_json
+            ::from_str(&data)
+            .with_context(|| format!("Failed parsing checkpoint: {}", self.path.display()))?;
+
+        Ok(Some(checkpoint))
+    }
+
+    fn save(&self, checkpoint: &Checkpoint) -> Result<()> {
+        // Write to a temporary file first so that a crash never leaves a truncated checkpoint.
+        let tmp_path = self.path.with_extension("tmp");
+        fs::write(&tmp_path, serde_json::to_vec(checkpoint)?)?;
+        fs::rename(&tmp_path, &self.path).with_context(||
+            format!("Failed writing checkpoint: {}", self.path.display())
+        )?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use google_cloud_logging_v2::model::LogEntry;
+    use google_cloud_wkt::Timestamp;
+
+    use crate::checkpoint::{ Checkpoint, CheckpointStore, FileCheckpointStore };
+
+    #[test]
+    fn should_round_trip_a_checkpoint() {
+        let path = std::env::temp_dir().join(format!("rtc-checkpoint-{}.json", std::process::id()));
+        let store = FileCheckpointStore::new(path.clone());
+
+        assert!(store.load().unwrap().is_none());
+
+        let mut checkpoint = Checkpoint::default();
+        checkpoint.cursor.advance(
+            &LogEntry::new().set_insert_id("a").set_timestamp(Timestamp::clamp(10, 0))
+        );
+        checkpoint.add_processed_trace("projects/p/traces/t".into());
+        store.save(&checkpoint).unwrap();
+
+        let loaded = store.load().unwrap().unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(loaded.cursor.timestamp, Some(Timestamp::clamp(10, 0)));
+        assert!(loaded.cursor.insert_ids.contains("a"));
+        assert_eq!(loaded.processed_traces["projects/p/traces/t"], 10);
+    }
+
+    #[test]
+    fn should_forget_traces_processed_long_before_the_newest_log_entry() {
+        let mut checkpoint = Checkpoint::default();
+        checkpoint.cursor.advance(&LogEntry::new().set_insert_id("a").set_timestamp(Timestamp::clamp(0, 0)));
+        checkpoint.add_processed_trace("old".into());
+
+        checkpoint.cursor.advance(
+            &LogEntry::new().set_insert_id("b").set_timestamp(Timestamp::clamp(2 * 24 * 60 * 60, 0))
+        );
+        checkpoint.add_processed_trace("new".into());
+        checkpoint.prune_processed_traces();
+
+        assert!(!checkpoint.processed_traces.contains_key("old"));
+        assert!(checkpoint.processed_traces.contains_key("new"));
+    }
+}
@@ -1,10 +1,35 @@
-// Note: wildcards are not allowed in the log filter for whatever reason.
-pub const DEFAULT_LOG_FILTER: &str = "resource.type:cloud_run_revision AND http_request:*";
-
-pub const PATH_NORMALIZE_PATTERNS: [(&str, &str); 2] = [
-    ("id", r"^\d+$"),
-    (
-        "uuid",
-        r"^[0-9a-fA-F]{8}-[0-9a-fA-F]{4}-[1-5][0-9a-fA-F]{3}-[89abAB][0-9a-fA-F]{3}-[0-9a-fA-F]{12}$",
-    ),
+/// Resource types whose log entries are mapped to services.
+pub const SUPPORTED_RESOURCE_TYPES: [&str; 7] = [
+    "cloud_run_revision",
+    "cloud_run_job",
+    "k8s_container",
+    "k8s_pod",
+    "gae_app",
+    "cloud_function",
+    "http_load_balancer",
+];
+
+/// External HTTP(S) load balancers, whose requests reveal the public entrypoints.
+pub const HTTP_LOAD_BALANCER_RESOURCE_TYPE: &str = "http_load_balancer";
+
+/// Jobs do not serve requests, their log entries are only useful to map the traces they start.
+pub const TRACE_ONLY_RESOURCE_TYPES: [&str; 1] = ["cloud_run_job"];
+
+/// Kind of the Cloud Trace spans that call another service.
+pub const RPC_CLIENT_SPAN_KIND: &str = "RPC_CLIENT";
+
+/// Label set by the Cloud SQL connectors on the spans of their connections.
+pub const CLOUD_SQL_INSTANCE_LABEL: &str = "/cloudsql/instance";
+
+/// Labels holding the URL requested by a client span, set by the Cloud Trace agents
+/// or by OpenTelemetry exporters.
+pub const HTTP_URL_LABELS: [&str; 3] = ["/http/url", "http.url", "url.full"];
+
+/// `serviceName` of the Firestore audit logs.
+pub const FIRESTORE_SERVICE_NAME: &str = "firestore.googleapis.com";
+
+/// Logged by the v2 and v1 Cloud SQL Auth Proxy when a connection is opened.
+pub const CLOUD_SQL_PROXY_CONNECTION_MESSAGES: [&str; 2] = [
+    "Accepted connection from",
+    "New connection for",
 ];
@@ -0,0 +1,99 @@
+use std::collections::HashSet;
+
+use google_cloud_logging_v2::model::LogEntry;
+use google_cloud_wkt::Timestamp;
+use serde::{ Deserialize, Serialize };
+
+/// Keeps track of the newest log entry that has been processed.
+///
+/// Cloud Logging timestamps are not unique, so alongside the newest timestamp
+/// we remember every insertId seen at that exact timestamp. A poll restarting
+/// from `timestamp>=` can then skip the entries it has already processed.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LogCursor {
+    pub timestamp: Option<Timestamp>,
+    pub insert_ids: HashSet<String>,
+}
+
+impl LogCursor {
+    /// Whether the log entry is newer than what the cursor has already seen.
+    /// Entries without a timestamp are always considered new.
+    pub fn is_new(&self, log_entry: &LogEntry) -> bool {
+        match (self.timestamp, log_entry.timestamp) {
+            (Some(cursor_timestamp), Some(timestamp)) => {
+                if timestamp == cursor_timestamp {
+                    return !self.insert_ids.contains(&log_entry.insert_id);
+                }
+                timestamp > cursor_timestamp
+            }
+            _ => true,
+        }
+    }
+
+    /// Moves the cursor forward if the log entry is the newest seen so far.
+    pub fn advance(&mut self, log_entry: &LogEntry) {
+        let Some(timestamp) = log_entry.timestamp else {
+            return;
+        };
+
+        match self.timestamp {
+            Some(cursor_timestamp) if timestamp < cursor_timestamp => {}
+            Some(cursor_timestamp) if timestamp == cursor_timestamp => {
+                self.insert_ids.insert(log_entry.insert_id.clone());
+            }
+            _ => {
+                self.timestamp = Some(timestamp);
+                self.insert_ids = HashSet::from_iter([log_entry.insert_id.clone()]);
+            }
+        }
+    }
+
+    /// Log filter that restricts a query to entries at or after the cursor.
+    pub fn filter(&self) -> Option<String> {
+        self.timestamp.map(|timestamp| format!("timestamp>=\"{}\"", String::from(timestamp)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use google_cloud_logging_v2::model::LogEntry;
+    use google_cloud_wkt::Timestamp;
+
+    use crate::cursor::LogCursor;
+
+    fn log_entry(insert_id: &str, seconds: i64) -> LogEntry {
+        LogEntry::new().set_insert_id(insert_id).set_timestamp(Timestamp::clamp(seconds, 0))
+    }
+
+    #[test]
+    fn should_consider_everything_new_when_empty() {
+        let cursor = LogCursor::default();
+        assert!(cursor.is_new(&log_entry("a", 10)));
+        assert_eq!(cursor.filter(), None);
+    }
+
+    #[test]
+    fn should_skip_entries_already_seen_at_the_same_timestamp() {
+        let mut cursor = LogCursor::default();
+        cursor.advance(&log_entry("a", 10));
+        cursor.advance(&log_entry("b", 10));
+
+        assert!(!cursor.is_new(&log_entry("a", 10)));
+        assert!(!cursor.is_new(&log_entry("b", 10)));
+        assert!(cursor.is_new(&log_entry("c", 10)));
+        assert!(!cursor.is_new(&log_entry("z", 9)));
+        assert!(cursor.is_new(&log_entry("a", 11)));
+    }
+
+    #[test]
+    fn should_only_move_forward() {
+        let mut cursor = LogCursor::default();
+        cursor.advance(&log_entry("a", 10));
+        cursor.advance(&log_entry("b", 12));
+        cursor.advance(&log_entry("c", 11));
+
+        assert_eq!(cursor.timestamp, Some(Timestamp::clamp(12, 0)));
+        assert_eq!(cursor.insert_ids.len(), 1);
+        assert_eq!(cursor.filter(), Some("timestamp>=\"1970-01-01T00:00:12Z\"".into()));
+    }
+}
@@ -0,0 +1,276 @@
+use std::sync::LazyLock;
+
+use google_cloud_logging_v2::model::LogEntry;
+use logs_to_graph::service_node_graph::{ Operation, ServiceName };
+use regex::Regex;
+use serde_json::Value;
+
+use crate::{ consts::{ CLOUD_SQL_INSTANCE_LABEL, FIRESTORE_SERVICE_NAME }, trace::Span };
+
+// Labels of the OpenTelemetry database semantic conventions, current ones first.
+const DB_SYSTEM_LABELS: [&str; 2] = ["db.system.name", "db.system"];
+const DB_INSTANCE_LABELS: [&str; 4] = ["server.address", "net.peer.name", "db.namespace", "db.name"];
+const DB_COLLECTION_LABELS: [&str; 4] = [
+    "db.collection.name",
+    "db.sql.table",
+    "db.mongodb.collection",
+    "db.cassandra.table",
+];
+const DB_OPERATION_LABELS: [&str; 2] = ["db.operation.name", "db.operation"];
+const DB_STATEMENT_LABELS: [&str; 2] = ["db.query.text", "db.statement"];
+
+const CLOUD_SQL_SYSTEM: &str = "cloudsql";
+const FIRESTORE_SYSTEM: &str = "firestore";
+/// Used when a client span does not tell which server it talked to.
+const DEFAULT_INSTANCE: &str = "default";
+/// Connector and proxy logs only cover opening connections, queries are not logged.
+const CONNECT_COMMAND: &str = "CONNECT";
+
+/// `[my-project:europe-west1:main] Accepted connection from 127.0.0.1:51234` for the v2 proxy,
+/// `New connection for "my-project:europe-west1:main"` for the v1 one.
+static CLOUD_SQL_PROXY_CONNECTION_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r#"(?:\[([^\]\s]+:[^\]\s]+:[^\]\s]+)\] Accepted connection from|New connection for "([^"]+)")"#
+    ).unwrap()
+});
+
+/// Pointers to a document path in the requests of the Firestore API, by method.
+const FIRESTORE_DOCUMENT_POINTERS: [&str; 5] = [
+    "/name",
+    "/document/name",
+    "/documents/0",
+    "/writes/0/update/name",
+    "/writes/0/delete",
+];
+const FIRESTORE_COLLECTION_POINTERS: [&str; 2] = ["/structuredQuery/from/0/collectionId", "/collectionId"];
+
+/// Datastores are named after their system and their instance, e.g. `redis:10.0.0.3`.
+pub fn datastore_name(system: &str, instance: &str) -> ServiceName {
+    format!("{}:{}", system, instance)
+}
+
+fn operation_name(operation: &Operation) -> Option<ServiceName> {
+    match operation {
+        Operation::Datastore { system, instance, .. } => Some(datastore_name(system, instance)),
+        _ => None,
+    }
+}
+
+/// The command of a database span, e.g. `SELECT` out of `SELECT * FROM users`.
+fn span_command(span: &Span) -> Option<String> {
+    if let Some(operation) = span.label(&DB_OPERATION_LABELS) {
+        return Some(operation.to_uppercase());
+    }
+
+    span.label(&DB_STATEMENT_LABELS)
+        .and_then(|statement| statement.split_whitespace().next())
+        .or(span.name.as_deref())
+        .map(|command| command.to_uppercase())
+}
+
+/// The datastore accessed by a database client span, or by a Cloud SQL connector span.
+pub fn span_datastore_call(span: &Span) -> Option<(ServiceName, Operation)> {
+    let operation = match span.label(&[CLOUD_SQL_INSTANCE_LABEL]) {
+        Some(instance) =>
+            Operation::Datastore {
+                system: CLOUD_SQL_SYSTEM.into(),
+                instance: instance.into(),
+                collection: span.label(&DB_COLLECTION_LABELS).map(String::from),
+                command: span
+                    .label(&DB_SYSTEM_LABELS)
+                    .and_then(|_| span_command(span))
+                    .or(Some(CONNECT_COMMAND.into())),
+            },
+        None =>
+            Operation::Datastore {
+                system: span.label(&DB_SYSTEM_LABELS)?.into(),
+                instance: span.label(&DB_INSTANCE_LABELS).unwrap_or(DEFAULT_INSTANCE).into(),
+                collection: span.label(&DB_COLLECTION_LABELS).map(String::from),
+                command: span_command(span),
+            },
+    };
+
+    Some((operation_name(&operation)?, operation))
+}
+
+fn log_message(log_entry: &LogEntry) -> Option<&str> {
+    log_entry
+        .text_payload()
+        .map(String::as_str)
+        .or_else(|| log_entry.json_payload()?.get("message")?.as_str())
+}
+
+/// The instance a Cloud SQL Auth Proxy sidecar opened a connection to, from its logs.
+pub fn cloud_sql_proxy_connection(log_entry: &LogEntry) -> Option<(ServiceName, Operation)> {
+    let captures = CLOUD_SQL_PROXY_CONNECTION_REGEX.captures(log_message(log_entry)?)?;
+    let instance = captures.get(1).or(captures.get(2))?.as_str();
+
+    let operation = Operation::Datastore {
+        system: CLOUD_SQL_SYSTEM.into(),
+        instance: instance.into(),
+        collection: None,
+        command: Some(CONNECT_COMMAND.into()),
+    };
+    Some((operation_name(&operation)?, operation))
+}
+
+/// `projects/my-project/databases/(default)/documents/users/42/orders/1` is in `orders`.
+fn document_collection(document_path: &str) -> Option<String> {
+    let (_, path) = document_path.split_once("/documents/")?;
+    let segments: Vec<&str> = path.split('/').collect();
+
+    // Collections and documents alternate, a path either ends with a collection or a document.
+    // A lone segment is a collection, an empty path has none.
+    let index = (segments.len() + (segments.len() % 2)).checked_sub(2)?;
+    segments.get(index).filter(|collection| !collection.is_empty()).map(|collection| collection.to_string())
+}
+
+/// A Firestore access, from a Data Access audit log entry. The caller is the account
+/// of the service, e.g. `users-service@my-project.iam.gserviceaccount.com` for `users-service`.
+pub fn firestore_access(log_entry: &LogEntry) -> Option<(String, ServiceName, Operation)> {
+    let payload = serde_json::to_value(log_entry.proto_payload()?).ok()?;
+    if payload.get("serviceName")?.as_str()? != FIRESTORE_SERVICE_NAME {
+        return None;
+    }
+
+    let principal_email = payload.pointer("/authenticationInfo/principalEmail")?.as_str()?;
+    let (account, domain) = principal_email.split_once('@')?;
+    // Default service accounts are shared by every service of the project.
+    if domain.ends_with("developer.gserviceaccount.com") || domain.ends_with("appspot.gserviceaccount.com") {
+        return None;
+    }
+
+    let database = payload
+        .get("resourceName")?
+        .as_str()?
+        .split_once("/databases/")?
+        .1.split('/')
+        .next()?;
+
+    let request = payload.get("request").unwrap_or(&Value::Null);
+    let collection = FIRESTORE_DOCUMENT_POINTERS.iter()
+        .find_map(|pointer| request.pointer(pointer)?.as_str().and_then(document_collection))
+        .or_else(|| {
+            FIRESTORE_COLLECTION_POINTERS.iter().find_map(|pointer|
+                request.pointer(pointer)?.as_str().map(String::from)
+            )
+        });
+
+    // e.g. `google.firestore.v1.Firestore.RunQuery`.
+    let command = payload
+        .get("methodName")
+        .and_then(|method_name| method_name.as_str())
+        .and_then(|method_name| method_name.rsplit('.').next())
+        .map(String::from);
+
+    let operation = Operation::Datastore {
+        system: FIRESTORE_SYSTEM.into(),
+        instance: database.into(),
+        collection,
+        command,
+    };
+    Some((account.to_string(), operation_name(&operation)?, operation))
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use google_cloud_logging_v2::model::LogEntry;
+    use logs_to_graph::service_node_graph::Operation;
+    use serde_json::json;
+
+    use crate::{
+        datastore::{
+            cloud_sql_proxy_connection,
+            document_collection,
+            firestore_access,
+            span_datastore_call,
+        },
+        trace::Span,
+    };
+
+    #[test]
+    fn should_map_database_client_spans() {
+        let span = Span {
+            labels: HashMap::from([
+                ("db.system".into(), "postgresql".into()),
+                ("server.address".into(), "10.0.0.3".into()),
+                ("db.sql.table".into(), "users".into()),
+                ("db.statement".into(), "select * from users where id = $1".into()),
+            ]),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            span_datastore_call(&span),
+            Some((
+                "postgresql:10.0.0.3".into(),
+                Operation::Datastore {
+                    system: "postgresql".into(),
+                    instance: "10.0.0.3".into(),
+                    collection: Some("users".into()),
+                    command: Some("SELECT".into()),
+                },
+            ))
+        );
+    }
+
+    #[test]
+    fn should_map_cloud_sql_proxy_connections() {
+        let log_entry: LogEntry = serde_json
+            ::from_value(
+                json!({
+                "textPayload": "2024/05/02 10:00:00 [my-project:europe-west1:main] Accepted connection from 127.0.0.1:51234",
+            })
+            )
+            .unwrap();
+
+        assert_eq!(
+            cloud_sql_proxy_connection(&log_entry).map(|(name, _)| name),
+            Some("cloudsql:my-project:europe-west1:main".into())
+        );
+    }
+
+    #[test]
+    fn should_find_the_collection_of_a_document() {
+        let path = |path: &str| format!("projects/my-project/databases/(default)/documents/{}", path);
+
+        assert_eq!(document_collection(&path("users/42/orders/1")).as_deref(), Some("orders"));
+        assert_eq!(document_collection(&path("users/42/orders")).as_deref(), Some("orders"));
+        assert_eq!(document_collection(&path("users")).as_deref(), Some("users"));
+        assert_eq!(document_collection(&path("")), None);
+    }
+
+    #[test]
+    fn should_map_firestore_audit_logs() {
+        let log_entry: LogEntry = serde_json
+            ::from_value(
+                json!({
+                "protoPayload": {
+                    "@type": "type.googleapis.com/google.cloud.audit.AuditLog",
+                    "serviceName": "firestore.googleapis.com",
+                    "methodName": "google.firestore.v1.Firestore.Commit",
+                    "resourceName": "projects/my-project/databases/(default)",
+                    "authenticationInfo": { "principalEmail": "users-service@my-project.iam.gserviceaccount.com" },
+                    "request": { "writes": [{ "update": { "name": "projects/my-project/databases/(default)/documents/users/42" } }] },
+                },
+            })
+            )
+            .unwrap();
+
+        assert_eq!(
+            firestore_access(&log_entry),
+            Some((
+                "users-service".into(),
+                "firestore:(default)".into(),
+                Operation::Datastore {
+                    system: "firestore".into(),
+                    instance: "(default)".into(),
+                    collection: Some("users".into()),
+                    command: Some("Commit".into()),
+                },
+            ))
+        );
+    }
+}
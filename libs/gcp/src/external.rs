@@ -0,0 +1,143 @@
+use logs_to_graph::{
+    grpc::grpc_operation,
+    normalize::normalize_path,
+    service_node_graph::{ Operation, ServiceName },
+};
+use regex::Regex;
+use url::Url;
+
+use crate::{ consts::HTTP_URL_LABELS, datastore::span_datastore_call, trace::Span };
+
+// Labels set by the Cloud Trace agents, followed by their OpenTelemetry equivalents.
+const HTTP_METHOD_LABELS: [&str; 3] = ["/http/method", "http.method", "http.request.method"];
+const HTTP_HOST_LABELS: [&str; 3] = ["/http/host", "server.address", "net.peer.name"];
+
+fn http_call(
+    span: &Span,
+    path_normalize_regexes: &[(String, Vec<Regex>)]
+) -> Option<(ServiceName, Operation)> {
+    let url = span.label(&HTTP_URL_LABELS)?;
+    let method = span.label(&HTTP_METHOD_LABELS)?;
+
+    let host = match span.label(&HTTP_HOST_LABELS) {
+        Some(host) => host.to_string(),
+        None => Url::parse(url).ok()?.host_str()?.to_string(),
+    };
+    // The port does not tell services apart.
+    let host = host.split(':').next().unwrap_or_default().to_string();
+
+    if let Some(operation) = grpc_operation(method, Url::parse(url).ok()?.path(), None) {
+        return Some((host, operation));
+    }
+
+    let path = normalize_path(url, path_normalize_regexes.to_vec()).ok()?;
+
+    Some((host, Operation::Http { method: method.to_uppercase(), path }))
+}
+
+/// The dependency called by a span, from its labels, such as `api.stripe.com`
+/// or `cloudsql:my-project:europe-west1:main`.
+pub fn external_call(
+    span: &Span,
+    path_normalize_regexes: &[(String, Vec<Regex>)]
+) -> Option<(ServiceName, Operation)> {
+    if let Some(call) = span_datastore_call(span) {
+        return Some(call);
+    }
+
+    if !span.is_client() {
+        return None;
+    }
+
+    http_call(span, path_normalize_regexes)
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use logs_to_graph::{
+        normalize::get_default_path_normalize_regexes,
+        service_node_graph::Operation,
+    };
+
+    use crate::{ external::external_call, trace::Span };
+
+    fn client_span(labels: &[(&str, &str)]) -> Span {
+        Span {
+            span_id: "1".into(),
+            kind: Some("RPC_CLIENT".into()),
+            labels: HashMap::from_iter(labels.iter().map(|(k, v)| (k.to_string(), v.to_string()))),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn should_map_third_party_http_calls() {
+        let span = client_span(
+            &[
+                ("/http/url", "https://api.stripe.com:443/v1/customers/42"),
+                ("/http/method", "POST"),
+                ("g.co/agent", "opentelemetry-go 1.24.0"),
+            ]
+        );
+
+        assert_eq!(
+            external_call(&span, &get_default_path_normalize_regexes()),
+            Some((
+                "api.stripe.com".into(),
+                Operation::Http { method: "POST".into(), path: "/v1/customers/{customers_id}".into() },
+            ))
+        );
+    }
+
+    #[test]
+    fn should_map_database_calls() {
+        let span = client_span(
+            &[
+                ("db.system", "redis"),
+                ("server.address", "10.0.0.3"),
+                ("db.statement", "get session:42"),
+            ]
+        );
+        assert_eq!(
+            external_call(&span, &[]),
+            Some((
+                "redis:10.0.0.3".into(),
+                Operation::Datastore {
+                    system: "redis".into(),
+                    instance: "10.0.0.3".into(),
+                    collection: None,
+                    command: Some("GET".into()),
+                },
+            ))
+        );
+
+        let span = Span {
+            labels: HashMap::from([
+                ("/cloudsql/instance".into(), "my-project:europe-west1:main".into()),
+            ]),
+            ..Default::default()
+        };
+        assert_eq!(
+            external_call(&span, &[]),
+            Some((
+                "cloudsql:my-project:europe-west1:main".into(),
+                Operation::Datastore {
+                    system: "cloudsql".into(),
+                    instance: "my-project:europe-west1:main".into(),
+                    collection: None,
+                    command: Some("CONNECT".into()),
+                },
+            ))
+        );
+    }
+
+    #[test]
+    fn should_ignore_server_spans() {
+        let mut span = client_span(&[("/http/url", "https://users/users/1"), ("/http/method", "GET")]);
+        span.kind = Some("RPC_SERVER".into());
+
+        assert_eq!(external_call(&span, &[]), None);
+    }
+}
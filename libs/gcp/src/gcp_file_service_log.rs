@@ -0,0 +1,146 @@
+use std::path::PathBuf;
+
+use anyhow::{ Ok, Result };
+use async_trait::async_trait;
+use google_cloud_logging_v2::model::LogEntry;
+use logs_to_graph::{
+    json_files::read_json_documents,
+    normalize::build_path_normalize_regexes,
+    service_logs::ServiceLogs,
+    service_node_graph::ServiceNodeGraph,
+};
+use regex::Regex;
+use tokio::sync::mpsc::Sender;
+use tracing::{ error, info };
+
+use crate::{ gcp_processor::GcpProcessor, options::GcpProcessingOptions, trace::Trace };
+
+/// Rebuilds the graph from exported log entries and traces, without any network access.
+///
+/// Log entries are read from `gcloud logging read --format=json` dumps or log sink
+/// exports, either as JSON arrays or JSON lines. Traces use the Cloud Trace v1 JSON format.
+pub struct GCPFileServiceLogs {
+    log_paths: Vec<PathBuf>,
+    trace_paths: Vec<PathBuf>,
+    path_normalize_regexes: Vec<(String, Vec<Regex>)>,
+    processing: GcpProcessingOptions,
+}
+
+impl GCPFileServiceLogs {
+    pub fn new(
+        log_paths: Vec<PathBuf>,
+        trace_paths: Vec<PathBuf>,
+        custom_path_normalize_patterns: Vec<String>,
+        processing: GcpProcessingOptions
+    ) -> Result<Self> {
+        let path_normalize_regexes = build_path_normalize_regexes(
+            &custom_path_normalize_patterns
+        )?;
+        processing.validate()?;
+
+        Ok(Self {
+            log_paths,
+            trace_paths,
+            path_normalize_regexes,
+            processing,
+        })
+    }
+}
+
+#[async_trait]
+impl ServiceLogs for GCPFileServiceLogs {
+    async fn run(&self, sender: Sender<ServiceNodeGraph>) -> Result<()> {
+        let mut gcp_processor = GcpProcessor::new(
+            self.path_normalize_regexes.clone()
+        ).with_options(&self.processing)?;
+
+        let log_entries = read_json_documents::<LogEntry>(&self.log_paths)?;
+        info!("Processing {} log entries", log_entries.len());
+
+        for log_entry in log_entries {
+            gcp_processor.process_log(log_entry);
+        }
+
+        if sender.send(gcp_processor.service_node_graph.clone()).await.is_err() {
+            error!("Failed to send service node graph from the Service Logs to the Service Graph.");
+        }
+
+        if self.trace_paths.is_empty() {
+            return Ok(());
+        }
+
+        let traces = read_json_documents::<Trace>(&self.trace_paths)?;
+        info!("Processing {} traces", traces.len());
+
+        for trace in traces {
+            gcp_processor.process_trace(trace);
+        }
+
+        if sender.send(gcp_processor.service_node_graph.clone()).await.is_err() {
+            error!("Failed to send service node graph from the Service Logs to the Service Graph.");
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use logs_to_graph::service_logs::ServiceLogs;
+    use tokio::sync::mpsc::channel;
+
+    use crate::{ gcp_file_service_log::GCPFileServiceLogs, options::GcpProcessingOptions };
+
+    const LOG_ENTRIES: &str =
+        r#"[
+        {
+            "insertId": "1",
+            "timestamp": "2025-01-01T00:00:00.123Z",
+            "severity": "INFO",
+            "resource": { "type": "cloud_run_revision", "labels": { "service_name": "graphql-service" } },
+            "httpRequest": { "requestMethod": "POST", "requestUrl": "https://graphql.test.com/", "status": 200, "latency": "0.120s" },
+            "trace": "projects/my-project/traces/abc",
+            "spanId": "1"
+        },
+        {
+            "insertId": "2",
+            "timestamp": "2025-01-01T00:00:00.456Z",
+            "severity": "INFO",
+            "resource": { "type": "cloud_run_revision", "labels": { "service_name": "users-service" } },
+            "httpRequest": { "requestMethod": "GET", "requestUrl": "https://users.test.com/users/12", "status": 200, "latency": "0.020s" },
+            "trace": "projects/my-project/traces/abc",
+            "spanId": "2"
+        }
+    ]"#;
+
+    const TRACES: &str =
+        r#"{"projectId": "my-project", "traceId": "abc", "spans": [{"spanId": "1"}, {"spanId": "2", "parentSpanId": "1"}]}"#;
+
+    #[tokio::test]
+    async fn should_rebuild_the_graph_from_exported_files() {
+        let dir = std::env::temp_dir().join(format!("rtc-file-service-log-{}", std::process::id()));
+        fs::create_dir_all(dir.join("logs")).unwrap();
+        fs::write(dir.join("logs").join("entries.json"), LOG_ENTRIES).unwrap();
+        fs::write(dir.join("traces.jsonl"), TRACES).unwrap();
+
+        let service_logs = GCPFileServiceLogs::new(
+            vec![dir.join("logs")],
+            vec![dir.join("traces.jsonl")],
+            vec![],
+            GcpProcessingOptions::default()
+        ).unwrap();
+
+        let (sender, mut receiver) = channel(32);
+        service_logs.run(sender).await.unwrap();
+        fs::remove_dir_all(dir).unwrap();
+
+        let graph_from_logs = receiver.recv().await.unwrap();
+        assert_eq!(graph_from_logs.services.len(), 2);
+
+        let graph_from_traces = receiver.recv().await.unwrap();
+        let invokes = &graph_from_traces.services["graphql-service"].invokes;
+        assert_eq!(invokes["users-service"].len(), 1);
+    }
+}
@@ -1,147 +1,1270 @@
 use std::collections::{ HashMap, HashSet };
 
 use google_cloud_logging_v2::model::LogEntry;
-use logs_to_graph::service_node_graph::{ Operation, ServiceNodeGraph };
-use tracing::{ error, warn };
-use crate::trace::Trace;
-use crate::types::{ Trace as TraceType, SpanId };
-use crate::normalize::{ normalize_path };
-use crate::utils::build_trace_path;
+use logs_to_graph::service_node_graph::{ Operation, ServiceKind, ServiceNodeGraph };
+use tracing::{ debug, error, warn };
+use anyhow::{ Ok, Result };
+use crate::caller::CallerClassifier;
+use crate::consts::{ HTTP_LOAD_BALANCER_RESOURCE_TYPE, SUPPORTED_RESOURCE_TYPES };
+use crate::resource::resource_service_name;
+use crate::datastore::{ cloud_sql_proxy_connection, firestore_access };
+use crate::external::external_call;
+use crate::graphql::GraphQLPayloadFields;
+use crate::messaging::{ MessageDelivery, MessageRole, message_call, message_delivery };
+use crate::options::GcpProcessingOptions;
+use crate::trace::{ Span, Trace };
+use crate::types::{ SpanId, TraceId };
+use logs_to_graph::graphql::graphql_operation_from_attributes;
+use logs_to_graph::grpc::grpc_operation;
+use logs_to_graph::metrics::{ RequestSample, SeriesOptions };
+use logs_to_graph::normalize::normalize_path;
+use crate::utils::{ parse_log_name_project, parse_trace_path };
 use regex::Regex;
+use url::Url;
 use logs_to_graph::service_node_graph::ServiceName;
 
+/// An invocation found in a trace, between the nearest spans mapped to services.
+#[derive(Debug, PartialEq)]
+pub struct TraceLink {
+    pub invoker: ServiceName,
+    pub target: ServiceName,
+    pub operation: Operation,
+    /// Spans between the invoker and the target that were not mapped to any service.
+    pub skipped_hops: usize,
+}
+
+/// What a span of a trace was mapped to, from its log entry.
+pub struct SpanService {
+    pub service_name: ServiceName,
+    /// The operation served by the span, `None` for log entries that are not requests.
+    pub operation: Option<Operation>,
+    /// Set when the request delivered a Pub/Sub or Cloud Tasks message.
+    pub delivery: Option<MessageDelivery>,
+    /// Who made the request, from its user agent, shown when no mapped service did.
+    pub caller: Option<ServiceName>,
+    /// Figures of the request, accounted for the invoker once the trace links it.
+    pub sample: Option<RequestSample>,
+    /// Unix time of the log entry, in seconds.
+    pub timestamp: Option<i64>,
+}
+
+impl SpanService {
+    /// Request logs and application logs of a request share its span, the most specific
+    /// operation wins, e.g. `mutation login` over `POST /graphql`.
+    fn merge(&mut self, other: SpanService) {
+        let is_more_specific = other.operation
+            .as_ref()
+            .is_some_and(|operation| {
+                self.operation.is_none() || matches!(operation, Operation::GraphQL { .. })
+            });
+        if is_more_specific {
+            self.operation = other.operation;
+        }
+        self.delivery = self.delivery.take().or(other.delivery);
+        self.caller = self.caller.take().or(other.caller);
+        self.sample = self.sample.take().or(other.sample);
+        self.timestamp = self.timestamp.max(other.timestamp);
+    }
+}
+
 pub struct GcpProcessor {
     pub service_node_graph: ServiceNodeGraph,
     pub path_normalize_regexes: Vec<(String, Vec<Regex>)>,
-    pub trace_map: HashMap<TraceType, HashMap<SpanId, (ServiceName, Operation)>>,
+    /// Keyed by trace id rather than by trace path, so that the log entries
+    /// of a trace crossing several projects end up in the same map.
+    pub trace_map: HashMap<TraceId, HashMap<SpanId, SpanService>>,
+    /// Every span fetched so far, across the projects the trace went through.
+    pub trace_spans: HashMap<TraceId, HashMap<SpanId, Span>>,
+    /// Prefixes service names with their project, e.g. `my-project/users-service`.
+    pub namespace_services: bool,
+    /// Load balancer backend services to the service they route to.
+    /// Unmapped backend services are used as service names as is.
+    pub backend_services: HashMap<String, ServiceName>,
+    /// Where GraphQL servers log the operation they serve, if they do.
+    pub graphql_fields: Option<GraphQLPayloadFields>,
+    /// Names the callers of entry operations after their user agent.
+    pub caller_classifier: CallerClassifier,
+    /// Names the callers of requests without a trace too.
+    pub classify_untraced_callers: bool,
+    /// Hosts of our own frontends, the pages referring requests are mapped for these only.
+    pub frontend_hosts: HashSet<String>,
+    /// Pub/Sub push subscriptions to the topic they are attached to.
+    pub subscription_topics: HashMap<String, String>,
+    /// How the requests of operations and edges are counted over time.
+    pub series_options: SeriesOptions,
 }
 
 impl GcpProcessor {
-    /**
-     * Logs that have already been validated to be a cloud_run_revision.
-     */
-    fn process_cloud_run_revision_log_entry(&mut self, log_entry: LogEntry) {
-        // For now, only processing logs that have a http_request object
-        // Future, should support other protocols.
-        if log_entry.http_request.is_none() {
-            return;
+    pub fn new(path_normalize_regexes: Vec<(String, Vec<Regex>)>) -> Self {
+        Self {
+            service_node_graph: ServiceNodeGraph::default(),
+            path_normalize_regexes,
+            trace_map: HashMap::default(),
+            trace_spans: HashMap::default(),
+            namespace_services: false,
+            backend_services: HashMap::default(),
+            graphql_fields: None,
+            caller_classifier: CallerClassifier::default(),
+            classify_untraced_callers: false,
+            frontend_hosts: HashSet::default(),
+            subscription_topics: HashMap::default(),
+            series_options: SeriesOptions::default(),
         }
+    }
 
-        let service_name_opt = log_entry.resource
-            .map(|resource| resource.labels.get("service_name").cloned())
-            .unwrap_or(None);
+    pub fn with_graphql_fields(mut self, graphql_fields: Option<GraphQLPayloadFields>) -> Self {
+        self.graphql_fields = graphql_fields;
+        self
+    }
 
-        if service_name_opt.is_none() {
-            warn!("Detected a cloud run revision log that did not have a service name set");
-            return;
+    pub fn with_caller_classifier(mut self, caller_classifier: CallerClassifier) -> Self {
+        self.caller_classifier = caller_classifier;
+        self
+    }
+
+    pub fn with_subscription_topics(mut self, subscription_topics: HashMap<String, String>) -> Self {
+        self.subscription_topics = subscription_topics;
+        self
+    }
+
+    pub fn with_untraced_callers(mut self, classify_untraced_callers: bool) -> Self {
+        self.classify_untraced_callers = classify_untraced_callers;
+        self
+    }
+
+    pub fn with_frontend_hosts(mut self, frontend_hosts: Vec<String>) -> Self {
+        self.frontend_hosts = frontend_hosts.into_iter().collect();
+        self
+    }
+
+    pub fn with_series_options(mut self, series_options: SeriesOptions) -> Self {
+        self.series_options = series_options;
+        self
+    }
+
+    /// Applies every processing option at once, as the GCP log engines configure them.
+    pub fn with_options(self, options: &GcpProcessingOptions) -> Result<Self> {
+        Ok(
+            self
+                .with_backend_services(options.backend_services.clone())
+                .with_graphql_fields(options.graphql_fields.clone())
+                .with_caller_classifier(options.caller_classifier()?)
+                .with_untraced_callers(options.classify_untraced_callers)
+                .with_frontend_hosts(options.frontend_hosts.clone())
+                .with_subscription_topics(options.subscription_topics.clone())
+                .with_series_options(options.series_options()?)
+        )
+    }
+
+    pub fn with_backend_services(mut self, backend_services: HashMap<String, ServiceName>) -> Self {
+        self.backend_services = backend_services;
+        self
+    }
+
+    /// Used when several projects are mapped together, services with the same name
+    /// in two projects are then kept apart.
+    pub fn with_namespaced_services(mut self) -> Self {
+        self.namespace_services = true;
+        self
+    }
+
+    fn get_service_name(&self, log_entry: &LogEntry, service_name: String) -> String {
+        if !self.namespace_services {
+            return service_name;
+        }
+
+        let project_id = log_entry.resource
+            .as_ref()
+            .and_then(|resource| resource.labels.get("project_id").cloned())
+            .or_else(|| parse_log_name_project(&log_entry.log_name));
+
+        match project_id {
+            Some(project_id) => format!("{}/{}", project_id, service_name),
+            None => service_name,
         }
+    }
+
+    /// Derives the operation described by the http_request of a log entry, if any.
+    fn get_http_operation(&self, log_entry: &LogEntry) -> Result<Option<Operation>> {
+        let Some(http_request) = log_entry.http_request.as_ref() else {
+            return Ok(None);
+        };
 
-        let http_request = log_entry.http_request.unwrap();
-        let path_result = normalize_path(
+        // Request logs do not include the content type, gRPC calls are told apart by their path.
+        let url = Url::parse(&http_request.request_url)?;
+        if let Some(operation) = grpc_operation(&http_request.request_method, url.path(), None) {
+            return Ok(Some(operation));
+        }
+
+        let path = normalize_path(
             http_request.request_url.as_str(),
             self.path_normalize_regexes.clone()
+        )?;
+
+        Ok(
+            Some(Operation::Http {
+                method: http_request.request_method.clone(),
+                path,
+            })
+        )
+    }
+
+    /// Status, latency and sizes of a completed request, application logs rarely have them.
+    fn get_request_sample(log_entry: &LogEntry) -> Option<RequestSample> {
+        let http_request = log_entry.http_request.as_ref().filter(|http_request| http_request.status > 0)?;
+
+        Some(RequestSample {
+            timestamp: log_entry.timestamp.as_ref().map(|timestamp| timestamp.seconds()),
+            status: u16::try_from(http_request.status).unwrap_or_default(),
+            latency_ms: http_request.latency
+                .as_ref()
+                .map(|latency| (latency.seconds() as f64) * 1000.0 + (latency.nanos() as f64) / 1e6),
+            request_bytes: u64::try_from(http_request.request_size).unwrap_or_default(),
+            response_bytes: u64::try_from(http_request.response_size).unwrap_or_default(),
+        })
+    }
+
+    /// Accounts for a request along an edge, or only links them when the figures are unknown.
+    fn add_request(
+        &mut self,
+        invoker: ServiceName,
+        target: ServiceName,
+        operation: Operation,
+        sample: Option<&RequestSample>
+    ) {
+        match sample {
+            Some(sample) =>
+                self.service_node_graph.record_invocation_request(
+                    invoker,
+                    target,
+                    operation,
+                    sample,
+                    &self.series_options
+                ),
+            None => self.service_node_graph.add_target_to_service(invoker, target, operation),
+        }
+    }
+
+    /// Page of one of our frontends that made the request, e.g. `shop.example.com/products/{products_id}`.
+    fn get_page(&self, log_entry: &LogEntry) -> Option<ServiceName> {
+        let referer = &log_entry.http_request.as_ref()?.referer;
+        if self.frontend_hosts.is_empty() || referer.is_empty() {
+            return None;
+        }
+
+        let url = Url::parse(referer).ok()?;
+        let host = url.host_str().filter(|host| self.frontend_hosts.contains(*host))?;
+        let path = normalize_path(referer, self.path_normalize_regexes.clone()).ok()?;
+
+        Some(format!("{}{}", host, path))
+    }
+
+    fn add_page_request(&mut self, log_entry: &LogEntry, service_name: &ServiceName, operation: &Operation) {
+        let Some(page) = self.get_page(log_entry) else {
+            return;
+        };
+
+        self.service_node_graph.add_node(page.clone(), ServiceKind::Page);
+        let sample = Self::get_request_sample(log_entry);
+        self.add_request(page, service_name.clone(), operation.clone(), sample.as_ref());
+    }
+
+    /// Load balancer requests create an entrypoint node, named after the requested host
+    /// and the URL map, which invokes the backend service the request was routed to.
+    fn process_load_balancer_log_entry(&mut self, log_entry: LogEntry) {
+        let (Some(resource), Some(http_request)) = (
+            log_entry.resource.as_ref(),
+            log_entry.http_request.as_ref(),
+        ) else {
+            return;
+        };
+
+        let host = match Url::parse(&http_request.request_url) {
+            Result::Ok(url) => url.host_str().unwrap_or_default().to_string(),
+            Err(e) => {
+                error!("Unable to process GCP load balancer log entry with malformed URL: {}", e);
+                return;
+            }
+        };
+
+        let entrypoint = match resource.labels.get("url_map_name").filter(|name| !name.is_empty()) {
+            Some(url_map_name) => format!("{} ({})", host, url_map_name),
+            None => host,
+        };
+        self.service_node_graph.add_node(entrypoint.clone(), ServiceKind::Entrypoint);
+
+        // Requests that were redirected or rejected by the load balancer itself have no backend.
+        let Some(backend_service_name) = resource.labels
+            .get("backend_service_name")
+            .filter(|name| !name.is_empty()) else {
+            return;
+        };
+
+        let service_name = self.backend_services
+            .get(backend_service_name)
+            .cloned()
+            .unwrap_or(backend_service_name.clone());
+        let service_name = self.get_service_name(&log_entry, service_name);
+
+        let operation = match self.get_http_operation(&log_entry) {
+            Result::Ok(Some(operation)) => operation,
+            _ => {
+                return;
+            }
+        };
+
+        // Backends log the requests they serve, along with the page that referred them,
+        // only the edge is accounted for here.
+        self.service_node_graph.add_operation_to_service(service_name.clone(), operation.clone());
+        let sample = Self::get_request_sample(&log_entry);
+        self.add_request(entrypoint, service_name, operation, sample.as_ref());
+    }
+
+    fn add_datastore_access(
+        &mut self,
+        service_name: ServiceName,
+        datastore_name: ServiceName,
+        operation: Operation
+    ) {
+        self.service_node_graph.add_node(datastore_name.clone(), ServiceKind::Datastore);
+        self.service_node_graph.add_operation_to_service(datastore_name.clone(), operation.clone());
+        self.service_node_graph.add_target_to_service(service_name, datastore_name, operation);
+    }
+
+    pub fn process_log(&mut self, log_entry: LogEntry) {
+        let Some(resource_type) = log_entry.resource.as_ref().map(|resource| resource.r#type.clone()) else {
+            return;
+        };
+
+        self.service_node_graph.set_observed_at(
+            log_entry.timestamp.as_ref().map(|timestamp| timestamp.seconds())
         );
 
-        // If there was a malformed URL, we simply stop and move on.
-        if path_result.is_err() {
-            error!(
-                "Unable to process GCP log entry with malformed URL: {}",
-                path_result.unwrap_err()
-            );
+        if resource_type == HTTP_LOAD_BALANCER_RESOURCE_TYPE {
+            self.process_load_balancer_log_entry(log_entry);
+            return;
+        }
+
+        if let Some((account, datastore_name, operation)) = firestore_access(&log_entry) {
+            let service_name = self.get_service_name(&log_entry, account);
+            self.add_datastore_access(service_name, datastore_name, operation);
             return;
         }
 
-        let service_name = service_name_opt.unwrap();
+        if !SUPPORTED_RESOURCE_TYPES.contains(&resource_type.as_str()) {
+            return;
+        }
 
-        let operation = Operation::Http {
-            method: http_request.request_method,
-            path: path_result.unwrap(),
+        let Some(service_name) = resource_service_name(&log_entry) else {
+            warn!("Detected a {} log that did not have a service name set", resource_type);
+            return;
         };
 
-        self.service_node_graph.add_operation_to_service(service_name.clone(), operation.clone());
+        let service_name = self.get_service_name(&log_entry, service_name);
+
+        // Cloud SQL Auth Proxy sidecars log the connections opened by the workload they run in.
+        if let Some((datastore_name, operation)) = cloud_sql_proxy_connection(&log_entry) {
+            self.add_datastore_access(service_name, datastore_name, operation);
+            return;
+        }
+
+        let operation = match self.get_http_operation(&log_entry) {
+            Result::Ok(operation) => operation,
+            // If there was a malformed URL, we simply stop and move on.
+            Err(e) => {
+                error!("Unable to process GCP log entry with malformed URL: {}", e);
+                return;
+            }
+        };
+        let operation = self.graphql_fields
+            .as_ref()
+            .and_then(|graphql_fields| graphql_fields.get_operation(&log_entry))
+            .or(operation);
+
+        let sample = Self::get_request_sample(&log_entry);
+        match operation.as_ref() {
+            Some(operation) => {
+                match sample.as_ref() {
+                    Some(sample) =>
+                        self.service_node_graph.record_operation_request(
+                            service_name.clone(),
+                            operation.clone(),
+                            sample,
+                            &self.series_options
+                        ),
+                    None =>
+                        self.service_node_graph.add_operation_to_service(
+                            service_name.clone(),
+                            operation.clone()
+                        ),
+                }
+                self.add_page_request(&log_entry, &service_name, operation);
+            }
+            None => self.service_node_graph.add_service(service_name.clone()),
+        }
+
+        let delivery = message_delivery(&log_entry, &self.subscription_topics);
+        if let Some(MessageDelivery { system, destination: Some(destination) }) = delivery.as_ref() {
+            self.service_node_graph.add_consumer(service_name.clone(), system, destination);
+        }
+
+        let caller = log_entry.http_request
+            .as_ref()
+            .filter(|_| operation.is_some())
+            .and_then(|http_request| self.caller_classifier.classify(&http_request.user_agent));
 
         // --- This section associates traces to the service name
 
         let span_id = log_entry.span_id;
+        let Some((_, trace_id)) = parse_trace_path(&log_entry.trace) else {
+            // Without a trace, nothing tells whether a mapped service made the request.
+            if let (true, Some(caller), Some(operation)) = (self.classify_untraced_callers, caller, operation) {
+                self.service_node_graph.add_node(caller.clone(), ServiceKind::Client);
+                self.add_request(caller, service_name, operation, sample.as_ref());
+            }
+            return;
+        };
 
-        self.trace_map
-            .entry(log_entry.trace)
-            .and_modify(|span_to_service_operation| {
-                span_to_service_operation.insert(span_id.clone(), (
-                    service_name.clone(),
-                    operation.clone(),
-                ));
-            })
-            .or_insert(
-                HashMap::from_iter([(span_id.clone(), (service_name.clone(), operation.clone()))])
-            );
+        let span_service = SpanService {
+            service_name,
+            operation,
+            delivery,
+            caller,
+            sample,
+            timestamp: log_entry.timestamp.as_ref().map(|timestamp| timestamp.seconds()),
+        };
+        let span_services = self.trace_map.entry(trace_id).or_default();
+        match span_services.get_mut(&span_id) {
+            Some(existing) => existing.merge(span_service),
+            None => {
+                span_services.insert(span_id, span_service);
+            }
+        }
     }
 
-    pub fn process_log(&mut self, log_entry: LogEntry) {
-        if let Some(resource) = log_entry.resource.as_ref() {
-            match resource.r#type.as_str() {
-                "cloud_run_revision" => self.process_cloud_run_revision_log_entry(log_entry),
-                _ => {}
-            }
+    /// Ancestors of a span, nearest first. Stops at the first one that has not been fetched yet,
+    /// as its parent is unknown.
+    fn ancestors<'a>(spans: &'a HashMap<SpanId, Span>, span_id: &SpanId) -> Vec<&'a SpanId> {
+        let mut ancestors = vec![];
+        let mut parent_span_id = spans.get(span_id).and_then(|span| span.parent_span_id.as_ref());
+
+        // Bounded by the number of spans, in case a malformed trace contains a cycle.
+        while let Some(current_span_id) = parent_span_id && ancestors.len() < spans.len() {
+            ancestors.push(current_span_id);
+            parent_span_id = spans
+                .get(current_span_id)
+                .and_then(|span| span.parent_span_id.as_ref());
         }
+
+        ancestors
+    }
+
+    /// Whether every ancestor of a span has been fetched, up to the root span of the trace.
+    fn is_rooted(spans: &HashMap<SpanId, Span>, span_id: &SpanId) -> bool {
+        let root_span_id = Self::ancestors(spans, span_id).last().copied().unwrap_or(span_id);
+        spans.get(root_span_id).is_some_and(|span| span.parent_span_id.is_none())
     }
 
-    pub fn process_trace(&mut self, trace: Trace) {
-        let trace_string = build_trace_path(trace.project_id, trace.trace_id);
-        let trace_entry = self.trace_map.get(&trace_string);
+    /// Nearest ancestor of a span that was mapped to a service, along with the number of
+    /// unmapped spans in between, e.g. HTTP client or middleware spans.
+    fn find_invoker_span<'a>(
+        spans: &'a HashMap<SpanId, Span>,
+        span_services: &HashMap<SpanId, SpanService>,
+        span_id: &SpanId
+    ) -> Option<(&'a SpanId, usize)> {
+        Self::ancestors(spans, span_id)
+            .into_iter()
+            .enumerate()
+            .find(|(_, ancestor_span_id)| span_services.contains_key(*ancestor_span_id))
+            .map(|(skipped_hops, ancestor_span_id)| (ancestor_span_id, skipped_hops))
+    }
+
+    /// Whether a host, e.g. `users.shop.svc.cluster.local`, is one of the mapped services.
+    fn is_known_host(&self, host: &str) -> bool {
+        let short_name = host.split('.').next().unwrap_or(host);
+        let namespaced_name = format!("/{}", short_name);
+
+        self.service_node_graph.services.values().any(|service| {
+            service.kind.is_service() &&
+                (service.name == host ||
+                    service.name == short_name ||
+                    service.name.ends_with(&namespaced_name))
+        })
+    }
 
-        if trace_entry.is_none() {
+    /// Links the services of a trace, returns the links that were found.
+    pub fn process_trace(&mut self, trace: Trace) -> Vec<TraceLink> {
+        // A trace crossing projects is fetched once per project, each time with
+        // the spans of that project only. They are stitched back together here.
+        let spans = self.trace_spans.entry(trace.trace_id.clone()).or_default();
+        for span in trace.spans {
+            spans.insert(span.span_id.clone(), span);
+        }
+
+        let Some(span_services) = self.trace_map.get(&trace.trace_id) else {
             warn!("Attempted to process a trace linked to nothing.");
-            return;
+            return vec![];
+        };
+        let spans = &self.trace_spans[&trace.trace_id];
+
+        // GraphQL servers trace the operation they serve in a span of their own, under the request.
+        let mut graphql_operations: HashMap<&SpanId, Operation> = HashMap::new();
+        for (span_id, span) in spans.iter() {
+            if span.is_client() {
+                continue;
+            }
+            let Some(operation) = graphql_operation_from_attributes(&span.labels, span.name.as_deref()) else {
+                continue;
+            };
+
+            let served_span_id = if span_services.contains_key(span_id) {
+                Some(span_id)
+            } else {
+                Self::find_invoker_span(spans, span_services, span_id).map(|(served_span_id, _)| served_span_id)
+            };
+            if let Some(served_span_id) = served_span_id {
+                graphql_operations.insert(served_span_id, operation);
+            }
+        }
+
+        let mut trace_links = vec![];
+        let mut consumers = vec![];
+        let mut served_operations = vec![];
+        let mut callers = vec![];
+        // Trace links to the spans whose request figures they account for.
+        let mut measured_span_ids: HashMap<usize, SpanId> = HashMap::new();
+        for (span_id, target_service_info) in span_services.iter() {
+            // Only spans that served a request can be invoked, e.g. a job can only be an invoker.
+            let Some(operation) = target_service_info.operation.clone() else {
+                continue;
+            };
+            let operation = match graphql_operations.remove(span_id) {
+                Some(graphql_operation) => {
+                    served_operations.push((
+                        target_service_info.service_name.clone(),
+                        graphql_operation.clone(),
+                    ));
+                    graphql_operation
+                }
+                None => operation,
+            };
+
+            // Spans without any mapped ancestor are the first of their trace, or their
+            // ancestors' log entries have not been processed yet (they could be on a different page).
+            // These associations can be made later with other traces with a similar hierarchy.
+            // Root requests are shown as invoked by their caller, when it could be classified.
+            // Until every ancestor has been fetched, a mapped service may still turn out to be the invoker.
+            let Some((invoker_span_id, skipped_hops)) = Self::find_invoker_span(
+                spans,
+                span_services,
+                span_id
+            ) else {
+                if
+                    let Some(caller) = target_service_info.caller.clone() &&
+                    Self::is_rooted(spans, span_id)
+                {
+                    callers.push(caller.clone());
+                    measured_span_ids.insert(trace_links.len(), span_id.clone());
+                    trace_links.push(TraceLink {
+                        invoker: caller,
+                        target: target_service_info.service_name.clone(),
+                        operation,
+                        skipped_hops: 0,
+                    });
+                }
+                continue;
+            };
+            let invoker = span_services[invoker_span_id].service_name.clone();
+
+            // A delivered message was sent to a topic or a queue rather than to the consumer.
+            // The topic of the publishing span is preferred over the subscription of the delivery.
+            if let Some(delivery) = target_service_info.delivery.as_ref() {
+                let published = Self::ancestors(spans, span_id)
+                    .into_iter()
+                    .take(skipped_hops)
+                    .filter_map(|ancestor_span_id| spans.get(ancestor_span_id).and_then(message_call))
+                    .find(|message_call| message_call.role == MessageRole::Publish)
+                    .map(|message_call| (message_call.system, message_call.destination))
+                    .or_else(||
+                        delivery.destination
+                            .clone()
+                            .map(|destination| (delivery.system.clone(), destination))
+                    );
+
+                if let Some((system, destination)) = published {
+                    consumers.push((
+                        target_service_info.service_name.clone(),
+                        system.clone(),
+                        destination.clone(),
+                    ));
+                    trace_links.push(TraceLink {
+                        invoker,
+                        target: format!("{}:{}", system, destination),
+                        operation: Operation::Message { system, destination },
+                        skipped_hops,
+                    });
+                    continue;
+                }
+            }
+
+            measured_span_ids.insert(trace_links.len(), span_id.clone());
+            trace_links.push(TraceLink {
+                invoker,
+                target: target_service_info.service_name.clone(),
+                operation,
+                skipped_hops,
+            });
         }
 
-        let span_ids_to_service_info_map = trace_entry.unwrap();
+        // Unmapped spans leading to a mapped one called a known service.
+        let forwarding_span_ids: HashSet<&SpanId> = span_services
+            .keys()
+            .flat_map(|span_id| {
+                Self::ancestors(spans, span_id)
+                    .into_iter()
+                    .take_while(|ancestor_span_id| !span_services.contains_key(*ancestor_span_id))
+            })
+            .collect();
 
-        for span in trace.spans.iter() {
-            // If the span does not have a parent_span_id
-            // then it means that it is the first of the trace
-            // and there is nothing to link it to
-            if span.parent_span_id.is_none() {
+        // Any other call is made to a dependency that does not log its requests.
+        let mut external_services = vec![];
+        for (span_id, span) in spans.iter() {
+            if span_services.contains_key(span_id) || forwarding_span_ids.contains(span_id) {
                 continue;
             }
 
-            let parent_span_id = span.parent_span_id.clone().unwrap();
-
-            // if for whatever reason we haven't previously registered
-            // the span_id or the parent_span_id to a service, we won't be able
-            // to make an association. This isn't a big deal as these associations
-            // can be made later with other traces with a similar hierarchy.
-            // Note: if there is no match, it could be caused because some log_entries
-            // with the associated span_ids have not yet been processed (they could be on a different page).
-            // Which is important to keep the page_sizes higher to avoid such issues.
-            //
-            // It could be feasible one day to keep in memory the traces until
-            // we have a complete map.
-            if
-                !span_ids_to_service_info_map.contains_key(&span.span_id) ||
-                !span_ids_to_service_info_map.contains_key(&parent_span_id)
-            {
+            if let Some(message_call) = message_call(span) {
+                let Some((invoker_span_id, skipped_hops)) = Self::find_invoker_span(
+                    spans,
+                    span_services,
+                    span_id
+                ) else {
+                    continue;
+                };
+                let service_name = span_services[invoker_span_id].service_name.clone();
+
+                match message_call.role {
+                    MessageRole::Publish =>
+                        trace_links.push(TraceLink {
+                            invoker: service_name,
+                            target: format!("{}:{}", message_call.system, message_call.destination),
+                            operation: Operation::Message {
+                                system: message_call.system,
+                                destination: message_call.destination,
+                            },
+                            skipped_hops,
+                        }),
+                    MessageRole::Consume =>
+                        consumers.push((service_name, message_call.system, message_call.destination)),
+                }
                 continue;
             }
 
-            let invoker_service_info = span_ids_to_service_info_map
-                .get(&parent_span_id)
+            let Some((service_name, operation)) = external_call(span, &self.path_normalize_regexes) else {
+                continue;
+            };
+
+            // The request logs of the called service may simply not have been processed yet.
+            let is_request = matches!(operation, Operation::Http { .. } | Operation::Grpc { .. });
+            if is_request && self.is_known_host(&service_name) {
+                continue;
+            }
+
+            let Some((invoker_span_id, skipped_hops)) = Self::find_invoker_span(
+                spans,
+                span_services,
+                span_id
+            ) else {
+                continue;
+            };
+
+            external_services.push((service_name.clone(), operation.clone()));
+            trace_links.push(TraceLink {
+                invoker: span_services[invoker_span_id].service_name.clone(),
+                target: service_name,
+                operation,
+                // The client span itself is not a hop, it is the call.
+                skipped_hops,
+            });
+        }
+
+        // The requests of a trace are made within moments of each other.
+        let observed_at = span_services
+            .values()
+            .filter_map(|span_service| span_service.timestamp)
+            .max();
+        self.service_node_graph.set_observed_at(observed_at);
+
+        for (service_name, operation) in served_operations {
+            self.service_node_graph.add_operation_to_service(service_name, operation);
+        }
+
+        for (service_name, operation) in external_services {
+            let kind = match operation {
+                Operation::Datastore { .. } => ServiceKind::Datastore,
+                _ => ServiceKind::External,
+            };
+            self.service_node_graph.add_node(service_name.clone(), kind);
+            self.service_node_graph.add_operation_to_service(service_name, operation);
+        }
+
+        for caller in callers {
+            self.service_node_graph.add_node(caller, ServiceKind::Client);
+        }
+
+        for (index, trace_link) in trace_links.iter().enumerate() {
+            debug!(
+                "Linked {} to {} through {} unlogged spans",
+                trace_link.invoker,
+                trace_link.target,
+                trace_link.skipped_hops
+            );
+            match &trace_link.operation {
+                Operation::Message { system, destination } =>
+                    self.service_node_graph.add_publisher(
+                        trace_link.invoker.clone(),
+                        system,
+                        destination
+                    ),
+                operation => {
+                    // A trace fetched again, e.g. from another project, has had its figures accounted for.
+                    let sample = measured_span_ids
+                        .get(&index)
+                        .and_then(|span_id| self.trace_map.get_mut(&trace.trace_id)?.get_mut(span_id))
+                        .and_then(|span_service| span_service.sample.take());
+                    self.add_request(
+                        trace_link.invoker.clone(),
+                        trace_link.target.clone(),
+                        operation.clone(),
+                        sample.as_ref()
+                    );
+                    self.service_node_graph.record_skipped_hops(
+                        trace_link.invoker.clone(),
+                        &trace_link.target,
+                        operation,
+                        trace_link.skipped_hops
+                    );
+                }
+            }
+        }
+
+        for (consumer, system, destination) in consumers {
+            self.service_node_graph.add_consumer(consumer, &system, &destination);
+        }
+
+        trace_links
+    }
+
+    /// Forgets the traces whose newest log entry is older than the given unix time.
+    /// A trace is kept after being processed, as it may be fetched again from
+    /// another project or linked to log entries that arrive later.
+    pub fn prune_traces(&mut self, oldest: i64) {
+        self.trace_map.retain(|_, span_services| {
+            span_services
+                .values()
+                .filter_map(|span_service| span_service.timestamp)
+                .max()
+                .is_some_and(|newest| newest >= oldest)
+        });
+
+        let trace_map = &self.trace_map;
+        self.trace_spans.retain(|trace_id, _| trace_map.contains_key(trace_id));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use google_cloud_logging_v2::model::LogEntry;
+    use google_cloud_wkt::{ Duration, Timestamp };
+    use logs_to_graph::{
+        normalize::get_default_path_normalize_regexes,
+        service_node_graph::ServiceKind,
+    };
+    use serde_json::json;
+
+    use crate::{ gcp_processor::GcpProcessor, trace::{ Span, Trace } };
+
+    fn log_entry(project_id: &str, service_name: &str, url: &str, span_id: &str) -> LogEntry {
+        serde_json
+            ::from_value(
+                json!({
+                "logName": format!("projects/{}/logs/run.googleapis.com%2Frequests", project_id),
+                "resource": { "type": "cloud_run_revision", "labels": { "service_name": service_name } },
+                "httpRequest": { "requestMethod": "GET", "requestUrl": url },
+                "trace": format!("projects/{}/traces/abc", project_id),
+                "spanId": span_id,
+            })
+            )
+            .unwrap()
+    }
+
+    fn span(span_id: &str, parent_span_id: Option<&str>) -> Span {
+        Span {
+            span_id: span_id.into(),
+            parent_span_id: parent_span_id.map(String::from),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn should_stitch_a_trace_crossing_projects() {
+        let mut gcp_processor = GcpProcessor::new(
+            get_default_path_normalize_regexes()
+        ).with_namespaced_services();
+
+        gcp_processor.process_log(log_entry("front", "graphql-service", "https://graphql/", "1"));
+        gcp_processor.process_log(log_entry("back", "users-service", "https://users/users/1", "2"));
+
+        // Each project only returns its own spans.
+        gcp_processor.process_trace(Trace {
+            project_id: "front".into(),
+            trace_id: "abc".into(),
+            spans: vec![span("1", None)],
+        });
+        gcp_processor.process_trace(Trace {
+            project_id: "back".into(),
+            trace_id: "abc".into(),
+            spans: vec![span("2", Some("1"))],
+        });
+
+        let graph = gcp_processor.service_node_graph;
+        assert_eq!(graph.services["front/graphql-service"].invokes["back/users-service"].len(), 1);
+    }
+
+    #[test]
+    fn should_link_services_through_unlogged_spans() {
+        let mut gcp_processor = GcpProcessor::new(get_default_path_normalize_regexes());
+
+        gcp_processor.process_log(log_entry("my-project", "graphql-service", "https://graphql/", "1"));
+        gcp_processor.process_log(
+            log_entry("my-project", "users-service", "https://users/users/1", "4")
+        );
+
+        // The HTTP client and middleware spans in between have no log entries.
+        let trace_links = gcp_processor.process_trace(Trace {
+            project_id: "my-project".into(),
+            trace_id: "abc".into(),
+            spans: vec![span("1", None), span("2", Some("1")), span("3", Some("2")), span("4", Some("3"))],
+        });
+
+        assert_eq!(trace_links.len(), 1);
+        assert_eq!(trace_links[0].invoker, "graphql-service");
+        assert_eq!(trace_links[0].target, "users-service");
+        assert_eq!(trace_links[0].skipped_hops, 2);
+        let graph = gcp_processor.service_node_graph;
+        let graphql_service = &graph.services["graphql-service"];
+        assert_eq!(graphql_service.invokes["users-service"].len(), 1);
+        assert_eq!(graphql_service.invokes_skipped_hops.values().collect::<Vec<_>>(), vec![&2]);
+    }
+
+    #[test]
+    fn should_forget_traces_logged_before_the_retention() {
+        let mut gcp_processor = GcpProcessor::new(get_default_path_normalize_regexes());
+
+        let logged = |trace_id: &str, seconds: i64| {
+            let mut log_entry = log_entry("my-project", "users-service", "https://users/users/1", "1");
+            log_entry.trace = format!("projects/my-project/traces/{}", trace_id);
+            log_entry.timestamp = Some(Timestamp::clamp(seconds, 0));
+            log_entry
+        };
+        gcp_processor.process_log(logged("old", 100));
+        gcp_processor.process_log(logged("recent", 200));
+        gcp_processor.process_trace(Trace {
+            project_id: "my-project".into(),
+            trace_id: "old".into(),
+            spans: vec![span("1", None)],
+        });
+
+        gcp_processor.prune_traces(150);
+
+        assert_eq!(gcp_processor.trace_map.keys().collect::<Vec<_>>(), vec!["recent"]);
+        assert!(gcp_processor.trace_spans.is_empty());
+    }
+
+    #[test]
+    fn should_link_root_requests_to_their_caller() {
+        let mut gcp_processor = GcpProcessor::new(get_default_path_normalize_regexes());
+
+        let with_user_agent = |mut log_entry: LogEntry, user_agent: &str| {
+            log_entry.http_request.as_mut().unwrap().user_agent = user_agent.into();
+            log_entry
+        };
+        gcp_processor.process_log(
+            with_user_agent(
+                log_entry("my-project", "graphql-service", "https://graphql/", "1"),
+                "Mozilla/5.0 (X11; Linux x86_64) Firefox/131.0"
+            )
+        );
+        // Made by a mapped service, the user agent of the request does not matter.
+        gcp_processor.process_log(
+            with_user_agent(
+                log_entry("my-project", "users-service", "https://users/users/1", "2"),
+                "curl/8.4.0"
+            )
+        );
+
+        let trace_links = gcp_processor.process_trace(Trace {
+            project_id: "my-project".into(),
+            trace_id: "abc".into(),
+            spans: vec![span("1", None), span("2", Some("1"))],
+        });
+
+        assert_eq!(trace_links.len(), 2);
+        let graph = gcp_processor.service_node_graph;
+        assert_eq!(graph.services["browser"].kind, ServiceKind::Client);
+        assert_eq!(graph.services["browser"].invokes["graphql-service"].len(), 1);
+        assert!(!graph.services.contains_key("curl"));
+    }
+
+    #[test]
+    fn should_wait_for_the_whole_ancestry_before_linking_a_caller() {
+        let mut gcp_processor = GcpProcessor::new(get_default_path_normalize_regexes());
+
+        let mut users_log_entry = log_entry("my-project", "users-service", "https://users/users/1", "2");
+        users_log_entry.http_request.as_mut().unwrap().user_agent = "curl/8.4.0".into();
+        gcp_processor.process_log(users_log_entry);
+
+        // The parent span comes from another project that has not been fetched yet.
+        let trace_links = gcp_processor.process_trace(Trace {
+            project_id: "my-project".into(),
+            trace_id: "abc".into(),
+            spans: vec![span("2", Some("1"))],
+        });
+        assert!(trace_links.is_empty());
+
+        gcp_processor.process_log(log_entry("front", "graphql-service", "https://graphql/", "1"));
+        let trace_links = gcp_processor.process_trace(Trace {
+            project_id: "front".into(),
+            trace_id: "abc".into(),
+            spans: vec![span("1", None)],
+        });
+
+        let graph = gcp_processor.service_node_graph;
+        assert!(trace_links.iter().all(|trace_link| trace_link.invoker == "graphql-service"));
+        assert!(!graph.services.contains_key("curl"));
+        assert_eq!(graph.services["graphql-service"].invokes["users-service"].len(), 1);
+    }
+
+    #[test]
+    fn should_only_name_the_callers_of_untraced_requests_when_asked_to() {
+        let untraced = || {
+            let mut log_entry = log_entry("my-project", "users-service", "https://users/users/1", "1");
+            log_entry.trace = String::new();
+            log_entry.http_request.as_mut().unwrap().user_agent = "curl/8.4.0".into();
+            log_entry
+        };
+
+        let mut gcp_processor = GcpProcessor::new(get_default_path_normalize_regexes());
+        gcp_processor.process_log(untraced());
+        assert!(!gcp_processor.service_node_graph.services.contains_key("curl"));
+
+        let mut gcp_processor = GcpProcessor::new(
+            get_default_path_normalize_regexes()
+        ).with_untraced_callers(true);
+        gcp_processor.process_log(untraced());
+        assert_eq!(gcp_processor.service_node_graph.services["curl"].invokes["users-service"].len(), 1);
+    }
+
+    #[test]
+    fn should_map_frontend_pages_to_the_operations_they_trigger() {
+        let mut gcp_processor = GcpProcessor::new(
+            get_default_path_normalize_regexes()
+        ).with_frontend_hosts(vec!["shop.example.com".into()]);
+
+        let with_referer = |mut log_entry: LogEntry, referer: &str| {
+            log_entry.http_request.as_mut().unwrap().referer = referer.into();
+            log_entry
+        };
+        for product_id in ["42", "43"] {
+            gcp_processor.process_log(
+                with_referer(
+                    log_entry("my-project", "products-service", "https://products/products/1", "1"),
+                    &format!("https://shop.example.com/products/{}?ref=home", product_id)
+                )
+            );
+        }
+        // Referers from other sites are not ours to map.
+        gcp_processor.process_log(
+            with_referer(
+                log_entry("my-project", "products-service", "https://products/products/1", "2"),
+                "https://www.google.com/"
+            )
+        );
+
+        let graph = gcp_processor.service_node_graph;
+        let page = &graph.services["shop.example.com/products/{products_id}"];
+        assert_eq!(page.kind, ServiceKind::Page);
+        assert_eq!(page.invokes["products-service"].len(), 1);
+        assert_eq!(graph.services.len(), 2);
+    }
+
+    #[test]
+    fn should_count_a_page_request_once_behind_a_load_balancer() {
+        let mut gcp_processor = GcpProcessor::new(get_default_path_normalize_regexes())
+            .with_frontend_hosts(vec!["shop.example.com".into()])
+            .with_backend_services(HashMap::from([("products-backend".into(), "products-service".into())]));
+
+        let http_request =
+            json!({
+            "requestMethod": "GET",
+            "requestUrl": "https://api.example.com/products/1",
+            "status": 200,
+            "referer": "https://shop.example.com/products/42",
+        });
+        gcp_processor.process_log(
+            serde_json
+                ::from_value(
+                    json!({
+                    "resource": { "type": "http_load_balancer", "labels": { "backend_service_name": "products-backend" } },
+                    "httpRequest": http_request,
+                })
+                )
                 .unwrap()
-                .clone();
-            let target_service_info = span_ids_to_service_info_map
-                .get(&span.span_id)
+        );
+        gcp_processor.process_log(
+            serde_json
+                ::from_value(
+                    json!({
+                    "resource": { "type": "cloud_run_revision", "labels": { "service_name": "products-service" } },
+                    "httpRequest": http_request,
+                    "trace": "projects/my-project/traces/abc",
+                    "spanId": "1",
+                })
+                )
                 .unwrap()
-                .clone();
+        );
 
-            self.service_node_graph.add_target_to_service(
-                invoker_service_info.0,
-                target_service_info.0,
-                target_service_info.1
-            );
+        let graph = gcp_processor.service_node_graph;
+        let page = &graph.services["shop.example.com/products/{products_id}"];
+        assert_eq!(page.invokes["products-service"].len(), 1);
+        assert_eq!(page.invoke_metrics.values().map(|metrics| metrics.count).sum::<u64>(), 1);
+    }
 
-            // TODO: once we've successfully processed a trace
-            // we should remove it from trace_id_to_span_id_to_service_info.
-            // Since technically it can infinitely increase.
+    #[test]
+    fn should_account_for_request_figures_once() {
+        let mut gcp_processor = GcpProcessor::new(get_default_path_normalize_regexes());
+
+        let completed = |mut log_entry: LogEntry, status: i32| {
+            let http_request = log_entry.http_request.as_mut().unwrap();
+            http_request.status = status;
+            http_request.latency = Some(Duration::clamp(0, 40_000_000));
+            http_request.response_size = 512;
+            log_entry.timestamp = Some(Timestamp::clamp(7300, 0));
+            log_entry
+        };
+        gcp_processor.process_log(
+            completed(log_entry("my-project", "graphql-service", "https://graphql/", "1"), 200)
+        );
+        gcp_processor.process_log(
+            completed(log_entry("my-project", "users-service", "https://users/users/1", "2"), 503)
+        );
+
+        for _ in 0..2 {
+            gcp_processor.process_trace(Trace {
+                project_id: "my-project".into(),
+                trace_id: "abc".into(),
+                spans: vec![span("1", None), span("2", Some("1"))],
+            });
         }
+
+        let graph = gcp_processor.service_node_graph;
+        let users_service = &graph.services["users-service"];
+        let exposed = users_service.operations.keys().next().unwrap();
+        assert_eq!(users_service.metrics[exposed].count, 1);
+        assert_eq!(users_service.metrics[exposed].get_latency_percentile(0.5), Some(40.0));
+        let invoke_metrics = &graph.services["graphql-service"].invoke_metrics[exposed];
+        assert_eq!(invoke_metrics.count, 1);
+        assert_eq!(invoke_metrics.status_classes["5xx"], 1);
+        assert_eq!(invoke_metrics.response_bytes, 512);
+        // Counted in the hour the request was made.
+        assert_eq!(invoke_metrics.series[&7200].error_count, 1);
+        assert_eq!(graph.services["graphql-service"].invokes_seen[exposed].last_seen, 7300);
+    }
+
+    #[test]
+    fn should_discover_external_dependencies_from_client_spans() {
+        let mut gcp_processor = GcpProcessor::new(get_default_path_normalize_regexes());
+
+        gcp_processor.process_log(log_entry("my-project", "graphql-service", "https://graphql/", "1"));
+        gcp_processor.process_log(
+            log_entry("my-project", "users-service", "https://users/users/1", "5")
+        );
+
+        let client_span = |span_id: &str, labels: &[(&str, &str)]| Span {
+            span_id: span_id.into(),
+            parent_span_id: Some("1".into()),
+            kind: Some("RPC_CLIENT".into()),
+            labels: labels
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+            ..Default::default()
+        };
+
+        let trace_links = gcp_processor.process_trace(Trace {
+            project_id: "my-project".into(),
+            trace_id: "abc".into(),
+            spans: vec![
+                span("1", None),
+                client_span("2", &[("/http/url", "https://api.stripe.com/v1/customers/42"), ("/http/method", "GET")]),
+                client_span("3", &[("db.system", "redis"), ("db.operation", "get")]),
+                client_span("4", &[("/http/url", "https://users-service/users/1"), ("/http/method", "GET")]),
+                span("5", Some("4"))
+            ],
+        });
+
+        assert_eq!(trace_links.len(), 3);
+        let graph = gcp_processor.service_node_graph;
+        assert_eq!(graph.services["api.stripe.com"].kind, ServiceKind::External);
+        assert_eq!(graph.services["redis:default"].kind, ServiceKind::Datastore);
+        assert_eq!(graph.services["redis:default"].operations.len(), 1);
+        assert_eq!(graph.services["graphql-service"].invokes.len(), 3);
+        assert_eq!(graph.services["users-service"].kind, ServiceKind::Service);
+    }
+
+    #[test]
+    fn should_link_publishers_and_consumers_through_topics() {
+        let mut gcp_processor = GcpProcessor::new(get_default_path_normalize_regexes());
+
+        gcp_processor.process_log(log_entry("my-project", "orders-service", "https://orders/orders", "1"));
+        gcp_processor.process_log(
+            serde_json
+                ::from_value(
+                    json!({
+                    "resource": { "type": "cloud_run_revision", "labels": { "service_name": "emails-service" } },
+                    "httpRequest": {
+                        "requestMethod": "POST",
+                        "requestUrl": "https://emails/push",
+                        "userAgent": "APIs-Google; (+https://developers.google.com/webmasters/APIs-Google.html)",
+                    },
+                    "trace": "projects/my-project/traces/abc",
+                    "spanId": "3",
+                })
+                )
+                .unwrap()
+        );
+
+        let publish_span = Span {
+            span_id: "2".into(),
+            parent_span_id: Some("1".into()),
+            labels: HashMap::from([
+                ("messaging.system".into(), "gcp_pubsub".into()),
+                ("messaging.destination.name".into(), "orders".into()),
+                ("messaging.operation.type".into(), "create".into()),
+            ]),
+            ..Default::default()
+        };
+        gcp_processor.process_trace(Trace {
+            project_id: "my-project".into(),
+            trace_id: "abc".into(),
+            spans: vec![span("1", None), publish_span, span("3", Some("2"))],
+        });
+
+        let graph = gcp_processor.service_node_graph;
+        assert_eq!(graph.services["pubsub:orders"].kind, ServiceKind::Destination);
+        assert!(graph.services["orders-service"].invokes.contains_key("pubsub:orders"));
+        assert!(!graph.services["orders-service"].invokes.contains_key("emails-service"));
+        assert!(graph.services["emails-service"].consumes.contains_key("pubsub:orders"));
+    }
+
+    #[test]
+    fn should_split_graphql_calls_into_their_operations() {
+        let mut gcp_processor = GcpProcessor::new(get_default_path_normalize_regexes());
+
+        gcp_processor.process_log(log_entry("my-project", "web-app", "https://web/", "1"));
+        gcp_processor.process_log(
+            log_entry("my-project", "graphql-service", "https://graphql/graphql", "2")
+        );
+
+        let graphql_span = Span {
+            span_id: "3".into(),
+            parent_span_id: Some("2".into()),
+            name: Some("mutation login".into()),
+            labels: HashMap::from([("graphql.operation.type".into(), "mutation".into())]),
+            ..Default::default()
+        };
+        gcp_processor.process_trace(Trace {
+            project_id: "my-project".into(),
+            trace_id: "abc".into(),
+            spans: vec![span("1", None), span("2", Some("1")), graphql_span],
+        });
+
+        let graph = gcp_processor.service_node_graph;
+        let login = graph.services["graphql-service"].operations
+            .iter()
+            .find(|(_, operation)| operation.get_label() == "Mutation login")
+            .map(|(id, _)| id.clone())
+            .unwrap();
+        assert!(graph.services["web-app"].invokes["graphql-service"].contains(&login));
+        assert_eq!(graph.services["web-app"].invokes["graphql-service"].len(), 1);
+    }
+
+    #[test]
+    fn should_link_a_job_to_a_gke_workload() {
+        let mut gcp_processor = GcpProcessor::new(get_default_path_normalize_regexes());
+
+        gcp_processor.process_log(
+            serde_json
+                ::from_value(
+                    json!({
+                    "resource": { "type": "cloud_run_job", "labels": { "job_name": "nightly-export" } },
+                    "trace": "projects/my-project/traces/abc",
+                    "spanId": "1",
+                })
+                )
+                .unwrap()
+        );
+        gcp_processor.process_log(
+            serde_json
+                ::from_value(
+                    json!({
+                    "resource": { "type": "k8s_container", "labels": { "namespace_name": "shop", "pod_name": "users-5f6d7c8b9-x2x8k" } },
+                    "httpRequest": { "requestMethod": "GET", "requestUrl": "https://users/users/1" },
+                    "trace": "projects/my-project/traces/abc",
+                    "spanId": "2",
+                })
+                )
+                .unwrap()
+        );
+        gcp_processor.process_trace(Trace {
+            project_id: "my-project".into(),
+            trace_id: "abc".into(),
+            spans: vec![span("1", None), span("2", Some("1"))],
+        });
+
+        let graph = gcp_processor.service_node_graph;
+        assert!(graph.services["nightly-export"].operations.is_empty());
+        assert_eq!(graph.services["shop/users"].operations.len(), 1);
+        assert_eq!(graph.services["nightly-export"].invokes["shop/users"].len(), 1);
+    }
+
+    #[test]
+    fn should_link_load_balancer_entrypoints_to_their_backend() {
+        let mut gcp_processor = GcpProcessor::new(
+            get_default_path_normalize_regexes()
+        ).with_backend_services(HashMap::from([("users-backend".into(), "users-service".into())]));
+
+        gcp_processor.process_log(
+            serde_json
+                ::from_value(
+                    json!({
+                    "resource": { "type": "http_load_balancer", "labels": {
+                        "url_map_name": "public-lb",
+                        "backend_service_name": "users-backend",
+                        "forwarding_rule_name": "public-https",
+                    } },
+                    "httpRequest": { "requestMethod": "GET", "requestUrl": "https://api.example.com/users/1" },
+                })
+                )
+                .unwrap()
+        );
+
+        let graph = gcp_processor.service_node_graph;
+        let entrypoint = &graph.services["api.example.com (public-lb)"];
+        assert_eq!(entrypoint.kind, ServiceKind::Entrypoint);
+        assert_eq!(entrypoint.invokes["users-service"].len(), 1);
+        assert_eq!(graph.services["users-service"].operations.len(), 1);
     }
 }
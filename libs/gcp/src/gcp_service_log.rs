@@ -1,181 +1,418 @@
-use std::{ collections::{ HashMap, HashSet }, sync::Arc };
+use std::{ collections::{ BTreeSet, HashSet }, path::PathBuf, sync::Arc };
 
 use async_trait::async_trait;
 use common::{ marc, types::MArc };
-use google_cloud_logging_v2::{ client::LoggingServiceV2 };
-use anyhow::{ Ok, Result, bail, Error };
+use anyhow::{ Context, Ok, Result, bail };
 use google_cloud_wkt::Timestamp;
-use logs_to_graph::{ service_logs::ServiceLogs, service_node_graph::{ self, ServiceNodeGraph } };
+use logs_to_graph::{
+    normalize::build_path_normalize_regexes,
+    service_logs::ServiceLogs,
+    service_node_graph::ServiceNodeGraph,
+};
 use regex::Regex;
-use tokio::{ sync::{ Semaphore, mpsc::{ Sender, channel } }, time::{ Duration, sleep } };
+use tokio::{
+    sync::{ Semaphore, mpsc::{ Sender, channel }, oneshot },
+    time::{ Duration, Instant, sleep },
+};
 use tracing::{ debug, error, info, warn };
 
 use crate::{
-    consts::DEFAULT_LOG_FILTER,
+    checkpoint::{ Checkpoint, CheckpointStore, FileCheckpointStore },
+    cursor::LogCursor,
     gcp_processor::GcpProcessor,
-    normalize::{ get_default_path_normalize_regexes },
-    trace::TracesAPI,
+    log_source::{ CloudLoggingSource, LogEntriesRequest, LogEntrySource },
+    options::GcpProcessingOptions,
+    resource::build_default_log_filter,
+    recording::{
+        RecordedLogEntrySource,
+        RecordedTraceSource,
+        RecordingLogEntrySource,
+        RecordingManifest,
+        RecordingTraceSource,
+        read_manifest,
+        write_manifest,
+    },
+    trace::{ TraceSource, TracesAPI },
 };
 
+/// Delay between two consecutive pages, keeps us well under the Logging API read quota.
+const PAGE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Least time between two checkpoint saves, the graph they include grows with its figures.
+const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How far back to look when neither a lookback nor a start of window is configured.
+const DEFAULT_LOOKBACK: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+pub struct GCPServiceLogsOptions {
+    /// Projects queried together, services are prefixed with their project when there are several.
+    pub project_ids: Vec<String>,
+    pub page_size: i32,
+    pub log_filter: Option<String>,
+    pub custom_path_normalize_patterns: Vec<String>,
+    /// When set, keeps polling for new log entries at this interval
+    /// once the last page has been reached instead of stopping.
+    /// Ignored when the window has an end (`to`).
+    pub follow: Option<Duration>,
+    /// How far back from now to start reading, when `from` is not set.
+    pub lookback: Option<Duration>,
+    /// RFC 3339 start of the window (inclusive).
+    pub from: Option<String>,
+    /// RFC 3339 end of the window (exclusive).
+    pub to: Option<String>,
+    /// Where to persist progress, so that a restart resumes where it stopped.
+    pub checkpoint_path: Option<PathBuf>,
+    /// Captures every Logging and Trace API response into this directory.
+    pub record_dir: Option<PathBuf>,
+    pub processing: GcpProcessingOptions,
+}
+
 pub struct GCPServiceLogs {
-    project_id: String,
+    project_ids: Vec<String>,
     log_filter: String,
-    client: Arc<LoggingServiceV2>,
+    log_source: Arc<dyn LogEntrySource>,
     page_size: i32,
     path_normalize_regexes: Vec<(String, Vec<Regex>)>,
-    traces_api: Arc<TracesAPI>,
+    trace_source: Arc<dyn TraceSource>,
+    follow: Option<Duration>,
+    checkpoint_store: Option<Arc<dyn CheckpointStore>>,
+    page_interval: Duration,
+    /// Waits for the traces of a page to be processed before reading the next page,
+    /// so that a replay always maps the same graphs in the same order.
+    wait_for_traces: bool,
+    processing: GcpProcessingOptions,
 }
 
 impl GCPServiceLogs {
-    pub async fn new(
-        project_id: String,
-        page_size: i32,
-        log_filter: Option<String>,
-        custom_path_normalize_patterns: Vec<String>
-    ) -> Result<Self> {
-        if project_id.is_empty() {
-            bail!("Expected a non empty project_id");
-        }
+    pub async fn new(options: GCPServiceLogsOptions) -> Result<Self> {
+        let log_source = CloudLoggingSource::new().await?;
+        let traces_api = TracesAPI::new().await?;
+
+        let Some(record_dir) = options.record_dir.clone() else {
+            return Self::with_sources(options, Arc::new(log_source), Arc::new(traces_api));
+        };
+
+        info!("Recording Logging and Trace API responses in {}", record_dir.display());
+
+        let project_ids = options.project_ids.clone();
+        let service_logs = Self::with_sources(
+            options,
+            Arc::new(RecordingLogEntrySource::new(Box::new(log_source), record_dir.clone())?),
+            Arc::new(RecordingTraceSource::new(Box::new(traces_api), record_dir.clone()))
+        )?;
+
+        write_manifest(&record_dir, &(RecordingManifest {
+            project_ids,
+            log_filter: service_logs.log_filter.clone(),
+            processing: service_logs.processing.clone(),
+        }))?;
 
-        let mut path_normalize_regexes: Vec<
-            (String, Vec<Regex>)
-        > = get_default_path_normalize_regexes();
+        Ok(service_logs)
+    }
 
-        if custom_path_normalize_patterns.len() > 0 {
-            let custom_path_regexes = custom_path_normalize_patterns
-                .iter()
-                .map(|pattern| Regex::new(pattern).unwrap())
-                .collect();
+    /// Replays a directory captured with `record_dir`, through the same pipeline.
+    pub fn from_recording(dir: PathBuf, custom_path_normalize_patterns: Vec<String>) -> Result<Self> {
+        let manifest = read_manifest(&dir)?;
+
+        let mut service_logs = Self::with_sources(
+            GCPServiceLogsOptions {
+                project_ids: manifest.project_ids,
+                page_size: 0,
+                log_filter: None,
+                custom_path_normalize_patterns,
+                follow: None,
+                lookback: None,
+                from: None,
+                to: None,
+                checkpoint_path: None,
+                record_dir: None,
+                processing: manifest.processing,
+            },
+            Arc::new(RecordedLogEntrySource::new(&dir)?),
+            Arc::new(RecordedTraceSource::new(&dir))
+        )?;
+        // Nothing to rate limit when reading from disk.
+        service_logs.page_interval = Duration::ZERO;
+        service_logs.wait_for_traces = true;
+
+        Ok(service_logs)
+    }
 
-            path_normalize_regexes.push(("custom_id".into(), custom_path_regexes));
+    /// Builds the service logs on top of arbitrary log and trace sources.
+    pub fn with_sources(
+        options: GCPServiceLogsOptions,
+        log_source: Arc<dyn LogEntrySource>,
+        trace_source: Arc<dyn TraceSource>
+    ) -> Result<Self> {
+        if options.project_ids.is_empty() || options.project_ids.iter().any(String::is_empty) {
+            bail!("Expected at least one project_id, none of them empty");
         }
 
-        let client = LoggingServiceV2::builder().build().await?; // Uses ADC by default
-        let traces_api = TracesAPI::new().await?;
+        let path_normalize_regexes = build_path_normalize_regexes(
+            &options.custom_path_normalize_patterns
+        )?;
 
-        let now = Timestamp::try_from(std::time::SystemTime::now())?;
-        let week_ago = Timestamp::clamp(now.seconds() - 7 * 24 * 60 * 60, 0);
+        options.processing.validate()?;
 
         let mut internal_log_filters: Vec<String> = vec![
-            DEFAULT_LOG_FILTER.to_string(),
-            format!("timestamp>=\"{}\"", String::from(week_ago))
+            build_default_log_filter(options.processing.graphql_fields.as_ref())
         ];
+        internal_log_filters.extend(
+            build_window_filters(options.lookback, options.from.as_deref(), options.to.as_deref())?
+        );
 
-        if log_filter.is_some() {
-            internal_log_filters.push(log_filter.unwrap());
+        if let Some(log_filter) = options.log_filter {
+            internal_log_filters.push(log_filter);
         }
 
         let internal_log_filter = internal_log_filters.join(" AND ");
 
         Ok(Self {
-            client: Arc::new(client),
-            project_id,
+            log_source,
+            project_ids: options.project_ids,
             log_filter: internal_log_filter,
-            page_size,
+            page_size: options.page_size,
             path_normalize_regexes,
-            traces_api: Arc::new(traces_api),
+            trace_source,
+            // A window with an end can never receive new log entries.
+            follow: options.follow.filter(|_| options.to.is_none()),
+            checkpoint_store: options.checkpoint_path.map(|path| {
+                Arc::new(FileCheckpointStore::new(path)) as Arc<dyn CheckpointStore>
+            }),
+            page_interval: PAGE_INTERVAL,
+            wait_for_traces: false,
+            processing: options.processing,
         })
     }
 }
 
+/// Builds the `timestamp` filters delimiting the window of log entries to read.
+fn build_window_filters(
+    lookback: Option<Duration>,
+    from: Option<&str>,
+    to: Option<&str>
+) -> Result<Vec<String>> {
+    let from = match from {
+        Some(from) => Timestamp::try_from(from).with_context(|| format!("Invalid from: {}", from))?,
+        None => {
+            let now = Timestamp::try_from(std::time::SystemTime::now())?;
+            let lookback = lookback.unwrap_or(DEFAULT_LOOKBACK);
+            Timestamp::clamp(now.seconds() - (lookback.as_secs() as i64), 0)
+        }
+    };
+
+    let mut filters = vec![format!("timestamp>=\"{}\"", String::from(from))];
+
+    if let Some(to) = to {
+        let to = Timestamp::try_from(to).with_context(|| format!("Invalid to: {}", to))?;
+
+        if to <= from {
+            bail!("Expected the end of the window to be after its start");
+        }
+
+        filters.push(format!("timestamp<\"{}\"", String::from(to)));
+    }
+
+    Ok(filters)
+}
+
+/// Appends the cursor position to the base log filter, if there is one.
+fn build_poll_filter(log_filter: &str, cursor: &LogCursor) -> String {
+    match cursor.filter() {
+        Some(cursor_filter) => format!("{} AND {}", log_filter, cursor_filter),
+        None => log_filter.to_string(),
+    }
+}
+
+/// The checkpoint being updated, saved along with the graph mapped up to its cursor.
+///
+/// Saves are throttled, a resume maps again what was mapped from the log entries
+/// after the cursor saved last, on top of the graph saved with it.
+struct CheckpointWriter {
+    checkpoint: Checkpoint,
+    store: Option<Arc<dyn CheckpointStore>>,
+    saved_at: Option<Instant>,
+}
+
+impl CheckpointWriter {
+    /// Saves the checkpoint if a store is configured, and unless it was saved less than
+    /// `CHECKPOINT_INTERVAL` ago. Failing to do so is not fatal, the next save will try again.
+    async fn save(&mut self, gcp_processor: &MArc<GcpProcessor>, throttled: bool) {
+        let Some(store) = &self.store else {
+            return;
+        };
+        if throttled && self.saved_at.is_some_and(|saved_at| saved_at.elapsed() < CHECKPOINT_INTERVAL) {
+            return;
+        }
+
+        // Taken while the checkpoint is locked, no trace can be recorded as processed meanwhile.
+        self.checkpoint.service_node_graph = gcp_processor.lock().await.service_node_graph.clone();
+        if let Err(e) = store.save(&self.checkpoint) {
+            error!("Failed to persist checkpoint: {}", e);
+        }
+        self.checkpoint.service_node_graph = ServiceNodeGraph::default();
+        self.saved_at = Some(Instant::now());
+    }
+}
+
 const CHANNEL_BUFFER_SIZE: usize = 32;
 
 #[async_trait]
 impl ServiceLogs for GCPServiceLogs {
     async fn run(&self, sender: Sender<ServiceNodeGraph>) -> Result<()> {
-        let (tx, mut rx) = channel::<Vec<String>>(CHANNEL_BUFFER_SIZE);
+        let (tx, mut rx) = channel::<(Vec<String>, Option<oneshot::Sender<()>>)>(
+            CHANNEL_BUFFER_SIZE
+        );
         let sender2 = sender.clone();
 
-        let client = Arc::clone(&self.client);
-        let traces_api = Arc::clone(&self.traces_api);
+        let log_source = Arc::clone(&self.log_source);
+        let trace_source = Arc::clone(&self.trace_source);
+
+        let mut checkpoint = match &self.checkpoint_store {
+            Some(store) => store.load()?.unwrap_or_default(),
+            None => Checkpoint::default(),
+        };
+
+        if let Some(timestamp) = checkpoint.cursor.timestamp {
+            info!("Resuming from checkpoint at {}", String::from(timestamp));
+        }
+
+        // Figures carry on from what was mapped before the restart.
+        let service_node_graph = std::mem::take(&mut checkpoint.service_node_graph);
 
-        let gcp_processor_marc = marc!(GcpProcessor {
-            service_node_graph: ServiceNodeGraph::default(),
-            path_normalize_regexes: self.path_normalize_regexes.clone(),
-            trace_map: HashMap::default(),
+        let mut cursor = checkpoint.cursor.clone();
+        let mut traces_seen: HashSet<String> = checkpoint.processed_traces.keys().cloned().collect();
+        let checkpoint_marc = marc!(CheckpointWriter {
+            checkpoint,
+            store: self.checkpoint_store.clone(),
+            saved_at: None,
         });
+        let checkpoint_marc_2 = Arc::clone(&checkpoint_marc);
+        let checkpoint_marc_3 = Arc::clone(&checkpoint_marc);
+
+        let mut gcp_processor = GcpProcessor::new(
+            self.path_normalize_regexes.clone()
+        )
+            .with_options(&self.processing)?;
+        if self.project_ids.len() > 1 {
+            gcp_processor = gcp_processor.with_namespaced_services();
+        }
+        gcp_processor.service_node_graph = service_node_graph;
+        let gcp_processor_marc = marc!(gcp_processor);
 
         // To be used in the second thread.
         let gcp_processor_marc_2 = Arc::clone(&gcp_processor_marc);
+        let gcp_processor_marc_3 = Arc::clone(&gcp_processor_marc);
 
         let page_size = self.page_size;
         let log_filter = self.log_filter.clone();
-        let project_id = self.project_id.clone();
+        let resource_names: Vec<String> = self.project_ids
+            .iter()
+            .map(|project_id| format!("projects/{}", project_id))
+            .collect();
+        let follow = self.follow;
+        let page_interval = self.page_interval;
+        let wait_for_traces = self.wait_for_traces;
 
         let t1 = tokio::spawn(async move {
             let mut page_token = String::new();
-            let project = format!("projects/{}", project_id);
+            // The filter must stay the same while paginating, it only moves
+            // forward with the cursor once a new poll starts.
+            let mut poll_filter = build_poll_filter(&log_filter, &cursor);
             loop {
-                let result = client
-                    .list_log_entries()
-                    .set_resource_names([project.clone()])
-                    .set_filter(&log_filter)
-                    .set_page_size(page_size)
-                    .set_order_by("timestamp asc")
-                    .set_page_token(&page_token)
-                    .send().await;
-
-                if result.is_err() {
-                    let e = result.unwrap_err();
-                    error!("Failed to fetch log entries: {}", e);
-                    sleep(Duration::from_secs(30)).await;
-                    continue;
-                }
-
-                let response = result.unwrap();
+                let result = log_source.list_log_entries(LogEntriesRequest {
+                    resource_names: resource_names.clone(),
+                    filter: poll_filter.clone(),
+                    page_size,
+                    page_token: page_token.clone(),
+                }).await;
+
+                let response = match result {
+                    Result::Ok(response) => response,
+                    Err(e) => {
+                        error!("Failed to fetch log entries: {}", e);
+                        sleep(page_interval).await;
+                        continue;
+                    }
+                };
 
                 page_token = response.next_page_token.clone();
 
                 debug!("Found {} results", response.entries.len());
 
+                // Locked first, the checkpoint is never saved with a graph ahead of its cursor.
+                let mut checkpoint_writer = checkpoint_marc.lock().await;
                 let mut gcp_processor = gcp_processor_marc.lock().await;
                 let mut new_traces: Vec<String> = vec![];
                 for log_entry in response.entries {
+                    if !cursor.is_new(&log_entry) {
+                        continue;
+                    }
+                    cursor.advance(&log_entry);
                     new_traces.push(log_entry.trace.clone());
                     gcp_processor.process_log(log_entry);
                 }
 
-                let send_res = sender.send(gcp_processor.service_node_graph.clone()).await;
+                let service_node_graph = gcp_processor.service_node_graph.clone();
+                drop(gcp_processor);
 
-                if send_res.is_err() {
+                // Note: traces of this page that are still being fetched when the process stops
+                // will not be retried, since the log entries that map their spans are not persisted.
+                checkpoint_writer.checkpoint.cursor = cursor.clone();
+                checkpoint_writer.save(&gcp_processor_marc, true).await;
+                drop(checkpoint_writer);
+
+                if sender.send(service_node_graph).await.is_err() {
                     error!(
                         "Failed to send service node graph from the Service Logs to the Service Graph."
                     );
                 }
 
-                if new_traces.len() > 0 {
-                    let res = tx.send(new_traces).await;
+                if !new_traces.is_empty() {
+                    let (processed_sender, processed_receiver) = oneshot::channel();
+                    let res = tx.send((new_traces, wait_for_traces.then_some(processed_sender))).await;
 
                     // TODO: Figure out what to do if this fails.
                     if res.is_err() {
                         error!("Failed to send message between threads.");
+                    } else if wait_for_traces {
+                        let _ = processed_receiver.await;
                     }
                 }
 
                 if page_token.is_empty() {
-                    info!("Next page token was empty");
-                    break;
+                    let Some(poll_interval) = follow else {
+                        info!("Next page token was empty");
+                        break;
+                    };
+
+                    debug!(
+                        "Caught up with the latest log entries, polling again in {:?}",
+                        poll_interval
+                    );
+                    sleep(poll_interval).await;
+                    poll_filter = build_poll_filter(&log_filter, &cursor);
+                    continue;
                 }
 
-                sleep(Duration::from_secs(30)).await;
+                sleep(page_interval).await;
             }
         });
 
         let t2 = tokio::spawn(async move {
-            let mut traces_seen: Vec<String> = vec![];
-
-            while let Some(new_traces) = rx.recv().await {
-                let mut iter = new_traces
+            while let Some((new_traces, processed_sender)) = rx.recv().await {
+                let iter: BTreeSet<&String> = new_traces
                     .iter()
-                    .filter(|new_trace| !traces_seen.contains(&new_trace));
+                    .filter(|new_trace| !traces_seen.contains(*new_trace))
+                    .collect();
 
                 // Ensures that not more than a 100 are concurrently being processed at once.
                 let sem = Arc::new(Semaphore::new(10));
                 let mut handles = Vec::new();
 
-                while let Some(new_trace) = iter.next() {
+                for new_trace in iter {
                     let trace = new_trace.to_string();
-                    let tmp_traces_api = Arc::clone(&traces_api);
+                    let tmp_trace_source = Arc::clone(&trace_source);
 
                     let tmp_gcp_processor_marc = Arc::clone(&gcp_processor_marc_2);
                     let permit = Arc::clone(&sem).acquire_owned().await.unwrap();
@@ -183,50 +420,306 @@ impl ServiceLogs for GCPServiceLogs {
                         tokio::spawn(async move {
                             debug!("Fetching trace information: {}", trace);
 
-                            let res = tmp_traces_api.get_trace(&trace).await;
+                            let res = tmp_trace_source.get_trace(&trace).await;
 
-                            if res.is_err() {
-                                let message = format!("Failed to get trace: {}", res.unwrap_err());
-                                warn!("{}", message);
-                                return trace;
-                            }
+                            let trace_response = match res {
+                                Result::Ok(trace_response) => trace_response,
+                                Err(e) => {
+                                    // Left unprocessed, it is fetched again if logged again.
+                                    warn!("Failed to get trace: {}", e);
+                                    return None;
+                                }
+                            };
 
                             let mut gcp_processor = tmp_gcp_processor_marc.lock().await;
 
-                            gcp_processor.process_trace(res.unwrap());
+                            let trace_links = gcp_processor.process_trace(trace_response);
+                            debug!("Found {} links in trace {}", trace_links.len(), trace);
 
                             drop(permit);
-                            trace
+                            Some(trace)
                         })
                     );
                 }
 
+                let mut checkpoint_writer = checkpoint_marc_2.lock().await;
+                let checkpoint = &mut checkpoint_writer.checkpoint;
                 for h in handles {
-                    match h.await {
-                        Result::Ok(trace) => {
-                            traces_seen.push(trace);
-                        }
-                        Err(_) => {}
+                    if let Result::Ok(Some(trace)) = h.await {
+                        traces_seen.insert(trace.clone());
+                        checkpoint.add_processed_trace(trace);
                     }
                 }
+                checkpoint.prune_processed_traces();
+                traces_seen.retain(|trace| checkpoint.processed_traces.contains_key(trace));
+                let mut gcp_processor = gcp_processor_marc_2.lock().await;
+                if let Some(oldest) = checkpoint.oldest_retained() {
+                    gcp_processor.prune_traces(oldest);
+                }
+                let service_node_graph = gcp_processor.service_node_graph.clone();
+                drop(gcp_processor);
 
-                let send_res = sender2.send(
-                    gcp_processor_marc_2.lock().await.service_node_graph.clone()
-                ).await;
+                checkpoint_writer.save(&gcp_processor_marc_2, true).await;
+                drop(checkpoint_writer);
+
+                let send_res = sender2.send(service_node_graph).await;
 
                 if send_res.is_err() {
                     error!(
                         "Failed to send service node graph from the Service Logs to the Service Graph."
                     );
                 }
+
+                if let Some(processed_sender) = processed_sender {
+                    let _ = processed_sender.send(());
+                }
             }
         });
 
         let (r1, r2) = tokio::join!(t1, t2);
 
+        // Whatever the throttle held back.
+        checkpoint_marc_3.lock().await.save(&gcp_processor_marc_3, false).await;
+
         r1?;
         r2?;
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::{ collections::VecDeque, sync::Arc };
+
+    use anyhow::{ Result, bail };
+    use async_trait::async_trait;
+    use google_cloud_logging_v2::model::LogEntry;
+    use logs_to_graph::service_logs::ServiceLogs;
+    use serde_json::json;
+    use tokio::{ sync::{ Mutex, mpsc::channel }, time::Duration };
+
+    use crate::{
+        checkpoint::{ CheckpointStore, FileCheckpointStore },
+        gcp_service_log::{ GCPServiceLogs, GCPServiceLogsOptions, build_window_filters },
+        log_source::{ LogEntriesPage, LogEntriesRequest, LogEntrySource },
+        options::GcpProcessingOptions,
+        trace::{ Trace, TraceSource },
+    };
+
+    /// Local stand-in for the Logging API that serves pre-recorded pages.
+    #[derive(Default)]
+    struct ScriptedLogSource {
+        pages: Mutex<VecDeque<LogEntriesPage>>,
+        requests: Mutex<Vec<LogEntriesRequest>>,
+    }
+
+    #[async_trait]
+    impl LogEntrySource for ScriptedLogSource {
+        async fn list_log_entries(&self, request: LogEntriesRequest) -> Result<LogEntriesPage> {
+            self.requests.lock().await.push(request);
+            Ok(self.pages.lock().await.pop_front().unwrap_or_default())
+        }
+    }
+
+    struct NoTraces;
+
+    #[async_trait]
+    impl TraceSource for NoTraces {
+        async fn get_trace(&self, trace: &str) -> Result<Trace> {
+            bail!("No trace available for {}", trace)
+        }
+    }
+
+    fn log_entry(insert_id: &str, timestamp: &str, url: &str) -> LogEntry {
+        serde_json
+            ::from_value(
+                json!({
+                "insertId": insert_id,
+                "timestamp": timestamp,
+                "resource": { "type": "cloud_run_revision", "labels": { "service_name": "users-service" } },
+                "httpRequest": { "requestMethod": "GET", "requestUrl": url, "status": 200 },
+            })
+            )
+            .unwrap()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn should_keep_polling_from_the_newest_log_entry_when_following() {
+        let log_source = Arc::new(ScriptedLogSource::default());
+        log_source.pages.lock().await.extend([
+            LogEntriesPage {
+                entries: vec![
+                    log_entry("a", "2025-01-01T00:00:10Z", "https://test.com/users"),
+                    log_entry("b", "2025-01-01T00:00:20Z", "https://test.com/books")
+                ],
+                next_page_token: String::new(),
+            },
+            LogEntriesPage {
+                entries: vec![
+                    log_entry("b", "2025-01-01T00:00:20Z", "https://test.com/books"),
+                    log_entry("c", "2025-01-01T00:00:30Z", "https://test.com/authors")
+                ],
+                next_page_token: String::new(),
+            },
+        ]);
+
+        let service_logs = GCPServiceLogs::with_sources(
+            GCPServiceLogsOptions {
+                project_ids: vec!["my-project".into()],
+                page_size: 10,
+                log_filter: None,
+                custom_path_normalize_patterns: vec![],
+                follow: Some(Duration::from_secs(5)),
+                lookback: None,
+                from: None,
+                to: None,
+                checkpoint_path: None,
+                record_dir: None,
+                processing: GcpProcessingOptions::default(),
+            },
+            log_source.clone(),
+            Arc::new(NoTraces)
+        ).unwrap();
+
+        let (sender, mut receiver) = channel(32);
+        let handle = tokio::spawn(async move { service_logs.run(sender).await });
+
+        let mut operation_count = 0;
+        while let Some(graph) = receiver.recv().await {
+            operation_count = graph.services["users-service"].operations.len();
+            if operation_count == 3 {
+                break;
+            }
+        }
+        handle.abort();
+
+        assert_eq!(operation_count, 3);
+
+        let requests = log_source.requests.lock().await;
+        assert!(!requests[0].filter.contains("2025-01-01T00:00:20Z"));
+        assert!(requests[1].filter.ends_with("AND timestamp>=\"2025-01-01T00:00:20Z\""));
+    }
+
+    #[test]
+    fn should_build_a_fixed_window() {
+        let filters = build_window_filters(
+            Some(Duration::from_secs(60)),
+            Some("2025-01-01T00:00:00Z"),
+            Some("2025-04-01T00:00:00Z")
+        ).unwrap();
+
+        assert_eq!(filters, vec![
+            "timestamp>=\"2025-01-01T00:00:00Z\"".to_string(),
+            "timestamp<\"2025-04-01T00:00:00Z\"".to_string()
+        ]);
+    }
+
+    #[test]
+    fn should_reject_a_window_ending_before_it_starts() {
+        let filters = build_window_filters(
+            None,
+            Some("2025-04-01T00:00:00Z"),
+            Some("2025-01-01T00:00:00Z")
+        );
+
+        assert!(filters.is_err());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn should_resume_from_the_checkpoint() {
+        let checkpoint_path = std::env
+            ::temp_dir()
+            .join(format!("rtc-resume-checkpoint-{}.json", std::process::id()));
+
+        let options = || GCPServiceLogsOptions {
+            project_ids: vec!["my-project".into()],
+            page_size: 10,
+            log_filter: None,
+            custom_path_normalize_patterns: vec![],
+            follow: None,
+            lookback: None,
+            from: None,
+            to: None,
+            checkpoint_path: Some(checkpoint_path.clone()),
+            record_dir: None,
+            processing: GcpProcessingOptions::default(),
+        };
+
+        let first_page = LogEntriesPage {
+            entries: vec![log_entry("a", "2025-01-01T00:00:10Z", "https://test.com/users")],
+            next_page_token: String::new(),
+        };
+
+        let log_source = Arc::new(ScriptedLogSource::default());
+        log_source.pages.lock().await.push_back(first_page.clone());
+        let service_logs = GCPServiceLogs::with_sources(
+            options(),
+            log_source.clone(),
+            Arc::new(NoTraces)
+        ).unwrap();
+        let (sender, _receiver) = channel(32);
+        service_logs.run(sender).await.unwrap();
+
+        let restarted_log_source = Arc::new(ScriptedLogSource::default());
+        restarted_log_source.pages.lock().await.push_back(first_page);
+        let service_logs = GCPServiceLogs::with_sources(
+            options(),
+            restarted_log_source.clone(),
+            Arc::new(NoTraces)
+        ).unwrap();
+        let (sender, mut receiver) = channel(32);
+        service_logs.run(sender).await.unwrap();
+
+        std::fs::remove_file(checkpoint_path).unwrap();
+
+        let requests = restarted_log_source.requests.lock().await;
+        assert!(requests[0].filter.ends_with("AND timestamp>=\"2025-01-01T00:00:10Z\""));
+
+        // The entry was already processed before the restart, it is not accounted for twice
+        // but what was mapped from it carries on.
+        let graph = receiver.recv().await.unwrap();
+        let service = graph.services.values().next().unwrap();
+        assert_eq!(graph.services.len(), 1);
+        assert_eq!(service.metrics.values().map(|metrics| metrics.count).sum::<u64>(), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn should_not_checkpoint_traces_that_could_not_be_fetched() {
+        let checkpoint_path = std::env
+            ::temp_dir()
+            .join(format!("rtc-unfetched-checkpoint-{}.json", std::process::id()));
+
+        let mut entry = log_entry("a", "2025-01-01T00:00:10Z", "https://test.com/users");
+        entry.trace = "projects/my-project/traces/abc".into();
+        let log_source = Arc::new(ScriptedLogSource::default());
+        log_source.pages.lock().await.push_back(LogEntriesPage {
+            entries: vec![entry],
+            next_page_token: String::new(),
+        });
+
+        let options = GCPServiceLogsOptions {
+            project_ids: vec!["my-project".into()],
+            page_size: 10,
+            log_filter: None,
+            custom_path_normalize_patterns: vec![],
+            follow: None,
+            lookback: None,
+            from: None,
+            to: None,
+            checkpoint_path: Some(checkpoint_path.clone()),
+            record_dir: None,
+            processing: GcpProcessingOptions::default(),
+        };
+        let service_logs = GCPServiceLogs::with_sources(options, log_source, Arc::new(NoTraces)).unwrap();
+        let (sender, _receiver) = channel(32);
+        service_logs.run(sender).await.unwrap();
+
+        let checkpoint = FileCheckpointStore::new(checkpoint_path.clone()).load().unwrap().unwrap();
+        std::fs::remove_file(checkpoint_path).unwrap();
+
+        // Fetched again when logged again, after a restart included.
+        assert!(checkpoint.cursor.insert_ids.contains("a"));
+        assert!(checkpoint.processed_traces.is_empty());
+    }
+}
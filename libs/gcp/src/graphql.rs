@@ -0,0 +1,62 @@
+use google_cloud_logging_v2::model::LogEntry;
+use logs_to_graph::{ graphql::graphql_operation, service_node_graph::Operation };
+use serde::{ Deserialize, Serialize };
+use serde_json::Value;
+
+/// Fields of the JSON payload holding the GraphQL operation, in the log entries of a GraphQL server.
+/// Nested fields are separated by dots, e.g. `graphql.operationName`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct GraphQLPayloadFields {
+    pub operation_type: String,
+    pub operation_name: String,
+}
+
+fn payload_field<'a>(payload: &'a serde_json::Map<String, Value>, path: &str) -> Option<&'a str> {
+    // Structured loggers often keep dotted keys flat.
+    if let Some(value) = payload.get(path) {
+        return value.as_str();
+    }
+
+    let (first, rest) = path.split_once('.')?;
+    payload_field(payload.get(first)?.as_object()?, rest)
+}
+
+impl GraphQLPayloadFields {
+    pub fn get_operation(&self, log_entry: &LogEntry) -> Option<Operation> {
+        let payload = log_entry.json_payload()?;
+
+        graphql_operation(
+            payload_field(payload, &self.operation_type)?,
+            payload_field(payload, &self.operation_name)?
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use google_cloud_logging_v2::model::LogEntry;
+    use logs_to_graph::service_node_graph::Operation;
+    use serde_json::json;
+
+    use crate::graphql::GraphQLPayloadFields;
+
+    #[test]
+    fn should_read_the_graphql_operation_from_the_json_payload() {
+        let graphql_fields = GraphQLPayloadFields {
+            operation_type: "graphql.operationType".into(),
+            operation_name: "graphql.operationName".into(),
+        };
+        let log_entry: LogEntry = serde_json
+            ::from_value(
+                json!({
+                "jsonPayload": { "graphql": { "operationType": "mutation", "operationName": "login" } },
+            })
+            )
+            .unwrap();
+
+        assert_eq!(
+            graphql_fields.get_operation(&log_entry),
+            Some(Operation::GraphQL { operation_type: "mutation".into(), name: "login".into() })
+        );
+    }
+}
@@ -1,9 +1,20 @@
+mod checkpoint;
 mod consts;
-mod normalize;
+mod cursor;
 mod creds;
-mod trace;
+mod datastore;
+mod external;
+mod messaging;
+mod recording;
+mod resource;
 mod types;
 mod gcp_processor;
 mod utils;
 
+pub mod caller;
+pub mod gcp_file_service_log;
 pub mod gcp_service_log;
+pub mod graphql;
+pub mod log_source;
+pub mod options;
+pub mod trace;
@@ -0,0 +1,58 @@
+use async_trait::async_trait;
+use anyhow::{ Ok, Result };
+use google_cloud_logging_v2::{ client::LoggingServiceV2, model::LogEntry };
+use serde::{ Deserialize, Serialize };
+
+/// A single `entries.list` request against the Logging API.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LogEntriesRequest {
+    pub resource_names: Vec<String>,
+    pub filter: String,
+    pub page_size: i32,
+    pub page_token: String,
+}
+
+/// A single page of log entries returned by a [`LogEntrySource`].
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LogEntriesPage {
+    pub entries: Vec<LogEntry>,
+    pub next_page_token: String,
+}
+
+/// Anything able to serve pages of log entries.
+/// The live implementation talks to Cloud Logging, but this lets the
+/// pipeline be driven by a local stand-in.
+#[async_trait]
+pub trait LogEntrySource: Send + Sync {
+    async fn list_log_entries(&self, request: LogEntriesRequest) -> Result<LogEntriesPage>;
+}
+
+pub struct CloudLoggingSource {
+    client: LoggingServiceV2,
+}
+
+impl CloudLoggingSource {
+    pub async fn new() -> Result<Self> {
+        let client = LoggingServiceV2::builder().build().await?; // Uses ADC by default
+        Ok(Self { client })
+    }
+}
+
+#[async_trait]
+impl LogEntrySource for CloudLoggingSource {
+    async fn list_log_entries(&self, request: LogEntriesRequest) -> Result<LogEntriesPage> {
+        let response = self.client
+            .list_log_entries()
+            .set_resource_names(request.resource_names)
+            .set_filter(request.filter)
+            .set_page_size(request.page_size)
+            .set_order_by("timestamp asc")
+            .set_page_token(request.page_token)
+            .send().await?;
+
+        Ok(LogEntriesPage {
+            entries: response.entries,
+            next_page_token: response.next_page_token,
+        })
+    }
+}
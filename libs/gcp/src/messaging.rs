@@ -0,0 +1,285 @@
+use std::collections::HashMap;
+
+use google_cloud_logging_v2::model::LogEntry;
+use url::Url;
+
+use crate::{ consts::HTTP_URL_LABELS, trace::Span };
+
+const PUBSUB_SYSTEM: &str = "pubsub";
+const CLOUD_TASKS_SYSTEM: &str = "cloud_tasks";
+
+const PUBSUB_HOST: &str = "pubsub.googleapis.com";
+const CLOUD_TASKS_HOST: &str = "cloudtasks.googleapis.com";
+
+/// `messaging.system` of the OpenTelemetry instrumentation of the Pub/Sub client libraries.
+const OTEL_PUBSUB_SYSTEM: &str = "gcp_pubsub";
+const MESSAGING_SYSTEM_LABEL: &str = "messaging.system";
+const MESSAGING_DESTINATION_LABELS: [&str; 2] = ["messaging.destination.name", "messaging.destination"];
+const MESSAGING_OPERATION_LABELS: [&str; 2] = ["messaging.operation.type", "messaging.operation"];
+
+const CLOUD_TASKS_USER_AGENT: &str = "Google-Cloud-Tasks";
+const PUBSUB_USER_AGENTS: [&str; 2] = ["APIs-Google", "CloudPubSub-Google"];
+const CLOUD_TASKS_QUEUE_HEADER: &str = "X-CloudTasks-QueueName";
+const USER_AGENT_HEADER: &str = "User-Agent";
+/// Sent by push subscriptions which write the message metadata in headers.
+const PUBSUB_SUBSCRIPTION_HEADER: &str = "X-Goog-Pubsub-Subscription-Name";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MessageRole {
+    Publish,
+    Consume,
+}
+
+/// A message sent to, or received from, a topic or a queue by a span.
+#[derive(Debug, PartialEq)]
+pub struct MessageCall {
+    pub role: MessageRole,
+    pub system: String,
+    pub destination: String,
+}
+
+/// A request that delivered a message, pushed by Pub/Sub or dispatched by Cloud Tasks.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MessageDelivery {
+    pub system: String,
+    /// The topic or the queue, when the request headers were logged and, for Pub/Sub,
+    /// the subscription is mapped to its topic.
+    pub destination: Option<String>,
+}
+
+/// `projects/my-project/topics/orders` is shortened to `orders`.
+fn short_name(resource_name: &str) -> String {
+    resource_name.rsplit('/').next().unwrap_or(resource_name).to_string()
+}
+
+/// Publishing with the REST APIs, e.g. `POST /v1/projects/{project}/topics/{topic}:publish`.
+fn rest_message_call(span: &Span) -> Option<MessageCall> {
+    let url = Url::parse(span.label(&HTTP_URL_LABELS)?).ok()?;
+    let segments: Vec<&str> = url.path_segments()?.collect();
+
+    match url.host_str()? {
+        PUBSUB_HOST => {
+            let (topic, method) = segments.last()?.split_once(':')?;
+            (segments.get(segments.len().checked_sub(2)?) == Some(&"topics") &&
+                method == "publish").then(|| MessageCall {
+                role: MessageRole::Publish,
+                system: PUBSUB_SYSTEM.into(),
+                destination: topic.to_string(),
+            })
+        }
+        CLOUD_TASKS_HOST => {
+            let queue = segments
+                .windows(3)
+                .find(|window| window[0] == "queues" && window[2] == "tasks")?[1];
+            Some(MessageCall {
+                role: MessageRole::Publish,
+                system: CLOUD_TASKS_SYSTEM.into(),
+                destination: queue.to_string(),
+            })
+        }
+        _ => None,
+    }
+}
+
+/// The message sent or received by a span, from the OpenTelemetry messaging labels
+/// or from the URL of the Pub/Sub and Cloud Tasks APIs.
+pub fn message_call(span: &Span) -> Option<MessageCall> {
+    let Some(system) = span.label(&[MESSAGING_SYSTEM_LABEL]) else {
+        return rest_message_call(span);
+    };
+
+    let role = match span.label(&MESSAGING_OPERATION_LABELS)? {
+        "publish" | "create" | "send" => MessageRole::Publish,
+        "receive" | "process" | "deliver" => MessageRole::Consume,
+        _ => {
+            return None;
+        }
+    };
+    let system = match system {
+        OTEL_PUBSUB_SYSTEM => PUBSUB_SYSTEM,
+        system => system,
+    };
+
+    Some(MessageCall {
+        role,
+        system: system.to_string(),
+        destination: short_name(span.label(&MESSAGING_DESTINATION_LABELS)?),
+    })
+}
+
+/// A request header, for services logging them in a `headers` object of their JSON payload.
+fn logged_header(log_entry: &LogEntry, name: &str) -> Option<String> {
+    log_entry
+        .json_payload()?
+        .get("headers")?
+        .as_object()?
+        .iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case(name))?
+        .1.as_str()
+        .map(String::from)
+}
+
+/// Whether a request log entry is the delivery of a message, from its user agent,
+/// which may only have been logged by the service along with the other headers.
+/// Push subscriptions do not tell the topic they are attached to, `subscription_topics`
+/// maps subscription names to topic names so that consumers line up with publishers.
+pub fn message_delivery(
+    log_entry: &LogEntry,
+    subscription_topics: &HashMap<String, String>
+) -> Option<MessageDelivery> {
+    let user_agent = match log_entry.http_request.as_ref() {
+        Some(http_request) => http_request.user_agent.clone(),
+        None => logged_header(log_entry, USER_AGENT_HEADER)?,
+    };
+
+    if user_agent.starts_with(CLOUD_TASKS_USER_AGENT) {
+        return Some(MessageDelivery {
+            system: CLOUD_TASKS_SYSTEM.into(),
+            destination: logged_header(log_entry, CLOUD_TASKS_QUEUE_HEADER),
+        });
+    }
+
+    if PUBSUB_USER_AGENTS.iter().any(|pubsub_user_agent| user_agent.starts_with(pubsub_user_agent)) {
+        // Wrapped push requests carry the subscription in their body instead.
+        let subscription = logged_header(log_entry, PUBSUB_SUBSCRIPTION_HEADER).or_else(||
+            log_entry
+                .json_payload()?
+                .get("subscription")?
+                .as_str()
+                .map(String::from)
+        );
+
+        return Some(MessageDelivery {
+            system: PUBSUB_SYSTEM.into(),
+            destination: subscription.and_then(|subscription| {
+                subscription_topics.get(&short_name(&subscription)).map(|topic| short_name(topic))
+            }),
+        });
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use google_cloud_logging_v2::model::LogEntry;
+    use serde_json::json;
+
+    use crate::{
+        messaging::{ MessageCall, MessageDelivery, MessageRole, message_call, message_delivery },
+        trace::Span,
+    };
+
+    fn span(labels: &[(&str, &str)]) -> Span {
+        Span {
+            span_id: "1".into(),
+            labels: labels
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect::<HashMap<_, _>>(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn should_detect_messages_from_span_labels() {
+        assert_eq!(
+            message_call(
+                &span(
+                    &[
+                        ("messaging.system", "gcp_pubsub"),
+                        ("messaging.destination.name", "projects/my-project/topics/orders"),
+                        ("messaging.operation.type", "create"),
+                    ]
+                )
+            ),
+            Some(MessageCall {
+                role: MessageRole::Publish,
+                system: "pubsub".into(),
+                destination: "orders".into(),
+            })
+        );
+
+        assert_eq!(
+            message_call(
+                &span(
+                    &[
+                        (
+                            "/http/url",
+                            "https://cloudtasks.googleapis.com/v2/projects/my-project/locations/europe-west1/queues/emails/tasks",
+                        ),
+                    ]
+                )
+            ),
+            Some(MessageCall {
+                role: MessageRole::Publish,
+                system: "cloud_tasks".into(),
+                destination: "emails".into(),
+            })
+        );
+
+        assert_eq!(
+            message_call(
+                &span(&[("/http/url", "https://pubsub.googleapis.com/v1/projects/my-project/topics/orders:publish")])
+            ).map(|message_call| message_call.destination),
+            Some("orders".into())
+        );
+    }
+
+    #[test]
+    fn should_detect_message_deliveries_from_request_logs() {
+        let log_entry: LogEntry = serde_json
+            ::from_value(
+                json!({
+                "httpRequest": { "requestMethod": "POST", "requestUrl": "https://emails/send", "userAgent": "Google-Cloud-Tasks" },
+                "jsonPayload": { "headers": { "x-cloudtasks-queuename": "emails" } },
+            })
+            )
+            .unwrap();
+
+        assert_eq!(
+            message_delivery(&log_entry, &HashMap::new()),
+            Some(MessageDelivery { system: "cloud_tasks".into(), destination: Some("emails".into()) })
+        );
+
+        // Logged by the service itself, with no request of the serving infrastructure.
+        let log_entry: LogEntry = serde_json
+            ::from_value(
+                json!({
+                "jsonPayload": { "headers": { "user-agent": "Google-Cloud-Tasks", "x-cloudtasks-queuename": "emails" } },
+            })
+            )
+            .unwrap();
+
+        assert_eq!(
+            message_delivery(&log_entry, &HashMap::new()).and_then(|delivery| delivery.destination),
+            Some("emails".into())
+        );
+    }
+
+    #[test]
+    fn should_resolve_push_subscriptions_to_their_topic() {
+        let log_entry: LogEntry = serde_json
+            ::from_value(
+                json!({
+                "httpRequest": { "requestMethod": "POST", "requestUrl": "https://emails/push", "userAgent": "APIs-Google" },
+                "jsonPayload": { "subscription": "projects/my-project/subscriptions/emails-push" },
+            })
+            )
+            .unwrap();
+
+        assert_eq!(
+            message_delivery(&log_entry, &HashMap::new()),
+            Some(MessageDelivery { system: "pubsub".into(), destination: None })
+        );
+        assert_eq!(
+            message_delivery(
+                &log_entry,
+                &HashMap::from([("emails-push".to_string(), "orders".to_string())])
+            ),
+            Some(MessageDelivery { system: "pubsub".into(), destination: Some("orders".into()) })
+        );
+    }
+}
@@ -0,0 +1,71 @@
+use std::collections::HashMap;
+
+use anyhow::{ Context, Ok, Result, bail };
+use logs_to_graph::{ metrics::SeriesOptions, service_node_graph::ServiceName };
+use serde::{ Deserialize, Serialize };
+
+use crate::{ caller::{ CallerClassifier, CallerRule }, graphql::GraphQLPayloadFields };
+
+/// How log entries and traces are mapped to services, shared by the GCP log engines
+/// and recorded along with a capture so that it replays the same way.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct GcpProcessingOptions {
+    /// Load balancer backend services to the service they route to.
+    #[serde(default)]
+    pub backend_services: HashMap<String, ServiceName>,
+    /// Where GraphQL servers log the operation they serve, if they do.
+    #[serde(default)]
+    pub graphql_fields: Option<GraphQLPayloadFields>,
+    /// User agent rules naming the callers of entry operations, tried before the default ones.
+    #[serde(default)]
+    pub caller_rules: Vec<CallerRule>,
+    /// Also names the callers of requests without a trace. Nothing then tells whether
+    /// one of our services made the request, e.g. with curl, so this is opt-in.
+    #[serde(default)]
+    pub classify_untraced_callers: bool,
+    /// Hosts of our own frontends, the pages referring requests to them become nodes.
+    #[serde(default)]
+    pub frontend_hosts: Vec<String>,
+    /// Pub/Sub push subscriptions to the topic they are attached to, as the requests
+    /// they push only name the subscription.
+    #[serde(default)]
+    pub subscription_topics: HashMap<String, String>,
+    /// Interval of the request series of operations and edges, e.g. "1h", the default.
+    #[serde(default)]
+    pub series_interval: Option<String>,
+    /// How long request series are kept, e.g. "30d", defaults to a week.
+    #[serde(default)]
+    pub series_retention: Option<String>,
+}
+
+impl GcpProcessingOptions {
+    pub fn caller_classifier(&self) -> Result<CallerClassifier> {
+        CallerClassifier::new(self.caller_rules.clone())
+    }
+
+    pub fn series_options(&self) -> Result<SeriesOptions> {
+        let mut series_options = SeriesOptions::default();
+
+        if let Some(interval) = &self.series_interval {
+            let interval = humantime::parse_duration(interval).context("Invalid series_interval")?;
+            if interval.as_secs() == 0 {
+                bail!("Expected series_interval to be at least a second");
+            }
+            series_options.interval_secs = interval.as_secs();
+        }
+
+        if let Some(retention) = &self.series_retention {
+            let retention = humantime::parse_duration(retention).context("Invalid series_retention")?;
+            series_options.retention_secs = retention.as_secs();
+        }
+
+        Ok(series_options)
+    }
+
+    /// Fails on the options that cannot be used, e.g. an invalid caller rule pattern.
+    pub fn validate(&self) -> Result<()> {
+        self.caller_classifier()?;
+        self.series_options()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,265 @@
+use std::{ collections::VecDeque, fs, path::{ Path, PathBuf } };
+
+use anyhow::{ Context, Ok, Result };
+use async_trait::async_trait;
+use serde::{ Deserialize, Serialize };
+use tokio::sync::Mutex;
+
+use crate::{
+    log_source::{ LogEntriesPage, LogEntriesRequest, LogEntrySource },
+    options::GcpProcessingOptions,
+    trace::{ Trace, TraceSource },
+};
+
+const MANIFEST_FILE: &str = "manifest.json";
+const PAGES_DIR: &str = "pages";
+const TRACES_DIR: &str = "traces";
+
+/// Describes what was being captured, so that a recording can be replayed on its own.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordingManifest {
+    pub project_ids: Vec<String>,
+    pub log_filter: String,
+    #[serde(flatten)]
+    pub processing: GcpProcessingOptions,
+}
+
+/// A `list_log_entries` call, as it was answered by the Logging API.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct RecordedPage {
+    request: LogEntriesRequest,
+    page: LogEntriesPage,
+}
+
+fn trace_file_name(trace: &str) -> String {
+    format!("{}.json", trace.replace('/', "_"))
+}
+
+pub fn write_manifest(dir: &Path, manifest: &RecordingManifest) -> Result<()> {
+    fs::create_dir_all(dir.join(PAGES_DIR))?;
+    fs::create_dir_all(dir.join(TRACES_DIR))?;
+    fs::write(dir.join(MANIFEST_FILE), serde_json::to_vec_pretty(manifest)?).with_context(||
+        format!("Failed writing recording manifest in {}", dir.display())
+    )?;
+    Ok(())
+}
+
+pub fn read_manifest(dir: &Path) -> Result<RecordingManifest> {
+    let path = dir.join(MANIFEST_FILE);
+    let data = fs
+        ::read_to_string(&path)
+        .with_context(|| format!("Failed reading recording manifest: {}", path.display()))?;
+    Ok(serde_json::from_str(&data)?)
+}
+
+/// Writes every page served by the inner source to disk before handing it over.
+pub struct RecordingLogEntrySource {
+    inner: Box<dyn LogEntrySource>,
+    dir: PathBuf,
+    page_count: Mutex<usize>,
+}
+
+impl RecordingLogEntrySource {
+    /// Pages are numbered after those of earlier runs in the same directory,
+    /// a replay then reads the runs one after the other.
+    pub fn new(inner: Box<dyn LogEntrySource>, dir: PathBuf) -> Result<Self> {
+        let pages_dir = dir.join(PAGES_DIR);
+        let mut page_count = 0;
+        if pages_dir.exists() {
+            for entry in fs
+                ::read_dir(&pages_dir)
+                .with_context(|| format!("Failed reading recorded pages: {}", pages_dir.display()))? {
+                let page_number = entry?
+                    .path()
+                    .file_stem()
+                    .and_then(|stem| stem.to_str())
+                    .and_then(|stem| stem.parse::<usize>().ok());
+                page_count = page_count.max(page_number.unwrap_or_default());
+            }
+        }
+
+        Ok(Self { inner, dir, page_count: Mutex::new(page_count) })
+    }
+}
+
+#[async_trait]
+impl LogEntrySource for RecordingLogEntrySource {
+    async fn list_log_entries(&self, request: LogEntriesRequest) -> Result<LogEntriesPage> {
+        let page = self.inner.list_log_entries(request.clone()).await?;
+
+        let mut page_count = self.page_count.lock().await;
+        *page_count += 1;
+        let path = self.dir.join(PAGES_DIR).join(format!("{:06}.json", *page_count));
+        let recorded_page = RecordedPage { request, page };
+        fs::write(&path, serde_json::to_vec(&recorded_page)?).with_context(||
+            format!("Failed recording page: {}", path.display())
+        )?;
+
+        Ok(recorded_page.page)
+    }
+}
+
+/// Writes every trace served by the inner source to disk before handing it over.
+pub struct RecordingTraceSource {
+    inner: Box<dyn TraceSource>,
+    dir: PathBuf,
+}
+
+impl RecordingTraceSource {
+    pub fn new(inner: Box<dyn TraceSource>, dir: PathBuf) -> Self {
+        Self { inner, dir }
+    }
+}
+
+#[async_trait]
+impl TraceSource for RecordingTraceSource {
+    async fn get_trace(&self, trace: &str) -> Result<Trace> {
+        let response = self.inner.get_trace(trace).await?;
+
+        let path = self.dir.join(TRACES_DIR).join(trace_file_name(trace));
+        fs::write(&path, serde_json::to_vec(&response)?).with_context(||
+            format!("Failed recording trace: {}", path.display())
+        )?;
+
+        Ok(response)
+    }
+}
+
+/// Serves the recorded pages back, in the order they were captured.
+///
+/// Polls of a followed recording each end with an empty page token,
+/// those are replaced so that the whole recording is read in one pass.
+pub struct RecordedLogEntrySource {
+    pages: Mutex<VecDeque<LogEntriesPage>>,
+}
+
+impl RecordedLogEntrySource {
+    pub fn new(dir: &Path) -> Result<Self> {
+        let pages_dir = dir.join(PAGES_DIR);
+        let mut paths = fs
+            ::read_dir(&pages_dir)
+            .with_context(|| format!("Failed reading recorded pages: {}", pages_dir.display()))?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<std::io::Result<Vec<PathBuf>>>()?;
+        paths.sort();
+
+        let mut pages = VecDeque::new();
+        for (index, path) in paths.iter().enumerate() {
+            let data = fs::read_to_string(path)?;
+            let mut recorded_page: RecordedPage = serde_json
+                ::from_str(&data)
+                .with_context(|| format!("Failed parsing recorded page: {}", path.display()))?;
+
+            let is_last = index + 1 == paths.len();
+            if !is_last && recorded_page.page.next_page_token.is_empty() {
+                recorded_page.page.next_page_token = format!("replay-{}", index + 1);
+            }
+
+            pages.push_back(recorded_page.page);
+        }
+
+        Ok(Self { pages: Mutex::new(pages) })
+    }
+}
+
+#[async_trait]
+impl LogEntrySource for RecordedLogEntrySource {
+    async fn list_log_entries(&self, _request: LogEntriesRequest) -> Result<LogEntriesPage> {
+        Ok(self.pages.lock().await.pop_front().unwrap_or_default())
+    }
+}
+
+/// Serves the recorded traces back.
+pub struct RecordedTraceSource {
+    dir: PathBuf,
+}
+
+impl RecordedTraceSource {
+    pub fn new(dir: &Path) -> Self {
+        Self { dir: dir.to_path_buf() }
+    }
+}
+
+#[async_trait]
+impl TraceSource for RecordedTraceSource {
+    async fn get_trace(&self, trace: &str) -> Result<Trace> {
+        let path = self.dir.join(TRACES_DIR).join(trace_file_name(trace));
+        let data = fs
+            ::read_to_string(&path)
+            .with_context(|| format!("Trace {} was not recorded", trace))?;
+        Ok(serde_json::from_str(&data)?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use anyhow::Result;
+    use async_trait::async_trait;
+    use logs_to_graph::service_logs::ServiceLogs;
+    use tokio::sync::mpsc::channel;
+
+    use crate::{
+        gcp_service_log::GCPServiceLogs,
+        log_source::{ LogEntriesPage, LogEntriesRequest, LogEntrySource },
+        options::GcpProcessingOptions,
+        recording::{ RecordedLogEntrySource, RecordingLogEntrySource, write_manifest, RecordingManifest },
+    };
+
+    struct SinglePage;
+
+    #[async_trait]
+    impl LogEntrySource for SinglePage {
+        async fn list_log_entries(&self, _request: LogEntriesRequest) -> Result<LogEntriesPage> {
+            Ok(LogEntriesPage { entries: vec![], next_page_token: "next".into() })
+        }
+    }
+
+    #[tokio::test]
+    async fn should_replay_what_was_recorded() {
+        let dir = std::env::temp_dir().join(format!("rtc-recording-{}", std::process::id()));
+        write_manifest(&dir, &(RecordingManifest {
+            project_ids: vec!["my-project".into()],
+            log_filter: String::new(),
+            processing: GcpProcessingOptions::default(),
+        })).unwrap();
+
+        let recording = RecordingLogEntrySource::new(Box::new(SinglePage), dir.clone()).unwrap();
+        recording.list_log_entries(LogEntriesRequest::default()).await.unwrap();
+
+        // A second run carries on after the pages of the first one.
+        let recording = RecordingLogEntrySource::new(Box::new(SinglePage), dir.clone()).unwrap();
+        recording.list_log_entries(LogEntriesRequest::default()).await.unwrap();
+        assert!(dir.join("pages/000002.json").exists());
+
+        let recorded = RecordedLogEntrySource::new(&dir).unwrap();
+        let page = recorded.list_log_entries(LogEntriesRequest::default()).await.unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+
+        assert_eq!(page.next_page_token, "next");
+    }
+
+    #[tokio::test]
+    async fn should_rebuild_the_graph_from_a_recording() {
+        let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(
+            "testdata/recordings/graphql-to-users"
+        );
+        let service_logs = GCPServiceLogs::from_recording(dir, vec![]).unwrap();
+
+        let (sender, mut receiver) = channel(32);
+        service_logs.run(sender).await.unwrap();
+
+        let mut graphs = vec![];
+        while let Some(graph) = receiver.recv().await {
+            graphs.push(graph);
+        }
+
+        // Traces are processed before the next page is read, the last graph has everything.
+        let graph = graphs.last().unwrap();
+
+        assert_eq!(graph.services["graphql-service"].operations.len(), 1);
+        assert_eq!(graph.services["users-service"].operations.len(), 1);
+        assert_eq!(graph.services["graphql-service"].invokes["users-service"].len(), 1);
+    }
+}
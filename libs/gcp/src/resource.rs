@@ -0,0 +1,191 @@
+use std::collections::HashMap;
+
+use google_cloud_logging_v2::model::LogEntry;
+
+use crate::{
+    consts::{
+        CLOUD_SQL_PROXY_CONNECTION_MESSAGES,
+        FIRESTORE_SERVICE_NAME,
+        SUPPORTED_RESOURCE_TYPES,
+        TRACE_ONLY_RESOURCE_TYPES,
+    },
+    graphql::GraphQLPayloadFields,
+};
+
+/// Pod labels are copied onto the log entries of GKE workloads, with dots replaced by underscores.
+const K8S_WORKLOAD_LABELS: [&str; 4] = [
+    "k8s-pod/app_kubernetes_io/name",
+    "k8s-pod/app.kubernetes.io/name",
+    "k8s-pod/app",
+    "k8s-pod/k8s-app",
+];
+
+/// Kubernetes generates name suffixes out of this alphabet, it has no vowels to avoid forming words.
+const K8S_NAME_HASH_ALPHABET: &str = "bcdfghjklmnpqrstvwxz2456789";
+
+/// Only the log entries of the supported resources that either describe a request,
+/// or, for jobs, belong to a trace, along with the connections logged by Cloud SQL Auth Proxy
+/// sidecars and the Firestore audit logs. Requests may also be logged by the services
+/// themselves, with their headers or, for GraphQL servers, their operation in the JSON payload.
+///
+/// Note: wildcards are not allowed in the log filter for whatever reason.
+pub fn build_default_log_filter(graphql_fields: Option<&GraphQLPayloadFields>) -> String {
+    let resource_types = SUPPORTED_RESOURCE_TYPES.map(|resource_type| format!("\"{}\"", resource_type));
+    let trace_only_resource_types = TRACE_ONLY_RESOURCE_TYPES.map(|resource_type|
+        format!("\"{}\"", resource_type)
+    );
+    let cloud_sql_proxy_messages = CLOUD_SQL_PROXY_CONNECTION_MESSAGES.map(|message|
+        format!("textPayload:\"{}\" OR jsonPayload.message:\"{}\"", message, message)
+    );
+
+    let mut request_filters = vec!["http_request:*".to_string(), "jsonPayload.headers:*".to_string()];
+    if let Some(graphql_fields) = graphql_fields {
+        let field = &graphql_fields.operation_type;
+        request_filters.push(format!("jsonPayload.{}:*", field));
+        // Structured loggers often keep dotted keys flat, those have to be quoted.
+        if field.contains('.') {
+            request_filters.push(format!("jsonPayload.\"{}\":*", field));
+        }
+    }
+
+    // Wrapped in parentheses since it is joined with other filters.
+    format!(
+        "((resource.type=({}) AND ({} OR (resource.type=({}) AND trace:*) OR (resource.type=\"k8s_container\" AND ({})))) OR protoPayload.serviceName=\"{}\")",
+        resource_types.join(" OR "),
+        request_filters.join(" OR "),
+        trace_only_resource_types.join(" OR "),
+        cloud_sql_proxy_messages.join(" OR "),
+        FIRESTORE_SERVICE_NAME
+    )
+}
+
+/// Strips the hashes Kubernetes appends to the pods of a Deployment (`users-7d4b9c-x2x8k`)
+/// or a StatefulSet/DaemonSet (`users-x2x8k`), to get back to the workload name.
+fn workload_from_pod_name(pod_name: &str) -> &str {
+    let is_hash = |part: &str| {
+        (5..=10).contains(&part.len()) && part.chars().all(|c| K8S_NAME_HASH_ALPHABET.contains(c))
+    };
+
+    let mut workload = pod_name;
+    for _ in 0..2 {
+        match workload.rsplit_once('-') {
+            Some((prefix, suffix)) if is_hash(suffix) => {
+                workload = prefix;
+            }
+            _ => {
+                break;
+            }
+        }
+    }
+    workload
+}
+
+/// Kubernetes workloads are named `{namespace}/{workload}`, the workload coming from the pod
+/// labels when GKE forwarded them, otherwise from the pod or the container name.
+fn k8s_service_name(
+    resource_labels: &HashMap<String, String>,
+    entry_labels: &HashMap<String, String>
+) -> Option<String> {
+    let namespace = resource_labels.get("namespace_name")?;
+
+    let workload = K8S_WORKLOAD_LABELS.iter()
+        .find_map(|label| entry_labels.get(*label).cloned())
+        .or_else(|| {
+            resource_labels.get("pod_name").map(|pod_name| workload_from_pod_name(pod_name).to_string())
+        })
+        .or_else(|| resource_labels.get("container_name").cloned())?;
+
+    Some(format!("{}/{}", namespace, workload))
+}
+
+/// Derives the service a log entry belongs to, following the naming rules of its resource type.
+pub fn resource_service_name(log_entry: &LogEntry) -> Option<String> {
+    let resource = log_entry.resource.as_ref()?;
+    let label = |name: &str| resource.labels.get(name).cloned();
+
+    match resource.r#type.as_str() {
+        "cloud_run_revision" => label("service_name"),
+        "cloud_run_job" => label("job_name"),
+        "k8s_container" | "k8s_pod" => k8s_service_name(&resource.labels, &log_entry.labels),
+        // App Engine services used to be called modules.
+        "gae_app" => label("module_id"),
+        "cloud_function" => label("function_name"),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use google_cloud_logging_v2::model::LogEntry;
+    use serde_json::json;
+
+    use crate::{
+        graphql::GraphQLPayloadFields,
+        resource::{ build_default_log_filter, resource_service_name, workload_from_pod_name },
+    };
+
+    fn log_entry(resource: serde_json::Value, labels: serde_json::Value) -> LogEntry {
+        serde_json::from_value(json!({ "resource": resource, "labels": labels })).unwrap()
+    }
+
+    #[test]
+    fn should_name_services_per_resource_type() {
+        let cases = [
+            (json!({ "type": "cloud_run_revision", "labels": { "service_name": "users" } }), "users"),
+            (json!({ "type": "cloud_run_job", "labels": { "job_name": "nightly-export" } }), "nightly-export"),
+            (json!({ "type": "gae_app", "labels": { "module_id": "default", "version_id": "v2" } }), "default"),
+            (json!({ "type": "cloud_function", "labels": { "function_name": "on-upload" } }), "on-upload"),
+            (
+                json!({ "type": "k8s_container", "labels": { "namespace_name": "shop", "pod_name": "cart-7d4b9c5f6-x2x8k", "container_name": "app" } }),
+                "shop/cart",
+            ),
+        ];
+
+        for (resource, expected) in cases {
+            assert_eq!(resource_service_name(&log_entry(resource, json!({}))).as_deref(), Some(expected));
+        }
+    }
+
+    #[test]
+    fn should_prefer_the_workload_pod_label() {
+        let entry = log_entry(
+            json!({ "type": "k8s_pod", "labels": { "namespace_name": "shop", "pod_name": "cart-0" } }),
+            json!({ "k8s-pod/app_kubernetes_io/name": "cart-api" })
+        );
+
+        assert_eq!(resource_service_name(&entry).as_deref(), Some("shop/cart-api"));
+    }
+
+    #[test]
+    fn should_strip_pod_hashes() {
+        assert_eq!(workload_from_pod_name("cart-7d4b9c5f6-x2x8k"), "cart");
+        assert_eq!(workload_from_pod_name("node-exporter-4tz9q"), "node-exporter");
+        assert_eq!(workload_from_pod_name("redis-0"), "redis-0");
+        assert_eq!(workload_from_pod_name("users-api"), "users-api");
+    }
+
+    #[test]
+    fn should_build_the_default_log_filter() {
+        let filter = build_default_log_filter(None);
+        assert!(filter.starts_with("((resource.type=(\"cloud_run_revision\" OR \"cloud_run_job\""));
+        assert!(filter.contains("(http_request:* OR jsonPayload.headers:* OR (resource.type=(\"cloud_run_job\") AND trace:*)"));
+        assert!(filter.ends_with(" OR protoPayload.serviceName=\"firestore.googleapis.com\")"));
+    }
+
+    #[test]
+    fn should_include_the_graphql_operations_logged_without_a_request() {
+        let filter = build_default_log_filter(
+            Some(
+                &(GraphQLPayloadFields {
+                    operation_type: "graphql.operationType".into(),
+                    operation_name: "graphql.operationName".into(),
+                })
+            )
+        );
+        assert!(
+            filter.contains(
+                "(http_request:* OR jsonPayload.headers:* OR jsonPayload.graphql.operationType:* OR jsonPayload.\"graphql.operationType\":* OR (resource.type"
+            )
+        );
+    }
+}
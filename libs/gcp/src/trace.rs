@@ -1,9 +1,16 @@
+use std::collections::HashMap;
+
 use anyhow::{ Ok, Result, bail };
-use serde::Deserialize;
+use async_trait::async_trait;
+use serde::{ Deserialize, Serialize };
 
-use crate::{ creds::load_creds, types::{ ParentSpanId, SpanId, TraceId } };
+use crate::{
+    consts::RPC_CLIENT_SPAN_KIND,
+    creds::load_creds,
+    types::{ ParentSpanId, SpanId, TraceId },
+};
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct Trace {
     pub project_id: String,
@@ -11,11 +18,39 @@ pub struct Trace {
     pub spans: Vec<Span>,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Default)]
 #[serde(rename_all = "camelCase")]
 pub struct Span {
     pub span_id: SpanId,
     pub parent_span_id: Option<ParentSpanId>,
+    #[serde(default)]
+    pub name: Option<String>,
+    /// `RPC_CLIENT`, `RPC_SERVER` or `SPAN_KIND_UNSPECIFIED`.
+    #[serde(default)]
+    pub kind: Option<String>,
+    /// e.g. `/http/url`, `/http/host`, `g.co/agent` or `db.system`.
+    #[serde(default)]
+    pub labels: HashMap<String, String>,
+}
+
+impl Span {
+    pub fn is_client(&self) -> bool {
+        self.kind.as_deref() == Some(RPC_CLIENT_SPAN_KIND)
+    }
+
+    /// Value of the first of the given labels that is set.
+    pub fn label(&self, keys: &[&str]) -> Option<&str> {
+        keys.iter()
+            .find_map(|key| self.labels.get(*key))
+            .map(|value| value.as_str())
+            .filter(|value| !value.is_empty())
+    }
+}
+
+/// Anything able to resolve a full trace path into its spans.
+#[async_trait]
+pub trait TraceSource: Send + Sync {
+    async fn get_trace(&self, trace: &str) -> Result<Trace>;
 }
 
 pub struct TracesAPI {
@@ -30,8 +65,11 @@ impl TracesAPI {
             token,
         })
     }
+}
 
-    pub async fn get_trace(&self, trace: &String) -> Result<Trace> {
+#[async_trait]
+impl TraceSource for TracesAPI {
+    async fn get_trace(&self, trace: &str) -> Result<Trace> {
         let url = format!("https://cloudtrace.googleapis.com/v1/{}", trace);
 
         let bearer = format!("Bearer {}", self.token);
@@ -1,7 +1,6 @@
 pub type SpanId = String;
 pub type TraceId = String;
 pub type ParentSpanId = String;
-pub type ProjectId = String;
 
 /// Represents the full trace url
 /// projects/{project_id}/traces/{trace_id}
@@ -1,3 +1,40 @@
-pub fn build_trace_path(project_id: String, trace_id: String) -> String {
-    format!("projects/{}/traces/{}", project_id, trace_id)
+/// Splits `projects/{project_id}/traces/{trace_id}` into its project and trace ids.
+pub fn parse_trace_path(trace: &str) -> Option<(String, String)> {
+    let mut parts = trace.split('/');
+    match (parts.next(), parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some("projects"), Some(project_id), Some("traces"), Some(trace_id), None) =>
+            Some((project_id.to_string(), trace_id.to_string())),
+        _ => None,
+    }
+}
+
+/// Extracts the project out of a `projects/{project_id}/logs/{log_id}` log name.
+pub fn parse_log_name_project(log_name: &str) -> Option<String> {
+    log_name
+        .strip_prefix("projects/")
+        .and_then(|rest| rest.split('/').next())
+        .filter(|project_id| !project_id.is_empty())
+        .map(String::from)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::utils::{ parse_log_name_project, parse_trace_path };
+
+    #[test]
+    fn should_parse_a_trace_path() {
+        assert_eq!(
+            parse_trace_path("projects/my-project/traces/abc"),
+            Some(("my-project".into(), "abc".into()))
+        );
+        assert_eq!(parse_trace_path("abc"), None);
+    }
+
+    #[test]
+    fn should_parse_the_project_of_a_log_name() {
+        assert_eq!(
+            parse_log_name_project("projects/my-project/logs/run.googleapis.com%2Frequests"),
+            Some("my-project".into())
+        );
+    }
 }
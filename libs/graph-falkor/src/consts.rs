@@ -1,19 +1,26 @@
-/*
-
-*/
+/// The node upserts are completed with when the node was seen, if known,
+/// e.g. `, s.last_seen = ...`, and `RETURN s`.
 pub const UPSERT_SERVICE_NODE_CYPHER: &str =
     r#"
     MERGE ( s:Service { name: $name })
     ON CREATE SET s.name = $name
-    RETURN s
-"#;
+    SET s.kind = $kind"#;
+
+/// Datastores have a label of their own, with the system and the instance they run.
+/// They expose their operations like services do, and are only ever invoked.
+pub const UPSERT_DATASTORE_NODE_CYPHER: &str =
+    r#"
+    MERGE ( s:Datastore { name: $name })
+    ON CREATE SET s.name = $name
+    SET s.kind = $kind, s.system = $system, s.instance = $instance"#;
 
+/// Completed with the properties, figures and seen times of the operation,
+/// e.g. `, o.grpc_method = $grpc_method`, and `RETURN o`.
 pub const UPSERT_OPERATION_CYPHER: &str =
     r#"
     MERGE (o:Operation {id: $id})
     ON CREATE SET o.label = $label, o.id = $id
-    RETURN o
-"#;
+    SET o.kind = $kind"#;
 
 pub const UPSERT_SERVICE_NODE_TO_OPERATION_CYPHER: &str =
     r#"
@@ -22,9 +29,59 @@ pub const UPSERT_SERVICE_NODE_TO_OPERATION_CYPHER: &str =
     RETURN r
 "#;
 
+pub const UPSERT_DATASTORE_NODE_TO_OPERATION_CYPHER: &str =
+    r#"
+    MATCH (s:Datastore {name: $name}), (o:Operation {id: $id})
+    MERGE (s)-[r:EXPOSES]-(o)
+    RETURN r
+"#;
+
+/// The relation upserts are completed with the figures and seen times of the edge, if any,
+/// e.g. `SET r.request_count = $request_count`, and `RETURN r`.
 pub const UPSERT_SERVICE_TO_SERVICE_OPERATION_RELATION: &str =
     r#"
     MATCH (s:Service {name: $name}), (o:Operation {id: $id})
-    MERGE (s)-[r:INVOKES]->(o)
-    RETURN r
+    MERGE (s)-[r:INVOKES]->(o)"#;
+
+pub const UPSERT_SERVICE_PUBLISHES_RELATION: &str =
+    r#"
+    MATCH (s:Service {name: $name}), (o:Operation {id: $id})
+    MERGE (s)-[r:PUBLISHES]->(o)"#;
+
+pub const UPSERT_SERVICE_CONSUMES_RELATION: &str =
+    r#"
+    MATCH (s:Service {name: $name}), (o:Operation {id: $id})
+    MERGE (s)-[r:CONSUMES]->(o)"#;
+
+/// Latest time anything stored was seen at, whichever source it came from. Relations
+/// are seen along with the service they start from, so the nodes are enough.
+pub const LATEST_SEEN_CYPHER: &str =
+    r#"
+    MATCH (n) WHERE n.last_seen IS NOT NULL
+    RETURN max(n.last_seen)
+"#;
+
+/// Edges and nodes last seen before `$cutoff` are flagged, the others unflagged.
+pub const FLAG_STALE_RELATIONS_CYPHER: &str =
+    r#"
+    MATCH ()-[r]->() WHERE r.last_seen IS NOT NULL
+    SET r.stale = r.last_seen < $cutoff
+"#;
+
+pub const FLAG_STALE_NODES_CYPHER: &str =
+    r#"
+    MATCH (n) WHERE (n:Service OR n:Datastore OR n:Operation) AND n.last_seen IS NOT NULL
+    SET n.stale = n.last_seen < $cutoff
+"#;
+
+pub const PRUNE_STALE_RELATIONS_CYPHER: &str =
+    r#"
+    MATCH ()-[r]->() WHERE r.last_seen < $cutoff
+    DELETE r
+"#;
+
+pub const PRUNE_STALE_NODES_CYPHER: &str =
+    r#"
+    MATCH (n) WHERE (n:Service OR n:Datastore OR n:Operation) AND n.last_seen < $cutoff
+    DETACH DELETE n
 "#;
@@ -1,18 +1,36 @@
-use std::{ collections::HashMap, num::NonZeroU8 };
+use std::{ collections::HashMap, num::NonZeroU8, time::Duration };
 use anyhow::{ Ok, Result };
 use async_trait::async_trait;
 use falkordb::{ FalkorClientBuilder, FalkorConnectionInfo, FalkorAsyncClient };
 use logs_to_graph::{
+    metrics::RequestMetrics,
     service_graph::ServiceGraph,
-    service_node_graph::{ Operation, ServiceName, ServiceNodeGraph, ServiceOperationId },
+    service_node_graph::{
+        Operation,
+        SeenRange,
+        ServiceName,
+        ServiceKind,
+        ServiceNode,
+        ServiceNodeGraph,
+        ServiceOperationId,
+    },
 };
 use tokio::sync::mpsc::Receiver;
 use tracing::{ debug, info, warn };
 
 use crate::consts::{
+    FLAG_STALE_NODES_CYPHER,
+    FLAG_STALE_RELATIONS_CYPHER,
+    LATEST_SEEN_CYPHER,
+    PRUNE_STALE_NODES_CYPHER,
+    PRUNE_STALE_RELATIONS_CYPHER,
+    UPSERT_DATASTORE_NODE_CYPHER,
+    UPSERT_DATASTORE_NODE_TO_OPERATION_CYPHER,
     UPSERT_OPERATION_CYPHER,
+    UPSERT_SERVICE_CONSUMES_RELATION,
     UPSERT_SERVICE_NODE_CYPHER,
     UPSERT_SERVICE_NODE_TO_OPERATION_CYPHER,
+    UPSERT_SERVICE_PUBLISHES_RELATION,
     UPSERT_SERVICE_TO_SERVICE_OPERATION_RELATION,
 };
 
@@ -22,6 +40,94 @@ mod macros;
 pub struct GraphFalkor {
     client: FalkorAsyncClient,
     graph: String,
+    /// Edges and nodes not seen for this long, counting back from the latest time anything
+    /// stored or about to be was seen at, are stale.
+    stale_after: Option<Duration>,
+    /// Deletes stale edges and nodes rather than flagging them.
+    prune_stale: bool,
+}
+
+/// An INVOKES, PUBLISHES or CONSUMES relation to upsert.
+struct Relation<'a> {
+    cypher: &'static str,
+    from_service_name: ServiceName,
+    to_service_operation_id: ServiceOperationId,
+    metrics: Option<&'a RequestMetrics>,
+    seen: Option<&'a SeenRange>,
+    /// Most spans left unmapped between the two ends, for INVOKES relations only.
+    skipped_hops: Option<usize>,
+}
+
+/// Keeps the earliest first_seen and the latest last_seen, as what is written again
+/// may have been seen over a shorter window, e.g. after a restart.
+fn seen_assignments(
+    variable: &str,
+    seen: Option<&SeenRange>,
+    params: &mut HashMap<String, String>
+) -> Vec<String> {
+    let Some(seen) = seen else {
+        return vec![];
+    };
+
+    params.insert("first_seen".into(), seen.first_seen.to_string());
+    params.insert("last_seen".into(), seen.last_seen.to_string());
+
+    vec![
+        format!(
+            "{v}.first_seen = CASE WHEN {v}.first_seen IS NULL OR {v}.first_seen > $first_seen THEN $first_seen ELSE {v}.first_seen END",
+            v = variable
+        ),
+        format!(
+            "{v}.last_seen = CASE WHEN {v}.last_seen IS NULL OR {v}.last_seen < $last_seen THEN $last_seen ELSE {v}.last_seen END",
+            v = variable
+        )
+    ]
+}
+
+/// Latest time anything of the graph was seen at.
+fn get_latest_seen(service_node_graph: &ServiceNodeGraph) -> Option<i64> {
+    service_node_graph.services
+        .values()
+        .flat_map(|service_node| {
+            service_node.seen
+                .iter()
+                .chain(service_node.operations_seen.values())
+                .chain(service_node.invokes_seen.values())
+                .chain(service_node.consumes_seen.values())
+        })
+        .map(|seen| seen.last_seen)
+        .max()
+}
+
+/// The upsert of an operation node and its parameters.
+fn get_operation_upsert(
+    service_operation_id: &str,
+    operation: &Operation,
+    metrics: Option<&RequestMetrics>,
+    seen: Option<&SeenRange>
+) -> (String, HashMap<String, String>) {
+    let mut operation_params: HashMap<String, String> = HashMap::from_iter([
+        ("label".into(), stringy!(operation.get_label())),
+        ("id".into(), stringy!(service_operation_id)),
+        ("kind".into(), stringy!(operation.get_kind())),
+    ]);
+
+    let mut upsert_operation_cypher = UPSERT_OPERATION_CYPHER.to_string();
+    for (property, value) in operation.get_properties() {
+        upsert_operation_cypher.push_str(&format!(", o.{} = ${}", property, property));
+        operation_params.insert(property.into(), stringy!(value));
+    }
+    // Figures are numbers, they are not quoted.
+    for (property, value) in metrics.map(RequestMetrics::get_properties).unwrap_or_default() {
+        upsert_operation_cypher.push_str(&format!(", o.{} = ${}", property, property));
+        operation_params.insert(property, value);
+    }
+    for assignment in seen_assignments("o", seen, &mut operation_params) {
+        upsert_operation_cypher.push_str(&format!(", {}", assignment));
+    }
+    upsert_operation_cypher.push_str("\n    RETURN o\n");
+
+    (upsert_operation_cypher, operation_params)
 }
 
 impl GraphFalkor {
@@ -31,16 +137,77 @@ impl GraphFalkor {
             .with_connection_info(info)
             .with_num_connections(max_pool) // small connection pool for concurrency
             .build().await?;
-        Ok(Self { client, graph: graph.to_string() })
+        Ok(Self { client, graph: graph.to_string(), stale_after: None, prune_stale: false })
+    }
+
+    pub fn with_stale_after(mut self, stale_after: Duration, prune_stale: bool) -> Self {
+        self.stale_after = Some(stale_after);
+        self.prune_stale = prune_stale;
+        self
     }
 
-    async fn upsert_service_node(&self, name: String) -> Result<()> {
+    /// Whether something seen at `seen` is left out of the graph, when stale entries are pruned.
+    fn is_pruned(&self, seen: Option<&SeenRange>, cutoff: Option<i64>) -> bool {
+        let is_stale = seen.zip(cutoff).is_some_and(|(seen, cutoff)| seen.last_seen < cutoff);
+        self.prune_stale && is_stale
+    }
+
+    /// Latest time anything already in the graph was seen at, e.g. by another log engine.
+    async fn get_stored_latest_seen(&self) -> Result<Option<i64>> {
+        let mut graph = self.client.select_graph(self.graph.clone());
+        let mut res = graph.query(LATEST_SEEN_CYPHER).execute().await?;
+
+        Ok(res.data.next().and_then(|row| row.first().and_then(|value| value.to_i64())))
+    }
+
+    async fn mark_stale(&self, cutoff: i64) -> Result<()> {
         let params: HashMap<String, String> = HashMap::from_iter([
+            ("cutoff".into(), cutoff.to_string()),
+        ]);
+        let cyphers = if self.prune_stale {
+            [PRUNE_STALE_RELATIONS_CYPHER, PRUNE_STALE_NODES_CYPHER]
+        } else {
+            [FLAG_STALE_RELATIONS_CYPHER, FLAG_STALE_NODES_CYPHER]
+        };
+
+        let mut graph = self.client.select_graph(self.graph.clone());
+        for cypher in cyphers {
+            let res = graph.query(cypher).with_params(&params).execute().await?;
+
+            if res.get_nodes_deleted().is_some_and(|count| count > 0) {
+                info!("Pruned {} stale nodes", res.get_nodes_deleted().unwrap_or_default());
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn upsert_service_node(&self, name: String, service_node: &ServiceNode) -> Result<()> {
+        let mut params: HashMap<String, String> = HashMap::from_iter([
             ("name".into(), stringy!(name.clone())),
             ("id".into(), stringy!(name.clone())),
+            ("kind".into(), stringy!(service_node.kind.get_label())),
         ]);
+
+        let datastore = service_node.operations.values().find_map(|operation| match operation {
+            Operation::Datastore { system, instance, .. } => Some((system.as_str(), instance.as_str())),
+            _ => None,
+        });
+        let mut cypher = if service_node.kind == ServiceKind::Datastore {
+            let (system, instance) = datastore.unwrap_or_default();
+            params.insert("system".into(), stringy!(system));
+            params.insert("instance".into(), stringy!(instance));
+            UPSERT_DATASTORE_NODE_CYPHER
+        } else {
+            UPSERT_SERVICE_NODE_CYPHER
+        }.to_string();
+        for assignment in seen_assignments("s", service_node.seen.as_ref(), &mut params) {
+            cypher.push_str(&format!(", {}", assignment));
+        }
+        cypher.push_str("\n    RETURN s\n");
+
         let mut graph = self.client.select_graph(self.graph.clone());
-        let res = graph.query(UPSERT_SERVICE_NODE_CYPHER).with_params(&params).execute().await?;
+        let res = graph.query(cypher).with_params(&params).execute().await?;
 
         if res.get_nodes_created().is_some_and(|count| count > 0) {
             info!("Created new service node: {}", name);
@@ -52,17 +219,21 @@ impl GraphFalkor {
         &self,
         name: String,
         service_operation_id: String,
-        operation: Operation
+        operation: Operation,
+        metrics: Option<&RequestMetrics>,
+        seen: Option<&SeenRange>,
+        exposes_cypher: &str
     ) -> Result<()> {
-        let operation_label = operation.get_label();
-        let operation_params: HashMap<String, String> = HashMap::from_iter([
-            ("label".into(), stringy!(operation_label.clone())),
-            ("id".into(), stringy!(service_operation_id.clone())),
-        ]);
+        let (upsert_operation_cypher, operation_params) = get_operation_upsert(
+            &service_operation_id,
+            &operation,
+            metrics,
+            seen
+        );
 
         let mut graph = self.client.select_graph(self.graph.clone());
         let upsert_operation_res = graph
-            .query(UPSERT_OPERATION_CYPHER)
+            .query(upsert_operation_cypher)
             .with_params(&operation_params)
             .execute().await?;
 
@@ -78,7 +249,7 @@ impl GraphFalkor {
         );
 
         let upsert_service_node_to_operation_res = graph
-            .query(UPSERT_SERVICE_NODE_TO_OPERATION_CYPHER)
+            .query(exposes_cypher)
             .with_params(&service_node_to_operation_relation_params)
             .execute().await?;
 
@@ -89,19 +260,43 @@ impl GraphFalkor {
         Ok(())
     }
 
+    /// `relation_cypher` is one of the INVOKES, PUBLISHES or CONSUMES upserts.
     async fn upsert_service_to_service_operation_relation(
         &self,
+        relation_cypher: &str,
         from_service_name: String,
-        to_service_operation_id: String
+        to_service_operation_id: String,
+        metrics: Option<&RequestMetrics>,
+        seen: Option<&SeenRange>,
+        skipped_hops: Option<usize>
     ) -> Result<()> {
-        let params: HashMap<String, String> = HashMap::from_iter([
+        let mut params: HashMap<String, String> = HashMap::from_iter([
             ("name".into(), stringy!(from_service_name)),
             ("id".into(), stringy!(to_service_operation_id)),
         ]);
 
+        let mut assignments = vec![];
+        for (property, value) in metrics.map(RequestMetrics::get_properties).unwrap_or_default() {
+            assignments.push(format!("r.{} = ${}", property, property));
+            params.insert(property, value);
+        }
+        assignments.extend(seen_assignments("r", seen, &mut params));
+        if let Some(skipped_hops) = skipped_hops {
+            params.insert("skipped_hops".into(), skipped_hops.to_string());
+            assignments.push(
+                "r.skipped_hops = CASE WHEN r.skipped_hops IS NULL OR r.skipped_hops < $skipped_hops THEN $skipped_hops ELSE r.skipped_hops END".into()
+            );
+        }
+
+        let mut upsert_relation_cypher = relation_cypher.to_string();
+        if !assignments.is_empty() {
+            upsert_relation_cypher.push_str(&format!("\n    SET {}", assignments.join(", ")));
+        }
+        upsert_relation_cypher.push_str("\n    RETURN r\n");
+
         let mut graph = self.client.select_graph(self.graph.clone());
         let res = graph
-            .query(UPSERT_SERVICE_TO_SERVICE_OPERATION_RELATION)
+            .query(upsert_relation_cypher)
             .with_params(&params)
             .execute().await?;
 
@@ -117,54 +312,128 @@ impl GraphFalkor {
     }
 
     async fn process(&mut self, service_node_graph: ServiceNodeGraph) -> Result<()> {
-        let mut service_to_service_relations: Vec<(ServiceName, ServiceOperationId)> = vec![];
+        let mut service_to_service_relations: Vec<Relation> = vec![];
+        // Sources lag behind one another, a cutoff from the graph just received alone would
+        // turn what the others saw last stale.
+        let cutoff = match self.stale_after {
+            Some(stale_after) => {
+                let stored_latest_seen = self.get_stored_latest_seen().await.unwrap_or_else(|e| {
+                    warn!("Failed to get when the graph was last seen: {}", e);
+                    None
+                });
+                get_latest_seen(&service_node_graph)
+                    .max(stored_latest_seen)
+                    .map(|latest_seen| latest_seen - (stale_after.as_secs() as i64))
+            }
+            None => None,
+        };
         debug!("Processing service node graph");
         for (service_name, service_node) in service_node_graph.services.iter() {
-            let upsert_service_node_res = self.upsert_service_node(service_name.clone()).await;
+            if self.is_pruned(service_node.seen.as_ref(), cutoff) {
+                continue;
+            }
 
-            if upsert_service_node_res.is_err() {
-                let e = upsert_service_node_res.unwrap_err();
+            let upsert_service_node_res = self.upsert_service_node(
+                service_name.clone(),
+                service_node
+            ).await;
+
+            if let Err(e) = upsert_service_node_res {
                 warn!("Failed to upsert service node: {}", e);
                 return Ok(());
             }
 
+            let exposes_cypher = if service_node.kind == ServiceKind::Datastore {
+                UPSERT_DATASTORE_NODE_TO_OPERATION_CYPHER
+            } else {
+                UPSERT_SERVICE_NODE_TO_OPERATION_CYPHER
+            };
+
             for (service_operation_id, operation) in service_node.operations.iter() {
+                let seen = service_node.operations_seen.get(service_operation_id);
+                if self.is_pruned(seen, cutoff) {
+                    continue;
+                }
+
                 let upsert_service_node_operation_result = self.upsert_service_node_operation(
                     service_name.clone(),
                     service_operation_id.clone(),
-                    operation.clone()
+                    operation.clone(),
+                    service_node.metrics.get(service_operation_id),
+                    seen,
+                    exposes_cypher
                 ).await;
 
-                if upsert_service_node_operation_result.is_err() {
-                    let e = upsert_service_node_operation_result.unwrap_err();
+                if let Err(e) = upsert_service_node_operation_result {
                     warn!("Failed to upsert service node operation: {}", e);
                     return Ok(());
                 }
             }
 
-            for (_, to_service_operation_ids) in service_node.invokes.iter() {
+            for (to_service_name, to_service_operation_ids) in service_node.invokes.iter() {
                 for to_service_operation_id in to_service_operation_ids.iter() {
-                    service_to_service_relations.push((
-                        service_name.clone(),
-                        to_service_operation_id.clone(),
-                    ));
+                    // Sending a message to a topic or a queue is publishing rather than invoking.
+                    let is_message = service_node_graph.services
+                        .get(to_service_name)
+                        .and_then(|to_service| to_service.operations.get(to_service_operation_id))
+                        .is_some_and(|operation| operation.is_message());
+                    let relation_cypher = if is_message {
+                        UPSERT_SERVICE_PUBLISHES_RELATION
+                    } else {
+                        UPSERT_SERVICE_TO_SERVICE_OPERATION_RELATION
+                    };
+
+                    service_to_service_relations.push(Relation {
+                        cypher: relation_cypher,
+                        from_service_name: service_name.clone(),
+                        to_service_operation_id: to_service_operation_id.clone(),
+                        metrics: service_node.invoke_metrics.get(to_service_operation_id),
+                        seen: service_node.invokes_seen.get(to_service_operation_id),
+                        skipped_hops: service_node.invokes_skipped_hops
+                            .get(to_service_operation_id)
+                            .copied(),
+                    });
+                }
+            }
+
+            for (_, destination_operation_ids) in service_node.consumes.iter() {
+                for destination_operation_id in destination_operation_ids.iter() {
+                    service_to_service_relations.push(Relation {
+                        cypher: UPSERT_SERVICE_CONSUMES_RELATION,
+                        from_service_name: service_name.clone(),
+                        to_service_operation_id: destination_operation_id.clone(),
+                        metrics: None,
+                        seen: service_node.consumes_seen.get(destination_operation_id),
+                        skipped_hops: None,
+                    });
                 }
             }
         }
 
-        for (from_service_name, to_service_operation_id) in service_to_service_relations {
+        for relation in service_to_service_relations {
+            if self.is_pruned(relation.seen, cutoff) {
+                continue;
+            }
+
             let upsert_service_to_service_operation_relation_res =
                 self.upsert_service_to_service_operation_relation(
-                    from_service_name,
-                    to_service_operation_id
+                    relation.cypher,
+                    relation.from_service_name,
+                    relation.to_service_operation_id,
+                    relation.metrics,
+                    relation.seen,
+                    relation.skipped_hops
                 ).await;
 
-            if upsert_service_to_service_operation_relation_res.is_err() {
-                let e = upsert_service_to_service_operation_relation_res.unwrap_err();
-                warn!("Failed to create an INVOKES relation between service node and a target service node operation: {}", e);
+            if let Err(e) = upsert_service_to_service_operation_relation_res {
+                warn!("Failed to create a relation between service node and a target service node operation: {}", e);
                 return Ok(());
             }
         }
+
+        if let Some(cutoff) = cutoff && let Err(e) = self.mark_stale(cutoff).await {
+            warn!("Failed to mark stale relations: {}", e);
+        }
         debug!("Done processing service_node_graph");
         Ok(())
     }
@@ -176,8 +445,7 @@ impl ServiceGraph for GraphFalkor {
         while let Some(service_node_graph) = receiver.recv().await {
             let process_res = self.process(service_node_graph).await;
 
-            if process_res.is_err() {
-                let e = process_res.unwrap_err();
+            if let Err(e) = process_res {
                 warn!("Failed to process ServiceNodeGraph: {}", e);
             }
         }
@@ -185,3 +453,25 @@ impl ServiceGraph for GraphFalkor {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use logs_to_graph::service_node_graph::Operation;
+
+    use crate::get_operation_upsert;
+
+    #[test]
+    fn should_keep_quotes_in_values_within_their_parameter() {
+        let operation = Operation::Datastore {
+            system: "firestore".into(),
+            instance: "projects/library".into(),
+            collection: Some(r#"books" }) DETACH DELETE (n) //"#.into()),
+            command: Some(r#"get\"#.into()),
+        };
+
+        let (cypher, params) = get_operation_upsert("id", &operation, None, None);
+        assert!(cypher.contains("o.datastore_collection = $datastore_collection"));
+        assert_eq!(params["datastore_collection"], r#""books\" }) DETACH DELETE (n) //""#);
+        assert_eq!(params["datastore_command"], r#""get\\""#);
+    }
+}
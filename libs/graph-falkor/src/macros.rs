@@ -1,8 +1,10 @@
+/// Quotes a value as a Cypher string. Parameters are pasted into the query as they are,
+/// so backslashes and quotes are escaped to keep values from ending the string early.
 #[macro_export]
 macro_rules! stringy {
     ($input:expr) => {
         {
-            format!("\"{}\"", $input)
+            format!("\"{}\"", $input.to_string().replace('\\', "\\\\").replace('"', "\\\""))
         }
     };
 }
@@ -0,0 +1,123 @@
+use std::path::PathBuf;
+
+use anyhow::{ Ok, Result };
+use async_trait::async_trait;
+use logs_to_graph::{
+    json_files::read_json_documents,
+    normalize::build_path_normalize_regexes,
+    service_logs::ServiceLogs,
+    service_node_graph::ServiceNodeGraph,
+    span_graph::SpanGraphBuilder,
+};
+use regex::Regex;
+use tokio::sync::mpsc::Sender;
+use tracing::{ error, info };
+
+use crate::model::JaegerExport;
+
+/// Rebuilds the graph from Jaeger JSON trace exports.
+pub struct JaegerFileServiceLogs {
+    paths: Vec<PathBuf>,
+    path_normalize_regexes: Vec<(String, Vec<Regex>)>,
+}
+
+impl JaegerFileServiceLogs {
+    pub fn new(paths: Vec<PathBuf>, custom_path_normalize_patterns: Vec<String>) -> Result<Self> {
+        let path_normalize_regexes = build_path_normalize_regexes(
+            &custom_path_normalize_patterns
+        )?;
+
+        Ok(Self { paths, path_normalize_regexes })
+    }
+}
+
+#[async_trait]
+impl ServiceLogs for JaegerFileServiceLogs {
+    async fn run(&self, sender: Sender<ServiceNodeGraph>) -> Result<()> {
+        let traces = read_json_documents::<JaegerExport>(&self.paths)?
+            .into_iter()
+            .flat_map(|export| export.data)
+            .collect::<Vec<_>>();
+        info!("Processing {} Jaeger traces", traces.len());
+
+        let mut span_graph_builder = SpanGraphBuilder::default();
+        for trace in traces.iter() {
+            for span in trace.to_graph_spans(&self.path_normalize_regexes) {
+                span_graph_builder.add_span(span);
+            }
+        }
+
+        if sender.send(span_graph_builder.service_node_graph).await.is_err() {
+            error!("Failed to send service node graph from the Service Logs to the Service Graph.");
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use logs_to_graph::{ service_logs::ServiceLogs, service_node_graph::Operation };
+    use tokio::sync::mpsc::channel;
+
+    use crate::jaeger_file_service_log::JaegerFileServiceLogs;
+
+    const EXPORT: &str =
+        r#"{
+        "data": [{
+            "traceID": "a1b2c3",
+            "spans": [
+                {
+                    "traceID": "a1b2c3",
+                    "spanID": "1",
+                    "operationName": "HTTP GET",
+                    "references": [],
+                    "tags": [
+                        { "key": "span.kind", "type": "string", "value": "client" },
+                        { "key": "http.method", "type": "string", "value": "GET" },
+                        { "key": "http.url", "type": "string", "value": "http://users/users/42?full=true" }
+                    ],
+                    "processID": "p1"
+                },
+                {
+                    "traceID": "a1b2c3",
+                    "spanID": "2",
+                    "operationName": "GET /users/:id",
+                    "references": [{ "refType": "CHILD_OF", "traceID": "a1b2c3", "spanID": "1" }],
+                    "tags": [
+                        { "key": "span.kind", "type": "string", "value": "server" },
+                        { "key": "http.method", "type": "string", "value": "GET" },
+                        { "key": "http.status_code", "type": "int64", "value": 200 },
+                        { "key": "http.url", "type": "string", "value": "http://users/users/42?full=true" }
+                    ],
+                    "processID": "p2"
+                }
+            ],
+            "processes": {
+                "p1": { "serviceName": "graphql-service", "tags": [] },
+                "p2": { "serviceName": "users-service", "tags": [] }
+            }
+        }]
+    }"#;
+
+    #[tokio::test]
+    async fn should_rebuild_the_graph_from_a_jaeger_export() {
+        let path = std::env::temp_dir().join(format!("rtc-jaeger-{}.json", std::process::id()));
+        fs::write(&path, EXPORT).unwrap();
+
+        let service_logs = JaegerFileServiceLogs::new(vec![path.clone()], vec![]).unwrap();
+        let (sender, mut receiver) = channel(32);
+        service_logs.run(sender).await.unwrap();
+        fs::remove_file(path).unwrap();
+
+        let graph = receiver.recv().await.unwrap();
+        assert_eq!(graph.services.len(), 2);
+        assert_eq!(
+            graph.services["users-service"].operations.values().next(),
+            Some(&(Operation::Http { method: "GET".into(), path: "/users/{users_id}".into() }))
+        );
+        assert_eq!(graph.services["graphql-service"].invokes["users-service"].len(), 1);
+    }
+}
@@ -0,0 +1,2 @@
+pub mod jaeger_file_service_log;
+pub mod model;
@@ -0,0 +1,118 @@
+use std::collections::HashMap;
+
+use logs_to_graph::span_graph::{ GraphSpan, SpanKind, operation_from_attributes };
+use regex::Regex;
+use serde::Deserialize;
+use serde_json::Value;
+
+const CHILD_OF: &str = "CHILD_OF";
+const UNKNOWN_SERVICE: &str = "unknown";
+
+/// The document returned by the Jaeger UI "Download JSON" and `/api/traces` endpoints.
+#[derive(Debug, Clone, Deserialize)]
+pub struct JaegerExport {
+    pub data: Vec<JaegerTrace>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JaegerTrace {
+    #[serde(rename = "traceID")]
+    pub trace_id: String,
+    pub spans: Vec<JaegerSpan>,
+    #[serde(default)]
+    pub processes: HashMap<String, Process>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Process {
+    pub service_name: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JaegerSpan {
+    #[serde(rename = "traceID")]
+    pub trace_id: String,
+    #[serde(rename = "spanID")]
+    pub span_id: String,
+    /// Only set by older Jaeger versions, newer ones use `references`.
+    #[serde(rename = "parentSpanID")]
+    pub parent_span_id: Option<String>,
+    pub operation_name: String,
+    #[serde(default)]
+    pub references: Vec<Reference>,
+    #[serde(default)]
+    pub tags: Vec<Tag>,
+    #[serde(rename = "processID")]
+    pub process_id: String,
+    /// Unix time in microseconds.
+    pub start_time: Option<i64>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Reference {
+    pub ref_type: String,
+    #[serde(rename = "spanID")]
+    pub span_id: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Tag {
+    pub key: String,
+    pub value: Value,
+}
+
+impl JaegerSpan {
+    fn parent_span_id(&self) -> Option<String> {
+        self.references
+            .iter()
+            .find(|reference| reference.ref_type == CHILD_OF)
+            .or(self.references.first())
+            .map(|reference| reference.span_id.clone())
+            .or(self.parent_span_id.clone().filter(|id| !id.is_empty()))
+    }
+
+    fn tags(&self) -> HashMap<String, String> {
+        self.tags
+            .iter()
+            .map(|tag| {
+                let value = match &tag.value {
+                    Value::String(value) => value.clone(),
+                    value => value.to_string(),
+                };
+                (tag.key.clone(), value)
+            })
+            .collect()
+    }
+}
+
+impl JaegerTrace {
+    pub fn to_graph_spans(&self, path_normalize_regexes: &[(String, Vec<Regex>)]) -> Vec<GraphSpan> {
+        self.spans
+            .iter()
+            .map(|span| {
+                let tags = span.tags();
+                let service = self.processes
+                    .get(&span.process_id)
+                    .map(|process| process.service_name.clone())
+                    .unwrap_or(UNKNOWN_SERVICE.into());
+
+                GraphSpan {
+                    trace_id: span.trace_id.clone(),
+                    span_id: span.span_id.clone(),
+                    parent_span_id: span.parent_span_id(),
+                    service,
+                    kind: tags
+                        .get("span.kind")
+                        .map(|kind| SpanKind::from_name(kind))
+                        .unwrap_or(SpanKind::Internal),
+                    operation: operation_from_attributes(&tags, path_normalize_regexes),
+                    start_time: span.start_time.map(|start_time| start_time / 1_000_000),
+                }
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,92 @@
+use std::collections::HashMap;
+
+use crate::service_node_graph::Operation;
+
+/// Attributes of the OpenTelemetry GraphQL semantic conventions.
+pub const GRAPHQL_OPERATION_TYPE_ATTRIBUTE: &str = "graphql.operation.type";
+pub const GRAPHQL_OPERATION_NAME_ATTRIBUTE: &str = "graphql.operation.name";
+
+const GRAPHQL_OPERATION_TYPES: [&str; 3] = ["query", "mutation", "subscription"];
+
+/// Whether `name` is a GraphQL name, i.e. matches `[_A-Za-z][_0-9A-Za-z]*`.
+fn is_graphql_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|first| first == '_' || first.is_ascii_alphabetic()) &&
+        chars.all(|char| char == '_' || char.is_ascii_alphanumeric())
+}
+
+/// A named GraphQL operation, anonymous operations are left to the HTTP operation.
+/// Names are set by clients, anything that is not a valid GraphQL name is ignored.
+pub fn graphql_operation(operation_type: &str, name: &str) -> Option<Operation> {
+    let operation_type = operation_type.trim().to_lowercase();
+    let name = name.trim();
+
+    if !GRAPHQL_OPERATION_TYPES.contains(&operation_type.as_str()) || !is_graphql_name(name) {
+        return None;
+    }
+
+    Some(Operation::GraphQL { operation_type, name: name.into() })
+}
+
+/// Spans of GraphQL servers are named `{operation type} {operation name}`, e.g. `mutation login`.
+pub fn graphql_operation_from_span_name(span_name: &str) -> Option<Operation> {
+    let (operation_type, name) = span_name.trim().split_once(' ')?;
+    graphql_operation(operation_type, name)
+}
+
+/// From the `graphql.operation.*` attributes, falling back to the span name.
+pub fn graphql_operation_from_attributes(
+    attributes: &HashMap<String, String>,
+    span_name: Option<&str>
+) -> Option<Operation> {
+    if
+        let (Some(operation_type), Some(name)) = (
+            attributes.get(GRAPHQL_OPERATION_TYPE_ATTRIBUTE),
+            attributes.get(GRAPHQL_OPERATION_NAME_ATTRIBUTE),
+        )
+    {
+        return graphql_operation(operation_type, name);
+    }
+
+    // Only trust the name of spans known to be GraphQL ones.
+    if !attributes.contains_key(GRAPHQL_OPERATION_TYPE_ATTRIBUTE) {
+        return None;
+    }
+    graphql_operation_from_span_name(span_name?)
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use crate::{
+        graphql::{ graphql_operation, graphql_operation_from_attributes, graphql_operation_from_span_name },
+        service_node_graph::Operation,
+    };
+
+    #[test]
+    fn should_extract_graphql_operations() {
+        assert_eq!(
+            graphql_operation_from_span_name("mutation login"),
+            Some(Operation::GraphQL { operation_type: "mutation".into(), name: "login".into() })
+        );
+        assert_eq!(graphql_operation_from_span_name("GET /graphql"), None);
+
+        let attributes = HashMap::from([
+            ("graphql.operation.type".into(), "Query".into()),
+            ("graphql.operation.name".into(), "user".into()),
+        ]);
+        assert_eq!(
+            graphql_operation_from_attributes(&attributes, None).map(|operation| operation.get_label()),
+            Some("Query user".into())
+        );
+    }
+
+    #[test]
+    fn should_only_accept_graphql_names() {
+        assert!(graphql_operation("query", "_getUser2").is_some());
+        assert_eq!(graphql_operation("query", "2users"), None);
+        assert_eq!(graphql_operation("query", "user\" }) DETACH DELETE (n) //"), None);
+        assert_eq!(graphql_operation("mutation", "log in"), None);
+    }
+}
@@ -0,0 +1,61 @@
+use crate::service_node_graph::Operation;
+
+/// Content type of gRPC requests, possibly followed by a codec, e.g. `application/grpc+proto`.
+pub const GRPC_CONTENT_TYPE: &str = "application/grpc";
+
+fn is_identifier(name: &str) -> bool {
+    !name.is_empty() &&
+        name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.') &&
+        !name.starts_with(|c: char| c.is_ascii_digit() || c == '.')
+}
+
+/// gRPC calls are `POST /package.Service/Method` requests. Without a gRPC content type,
+/// only fully qualified services with a PascalCase method are taken for gRPC,
+/// so that REST paths such as `/users/42` are left alone.
+pub fn grpc_operation(method: &str, path: &str, content_type: Option<&str>) -> Option<Operation> {
+    if !method.eq_ignore_ascii_case("POST") {
+        return None;
+    }
+
+    let path = path.split(['?', '#']).next().unwrap_or_default();
+    let (service, grpc_method) = path.strip_prefix('/')?.split_once('/')?;
+    if !is_identifier(service) || !is_identifier(grpc_method) || grpc_method.contains('.') {
+        return None;
+    }
+
+    let is_grpc_content_type = content_type.is_some_and(|content_type|
+        content_type.starts_with(GRPC_CONTENT_TYPE)
+    );
+    let looks_like_grpc =
+        service.contains('.') && grpc_method.starts_with(|c: char| c.is_ascii_uppercase());
+    if !is_grpc_content_type && !looks_like_grpc {
+        return None;
+    }
+
+    Some(Operation::Grpc { service: service.into(), method: grpc_method.into() })
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{ grpc::grpc_operation, service_node_graph::Operation };
+
+    #[test]
+    fn should_detect_grpc_calls_from_their_path() {
+        assert_eq!(
+            grpc_operation("POST", "/books.v1.Books/GetBook", None),
+            Some(Operation::Grpc { service: "books.v1.Books".into(), method: "GetBook".into() })
+        );
+        assert_eq!(
+            grpc_operation("POST", "/Books/GetBook", Some("application/grpc+proto")),
+            Some(Operation::Grpc { service: "Books".into(), method: "GetBook".into() })
+        );
+    }
+
+    #[test]
+    fn should_leave_rest_paths_alone() {
+        assert_eq!(grpc_operation("POST", "/Books/GetBook", None), None);
+        assert_eq!(grpc_operation("POST", "/users/42", None), None);
+        assert_eq!(grpc_operation("GET", "/books.v1.Books/GetBook", None), None);
+        assert_eq!(grpc_operation("POST", "/books.v1.Books/GetBook/1", None), None);
+    }
+}
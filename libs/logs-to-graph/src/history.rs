@@ -0,0 +1,495 @@
+use std::{
+    collections::HashMap,
+    fs::{ self, OpenOptions },
+    io::Write,
+    path::PathBuf,
+    time::{ Duration, SystemTime, UNIX_EPOCH },
+};
+
+use anyhow::{ Context, Result };
+use async_trait::async_trait;
+use common::types::BMArc;
+use serde::{ Deserialize, Serialize };
+use tokio::{ sync::mpsc::{ self, Receiver }, task };
+use tracing::warn;
+
+use crate::{
+    CHANNEL_BUFFER_SIZE,
+    service_graph::ServiceGraph,
+    service_node_graph::{
+        Operation,
+        SeenRange,
+        ServiceKind,
+        ServiceName,
+        ServiceNode,
+        ServiceNodeGraph,
+        ServiceOperationId,
+    },
+};
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Change {
+    Added,
+    Removed,
+}
+
+/// A node, an operation or an edge of the graph.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum GraphElement {
+    Service {
+        name: ServiceName,
+        kind: ServiceKind,
+    },
+    Operation {
+        service: ServiceName,
+        operation_id: ServiceOperationId,
+        operation: Operation,
+    },
+    /// A service invoking, or publishing to, an operation of another one.
+    Invocation {
+        from: ServiceName,
+        to: ServiceName,
+        operation_id: ServiceOperationId,
+    },
+    /// A service receiving messages from a destination.
+    Consumption {
+        consumer: ServiceName,
+        destination: ServiceName,
+        operation_id: ServiceOperationId,
+    },
+}
+
+impl GraphElement {
+    /// Identifies the element across changes, e.g. the kind of a node is not part of it.
+    fn get_key(&self) -> String {
+        match self {
+            GraphElement::Service { name, .. } => format!("service:{}", name),
+            GraphElement::Operation { operation_id, .. } => format!("operation:{}", operation_id),
+            GraphElement::Invocation { from, operation_id, .. } =>
+                format!("invocation:{}:{}", from, operation_id),
+            GraphElement::Consumption { consumer, operation_id, .. } =>
+                format!("consumption:{}:{}", consumer, operation_id),
+        }
+    }
+
+    fn add_to(&self, graph: &mut ServiceNodeGraph) {
+        fn node<'a>(graph: &'a mut ServiceNodeGraph, name: &ServiceName) -> &'a mut ServiceNode {
+            graph.services.entry(name.clone()).or_insert_with(|| ServiceNode::new(name.clone()))
+        }
+
+        match self {
+            GraphElement::Service { name, kind } => {
+                node(graph, name).kind = *kind;
+            }
+            GraphElement::Operation { service, operation_id, operation } => {
+                node(graph, service).operations.insert(operation_id.clone(), operation.clone());
+            }
+            GraphElement::Invocation { from, to, operation_id } => {
+                node(graph, from).invokes.entry(to.clone()).or_default().insert(operation_id.clone());
+            }
+            GraphElement::Consumption { consumer, destination, operation_id } => {
+                node(graph, consumer)
+                    .consumes.entry(destination.clone())
+                    .or_default()
+                    .insert(operation_id.clone());
+            }
+        }
+    }
+}
+
+/// An element appearing in, or disappearing from, the graph at a unix time in seconds.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ChangeEvent {
+    pub timestamp: i64,
+    pub change: Change,
+    pub element: GraphElement,
+}
+
+/// The elements of a graph, with when they were seen if known.
+fn get_elements(graph: &ServiceNodeGraph) -> Vec<(GraphElement, Option<SeenRange>)> {
+    let mut elements = vec![];
+
+    for service in graph.services.values() {
+        elements.push((
+            GraphElement::Service { name: service.name.clone(), kind: service.kind },
+            service.seen,
+        ));
+
+        for (operation_id, operation) in service.operations.iter() {
+            elements.push((
+                GraphElement::Operation {
+                    service: service.name.clone(),
+                    operation_id: operation_id.clone(),
+                    operation: operation.clone(),
+                },
+                service.operations_seen.get(operation_id).copied(),
+            ));
+        }
+
+        for (to, operation_ids) in service.invokes.iter() {
+            for operation_id in operation_ids {
+                elements.push((
+                    GraphElement::Invocation {
+                        from: service.name.clone(),
+                        to: to.clone(),
+                        operation_id: operation_id.clone(),
+                    },
+                    service.invokes_seen.get(operation_id).copied(),
+                ));
+            }
+        }
+
+        for (destination, operation_ids) in service.consumes.iter() {
+            for operation_id in operation_ids {
+                elements.push((
+                    GraphElement::Consumption {
+                        consumer: service.name.clone(),
+                        destination: destination.clone(),
+                        operation_id: operation_id.clone(),
+                    },
+                    service.consumes_seen.get(operation_id).copied(),
+                ));
+            }
+        }
+    }
+
+    elements
+}
+
+/// Reads the change events of a history file, oldest first.
+pub fn read_history(path: &PathBuf) -> Result<Vec<ChangeEvent>> {
+    if !path.exists() {
+        return Ok(vec![]);
+    }
+
+    let data = fs
+        ::read_to_string(path)
+        .with_context(|| format!("Failed reading history: {}", path.display()))?;
+
+    let mut events = data
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            serde_json
+                ::from_str::<ChangeEvent>(line)
+                .with_context(|| format!("Failed parsing history: {}", path.display()))
+        })
+        .collect::<Result<Vec<ChangeEvent>>>()?;
+
+    // Events are appended as they are detected, which is not always in time order.
+    events.sort_by_key(|event| event.timestamp);
+    Ok(events)
+}
+
+/// The elements present at `at`, by key.
+fn replay(events: &[ChangeEvent], at: i64) -> HashMap<String, GraphElement> {
+    let mut elements = HashMap::new();
+    for event in events.iter().take_while(|event| event.timestamp <= at) {
+        match event.change {
+            Change::Added => elements.insert(event.element.get_key(), event.element.clone()),
+            Change::Removed => elements.remove(&event.element.get_key()),
+        };
+    }
+    elements
+}
+
+fn build_graph(elements: impl IntoIterator<Item = GraphElement>) -> ServiceNodeGraph {
+    let mut graph = ServiceNodeGraph::default();
+    for element in elements {
+        element.add_to(&mut graph);
+    }
+    graph
+}
+
+/// The graph as it stood at `at`, from events sorted by time.
+pub fn graph_as_of(events: &[ChangeEvent], at: i64) -> ServiceNodeGraph {
+    build_graph(replay(events, at).into_values())
+}
+
+/// Everything that was part of the graph at some point between `from` and `to`,
+/// from events sorted by time.
+pub fn graph_between(events: &[ChangeEvent], from: i64, to: i64) -> ServiceNodeGraph {
+    let mut elements = replay(events, from);
+    for event in events {
+        if event.change == Change::Added && event.timestamp > from && event.timestamp <= to {
+            elements.insert(event.element.get_key(), event.element.clone());
+        }
+    }
+    build_graph(elements.into_values())
+}
+
+/// Records the changes of successive graphs into a JSON lines file.
+///
+/// Graphs are cumulative and a restarted source starts from scratch, so an element
+/// missing from a graph is not a removal. Elements are removed once not seen for
+/// `stale_after`, counting back from the latest time seen, never without it.
+pub struct GraphHistory {
+    path: PathBuf,
+    stale_after: Option<Duration>,
+    /// Elements currently in the graph, by key, with when they were last seen.
+    present: HashMap<String, (GraphElement, i64)>,
+    /// When elements no longer in the graph were removed, by key.
+    removed_at: HashMap<String, i64>,
+}
+
+impl GraphHistory {
+    /// Resumes from the changes already recorded in `path`, if any.
+    pub fn open(path: PathBuf, stale_after: Option<Duration>) -> Result<Self> {
+        let mut history = Self {
+            path,
+            stale_after,
+            present: HashMap::new(),
+            removed_at: HashMap::new(),
+        };
+
+        for event in read_history(&history.path)? {
+            history.apply(&event);
+        }
+
+        Ok(history)
+    }
+
+    fn apply(&mut self, event: &ChangeEvent) {
+        let key = event.element.get_key();
+        match event.change {
+            Change::Added => {
+                self.removed_at.remove(&key);
+                self.present.insert(key, (event.element.clone(), event.timestamp));
+            }
+            Change::Removed => {
+                self.present.remove(&key);
+                self.removed_at.insert(key, event.timestamp);
+            }
+        }
+    }
+
+    /// Records what changed since the previous graph. Elements with no seen times,
+    /// from sources not reporting them, are seen at `now`. Only seen times age elements
+    /// out though, as `now` is unrelated to when the logs being read were written.
+    pub fn record(&mut self, graph: &ServiceNodeGraph, now: i64) -> Result<Vec<ChangeEvent>> {
+        let elements = get_elements(graph);
+        let stale_after = self.stale_after.map(|stale_after| stale_after.as_secs() as i64);
+        let latest_seen = elements
+            .iter()
+            .filter_map(|(_, seen)| seen.map(|seen| seen.last_seen))
+            .max();
+        let cutoff = stale_after.zip(latest_seen).map(|(stale_after, latest_seen)| latest_seen - stale_after);
+
+        let mut events = vec![];
+        for (element, seen) in elements {
+            let seen = seen.unwrap_or(SeenRange { first_seen: now, last_seen: now });
+            if cutoff.is_some_and(|cutoff| seen.last_seen < cutoff) {
+                continue;
+            }
+
+            let key = element.get_key();
+            match self.present.get_mut(&key) {
+                Some((_, last_seen)) => {
+                    *last_seen = (*last_seen).max(seen.last_seen);
+                }
+                None => {
+                    // Elements coming back were seen again after their removal, not before.
+                    let timestamp = match self.removed_at.get(&key) {
+                        Some(removed_at) if seen.first_seen <= *removed_at =>
+                            seen.last_seen.max(removed_at + 1),
+                        _ => seen.first_seen,
+                    };
+                    events.push(ChangeEvent { timestamp, change: Change::Added, element });
+                }
+            }
+        }
+
+        if let Some((cutoff, stale_after)) = cutoff.zip(stale_after) {
+            for (element, last_seen) in self.present.values() {
+                if *last_seen < cutoff {
+                    // Gone once stale, not when last seen.
+                    events.push(ChangeEvent {
+                        timestamp: last_seen + stale_after,
+                        change: Change::Removed,
+                        element: element.clone(),
+                    });
+                }
+            }
+        }
+
+        events.sort_by_key(|event| event.timestamp);
+        self.append(&events)?;
+        for event in events.iter() {
+            self.apply(event);
+        }
+
+        Ok(events)
+    }
+
+    fn append(&self, events: &[ChangeEvent]) -> Result<()> {
+        if events.is_empty() {
+            return Ok(());
+        }
+
+        let mut lines = String::new();
+        for event in events {
+            lines.push_str(&serde_json::to_string(event)?);
+            lines.push('\n');
+        }
+
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| file.write_all(lines.as_bytes()))
+            .with_context(|| format!("Failed writing history: {}", self.path.display()))
+    }
+}
+
+/// Records the history of the graphs received, then passes them on to another service graph.
+pub struct HistoryServiceGraph {
+    history: GraphHistory,
+    service_graph: BMArc<dyn ServiceGraph>,
+}
+
+impl HistoryServiceGraph {
+    pub fn new(history: GraphHistory, service_graph: BMArc<dyn ServiceGraph>) -> Self {
+        Self { history, service_graph }
+    }
+}
+
+#[async_trait]
+impl ServiceGraph for HistoryServiceGraph {
+    async fn run(&mut self, mut receiver: Receiver<ServiceNodeGraph>) -> Result<()> {
+        let (sender, inner_receiver) = mpsc::channel::<ServiceNodeGraph>(CHANNEL_BUFFER_SIZE);
+        let service_graph = self.service_graph.clone();
+        let consumer = task::spawn(async move {
+            service_graph.lock().await.run(inner_receiver).await
+        });
+
+        while let Some(service_node_graph) = receiver.recv().await {
+            let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+            // The graph is still worth passing on without its history.
+            if let Err(e) = self.history.record(&service_node_graph, now) {
+                warn!("Failed to record the history of the graph: {}", e);
+            }
+
+            if sender.send(service_node_graph).await.is_err() {
+                break;
+            }
+        }
+        drop(sender);
+
+        consumer.await?
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use crate::{
+        history::{ Change, GraphHistory, graph_as_of, graph_between, read_history },
+        service_node_graph::{ Operation, ServiceNodeGraph },
+    };
+
+    const DAY: i64 = 24 * 60 * 60;
+
+    fn http(path: &str) -> Operation {
+        Operation::Http { method: "GET".into(), path: path.into() }
+    }
+
+    fn graph_seen_at(timestamp: i64, edges: &[(&str, &str, &str)]) -> ServiceNodeGraph {
+        let mut graph = ServiceNodeGraph::default();
+        graph.set_observed_at(Some(timestamp));
+        for (from, to, path) in edges {
+            graph.add_operation_to_service(to.to_string(), http(path));
+            graph.add_target_to_service(from.to_string(), to.to_string(), http(path));
+        }
+        graph
+    }
+
+    #[test]
+    fn should_rebuild_the_graph_as_of_any_time() {
+        let path = std::env::temp_dir().join(format!("rtc-history-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let mut history = GraphHistory::open(path.clone(), Some(Duration::from_secs(2 * (DAY as u64)))).unwrap();
+
+        let mut graph = graph_seen_at(DAY, &[("front", "books", "/books")]);
+        let events = history.record(&graph, 0).unwrap();
+        assert!(events.iter().all(|event| event.change == Change::Added && event.timestamp == DAY));
+
+        // Nothing changed.
+        assert!(history.record(&graph, 0).unwrap().is_empty());
+
+        graph.merge(&graph_seen_at(5 * DAY, &[("front", "users", "/users")]));
+        let events = history.record(&graph, 0).unwrap();
+        let removed: Vec<_> = events.iter().filter(|event| event.change == Change::Removed).collect();
+        // The books service, its operation and the edge to it.
+        assert_eq!(removed.len(), 3);
+        assert!(removed.iter().all(|event| event.timestamp == 3 * DAY));
+
+        // A restart resumes from the recorded history, and a graph missing elements removes none.
+        let mut history = GraphHistory::open(path.clone(), Some(Duration::from_secs(2 * (DAY as u64)))).unwrap();
+        assert!(history.record(&graph_seen_at(5 * DAY, &[]), 0).unwrap().is_empty());
+
+        let events = read_history(&path).unwrap();
+
+        let graph = graph_as_of(&events, 2 * DAY);
+        assert!(graph.services["front"].invokes.contains_key("books"));
+        assert_eq!(graph.services["books"].operations.len(), 1);
+        assert!(!graph.services.contains_key("users"));
+
+        let graph = graph_as_of(&events, 6 * DAY);
+        assert!(!graph.services["front"].invokes.contains_key("books"));
+        assert!(!graph.services.contains_key("books"));
+        assert!(graph.services["front"].invokes.contains_key("users"));
+
+        let graph = graph_between(&events, 2 * DAY, 6 * DAY);
+        assert!(graph.services["front"].invokes.contains_key("books"));
+        assert!(graph.services["front"].invokes.contains_key("users"));
+
+        assert!(graph_as_of(&events, 0).services.is_empty());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn should_not_age_elements_out_from_the_time_of_recording() {
+        let path = std::env::temp_dir().join(format!("rtc-history-now-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let mut history = GraphHistory::open(path.clone(), Some(Duration::from_secs(DAY as u64))).unwrap();
+        history.record(&graph_seen_at(0, &[("front", "books", "/books")]), 0).unwrap();
+
+        // A source without seen times, recorded long after the logs above were written.
+        let mut graph = ServiceNodeGraph::default();
+        graph.add_target_to_service("front".into(), "users".into(), http("/users"));
+        let events = history.record(&graph, 10 * DAY).unwrap();
+        assert!(!events.is_empty());
+        assert!(events.iter().all(|event| event.change == Change::Added && event.timestamp == 10 * DAY));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn should_add_elements_coming_back_after_their_removal() {
+        let path = std::env::temp_dir().join(format!("rtc-history-back-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let mut history = GraphHistory::open(path.clone(), Some(Duration::from_secs(DAY as u64))).unwrap();
+        let mut graph = graph_seen_at(0, &[("front", "books", "/books")]);
+        history.record(&graph, 0).unwrap();
+        graph.merge(&graph_seen_at(3 * DAY, &[("front", "users", "/users")]));
+        history.record(&graph, 0).unwrap();
+
+        graph.merge(&graph_seen_at(4 * DAY, &[("front", "books", "/books")]));
+        let events = history.record(&graph, 0).unwrap();
+        assert!(!events.is_empty());
+        assert!(events.iter().all(|event| event.change == Change::Added && event.timestamp == 4 * DAY));
+
+        let events = read_history(&path).unwrap();
+        assert!(!graph_as_of(&events, 2 * DAY).services["front"].invokes.contains_key("books"));
+        assert!(graph_as_of(&events, 4 * DAY).services["front"].invokes.contains_key("books"));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
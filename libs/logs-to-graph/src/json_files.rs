@@ -0,0 +1,72 @@
+use std::{ fs, io::Read, path::{ Path, PathBuf } };
+
+use anyhow::{ Context, Ok, Result };
+use serde::de::DeserializeOwned;
+use tracing::debug;
+
+/// Path that designates the standard input instead of a file.
+pub const STDIN_PATH: &str = "-";
+
+/// Expands directories into the files they contain, recursively and in a stable order.
+fn collect_files(paths: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    let mut files = vec![];
+
+    for path in paths {
+        if path.as_os_str() == STDIN_PATH || !path.is_dir() {
+            files.push(path.clone());
+            continue;
+        }
+
+        let mut children = fs
+            ::read_dir(path)
+            .with_context(|| format!("Failed reading directory: {}", path.display()))?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<std::io::Result<Vec<PathBuf>>>()?;
+        children.sort();
+
+        files.extend(collect_files(&children)?);
+    }
+
+    Ok(files)
+}
+
+fn read_input(path: &Path) -> Result<String> {
+    if path.as_os_str() == STDIN_PATH {
+        let mut data = String::new();
+        std::io::stdin().read_to_string(&mut data).context("Failed reading stdin")?;
+        return Ok(data);
+    }
+
+    fs::read_to_string(path).with_context(|| format!("Failed reading file: {}", path.display()))
+}
+
+/// Parses either a JSON array of documents or a stream of JSON documents (JSON lines).
+pub fn parse_json_documents<T: DeserializeOwned>(data: &str) -> Result<Vec<T>> {
+    if data.trim_start().starts_with('[') {
+        return Ok(serde_json::from_str::<Vec<T>>(data)?);
+    }
+
+    let documents = serde_json::Deserializer
+        ::from_str(data)
+        .into_iter::<T>()
+        .collect::<serde_json::Result<Vec<T>>>()?;
+
+    Ok(documents)
+}
+
+/// Reads every JSON document out of files, directories or "-" for stdin.
+pub fn read_json_documents<T: DeserializeOwned>(paths: &[PathBuf]) -> Result<Vec<T>> {
+    let mut documents = vec![];
+
+    for path in collect_files(paths)? {
+        let data = read_input(&path)?;
+        let parsed = parse_json_documents::<T>(&data).with_context(||
+            format!("Failed parsing JSON in {}", path.display())
+        )?;
+
+        debug!("Read {} documents from {}", parsed.len(), path.display());
+        documents.extend(parsed);
+    }
+
+    Ok(documents)
+}
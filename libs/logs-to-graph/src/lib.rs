@@ -4,12 +4,20 @@ use anyhow::{ Ok, Result };
 use common::types::BMArc;
 use service_logs::ServiceLogs;
 use tokio::{ sync::{ mpsc }, task };
+use tracing::error;
 
 use crate::{ service_graph::ServiceGraph, service_node_graph::ServiceNodeGraph };
 
+pub mod graphql;
+pub mod history;
+pub mod grpc;
+pub mod json_files;
+pub mod metrics;
 pub mod service_graph;
 pub mod service_logs;
 pub mod service_node_graph;
+pub mod normalize;
+pub mod span_graph;
 mod macros;
 
 /// Max number of messages in queue before backpressure is applied
@@ -17,13 +25,22 @@ const CHANNEL_BUFFER_SIZE: usize = 32;
 
 pub struct LogsToGraph {
     service_graph: BMArc<dyn ServiceGraph>,
-    service_logs: BMArc<dyn ServiceLogs>,
+    service_logs: Vec<BMArc<dyn ServiceLogs>>,
+}
+
+/// Merges the latest graph of every source, each source sends its whole graph every time.
+fn merge_latest(latest: &[ServiceNodeGraph]) -> ServiceNodeGraph {
+    let mut merged = ServiceNodeGraph::default();
+    for service_node_graph in latest {
+        merged.merge(service_node_graph);
+    }
+    merged
 }
 
 impl LogsToGraph {
     pub fn new(
         service_graph: BMArc<dyn ServiceGraph>,
-        service_logs: BMArc<dyn ServiceLogs>
+        service_logs: Vec<BMArc<dyn ServiceLogs>>
     ) -> Self {
         Self {
             service_graph,
@@ -33,14 +50,54 @@ impl LogsToGraph {
 
     pub async fn run(&self) -> Result<()> {
         let (sender, receiver) = mpsc::channel::<ServiceNodeGraph>(CHANNEL_BUFFER_SIZE);
+        let (source_sender, mut source_receiver) = mpsc::channel::<(usize, ServiceNodeGraph)>(
+            CHANNEL_BUFFER_SIZE
+        );
 
-        let service_logs = Arc::clone(&self.service_logs);
-        let service_graph = Arc::clone(&self.service_graph);
+        let mut producers = vec![];
+        for (index, service_logs) in self.service_logs.iter().enumerate() {
+            let service_logs = Arc::clone(service_logs);
+            let (producer_sender, mut producer_receiver) = mpsc::channel::<ServiceNodeGraph>(
+                CHANNEL_BUFFER_SIZE
+            );
+
+            producers.push(
+                task::spawn(async move { service_logs.lock().await.run(producer_sender).await })
+            );
 
-        let producer = task::spawn(async move { service_logs.lock().await.run(sender).await });
+            let source_sender = source_sender.clone();
+            task::spawn(async move {
+                while let Some(service_node_graph) = producer_receiver.recv().await {
+                    if source_sender.send((index, service_node_graph)).await.is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(source_sender);
+
+        let source_count = self.service_logs.len();
+        let merger = task::spawn(async move {
+            let mut latest = vec![ServiceNodeGraph::default(); source_count];
+            while let Some((index, service_node_graph)) = source_receiver.recv().await {
+                latest[index] = service_node_graph;
+                if sender.send(merge_latest(&latest)).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        let service_graph = Arc::clone(&self.service_graph);
         let consumer = task::spawn(async move { service_graph.lock().await.run(receiver).await });
 
-        let _ = tokio::join!(producer, consumer);
+        for producer in producers {
+            match producer.await {
+                std::result::Result::Ok(Err(e)) => error!("Log engine stopped: {:#}", e),
+                Err(e) => error!("Log engine panicked: {}", e),
+                _ => {}
+            }
+        }
+        let _ = tokio::join!(merger, consumer);
 
         Ok(())
     }
@@ -11,8 +11,6 @@ macro_rules! hash {
 
 #[cfg(test)]
 mod tests {
-    use super::super::hash;
-
     #[test]
     fn should_hash() {
         assert_eq!(
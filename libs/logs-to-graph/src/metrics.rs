@@ -0,0 +1,298 @@
+use std::collections::BTreeMap;
+
+use serde::{ Deserialize, Serialize };
+
+/// Upper bounds of the latency buckets, in milliseconds. The last bucket holds anything slower.
+const LATENCY_BUCKET_BOUNDS_MS: [f64; 14] = [
+    5.0,
+    10.0,
+    25.0,
+    50.0,
+    100.0,
+    250.0,
+    500.0,
+    1000.0,
+    2500.0,
+    5000.0,
+    10000.0,
+    30000.0,
+    60000.0,
+    f64::INFINITY,
+];
+
+const DEFAULT_SERIES_INTERVAL_SECS: u64 = 60 * 60;
+const DEFAULT_SERIES_RETENTION_SECS: u64 = 7 * 24 * 60 * 60;
+
+/// How requests are counted over time, hourly over a week by default.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct SeriesOptions {
+    pub interval_secs: u64,
+    /// Buckets older than this, counting back from the latest one, are dropped.
+    pub retention_secs: u64,
+}
+
+impl Default for SeriesOptions {
+    fn default() -> Self {
+        Self {
+            interval_secs: DEFAULT_SERIES_INTERVAL_SECS,
+            retention_secs: DEFAULT_SERIES_RETENTION_SECS,
+        }
+    }
+}
+
+/// Requests of a series interval.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+pub struct SeriesBucket {
+    pub count: u64,
+    pub error_count: u64,
+}
+
+/// What a request log tells about a single request.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RequestSample {
+    /// Unix time of the request, in seconds.
+    pub timestamp: Option<i64>,
+    /// HTTP status, `0` when unknown.
+    pub status: u16,
+    pub latency_ms: Option<f64>,
+    pub request_bytes: u64,
+    pub response_bytes: u64,
+}
+
+/// Traffic, errors and latency of the requests made to an operation, or along an edge.
+///
+/// Latencies are kept in fixed buckets rather than as samples, so that the figures of
+/// several sources add up. Percentiles are estimated from the bucket bounds.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct RequestMetrics {
+    pub count: u64,
+    /// Requests by status class, e.g. `5xx`.
+    pub status_classes: BTreeMap<String, u64>,
+    pub request_bytes: u64,
+    pub response_bytes: u64,
+    /// Requests per latency bucket, empty until a latency is recorded.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub latency_buckets: Vec<u64>,
+    #[serde(default)]
+    pub max_latency_ms: f64,
+    /// Requests per interval, keyed by the unix time at which the interval starts.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub series: BTreeMap<i64, SeriesBucket>,
+}
+
+impl RequestMetrics {
+    pub fn record(&mut self, sample: &RequestSample, series_options: &SeriesOptions) {
+        self.count += 1;
+        if sample.status > 0 {
+            *self.status_classes.entry(format!("{}xx", sample.status / 100)).or_default() += 1;
+        }
+        self.request_bytes += sample.request_bytes;
+        self.response_bytes += sample.response_bytes;
+
+        if let Some(latency_ms) = sample.latency_ms {
+            if self.latency_buckets.is_empty() {
+                self.latency_buckets = vec![0; LATENCY_BUCKET_BOUNDS_MS.len()];
+            }
+            let bucket = LATENCY_BUCKET_BOUNDS_MS.iter()
+                .position(|bound| latency_ms <= *bound)
+                .unwrap_or(LATENCY_BUCKET_BOUNDS_MS.len() - 1);
+            self.latency_buckets[bucket] += 1;
+            self.max_latency_ms = self.max_latency_ms.max(latency_ms);
+        }
+
+        if let Some(timestamp) = sample.timestamp {
+            self.record_in_series(timestamp, sample.status >= 400, series_options);
+        }
+    }
+
+    fn record_in_series(&mut self, timestamp: i64, is_error: bool, series_options: &SeriesOptions) {
+        let interval = series_options.interval_secs.max(1) as i64;
+        let bucket = self.series.entry(timestamp - timestamp.rem_euclid(interval)).or_default();
+        bucket.count += 1;
+        if is_error {
+            bucket.error_count += 1;
+        }
+
+        // Retention counts back from the latest request rather than from now, so that
+        // backfills of past windows keep their series.
+        if let Some(latest) = self.series.keys().next_back().copied() {
+            let oldest = latest - (series_options.retention_secs as i64);
+            self.series = self.series.split_off(&oldest);
+        }
+    }
+
+    pub fn merge(&mut self, other: &RequestMetrics) {
+        self.count += other.count;
+        for (status_class, count) in other.status_classes.iter() {
+            *self.status_classes.entry(status_class.clone()).or_default() += count;
+        }
+        self.request_bytes += other.request_bytes;
+        self.response_bytes += other.response_bytes;
+
+        if !other.latency_buckets.is_empty() {
+            self.latency_buckets.resize(LATENCY_BUCKET_BOUNDS_MS.len(), 0);
+            for (bucket, count) in self.latency_buckets.iter_mut().zip(other.latency_buckets.iter()) {
+                *bucket += count;
+            }
+        }
+        self.max_latency_ms = self.max_latency_ms.max(other.max_latency_ms);
+
+        for (start, other_bucket) in other.series.iter() {
+            let bucket = self.series.entry(*start).or_default();
+            bucket.count += other_bucket.count;
+            bucket.error_count += other_bucket.error_count;
+        }
+    }
+
+    /// Requests answered with a 4xx or a 5xx status.
+    pub fn get_error_count(&self) -> u64 {
+        ["4xx", "5xx"]
+            .iter()
+            .filter_map(|status_class| self.status_classes.get(*status_class))
+            .sum()
+    }
+
+    /// Upper bound of the bucket holding the given percentile, e.g. `0.95`,
+    /// capped by the slowest request seen.
+    pub fn get_latency_percentile(&self, percentile: f64) -> Option<f64> {
+        let total: u64 = self.latency_buckets.iter().sum();
+        if total == 0 {
+            return None;
+        }
+
+        let rank = ((total as f64) * percentile).ceil().max(1.0) as u64;
+        let mut seen = 0;
+        for (bucket, count) in self.latency_buckets.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return Some(LATENCY_BUCKET_BOUNDS_MS[bucket].min(self.max_latency_ms));
+            }
+        }
+
+        Some(self.max_latency_ms)
+    }
+
+    /// Figures as flat properties, e.g. for graph databases.
+    pub fn get_properties(&self) -> Vec<(String, String)> {
+        let mut properties = vec![
+            ("request_count".to_string(), self.count.to_string()),
+            ("error_count".to_string(), self.get_error_count().to_string()),
+            ("request_bytes".to_string(), self.request_bytes.to_string()),
+            ("response_bytes".to_string(), self.response_bytes.to_string()),
+        ];
+
+        for (status_class, count) in self.status_classes.iter() {
+            properties.push((format!("status_{}_count", status_class), count.to_string()));
+        }
+
+        for (name, percentile) in [("p50", 0.5), ("p95", 0.95), ("p99", 0.99)] {
+            if let Some(latency_ms) = self.get_latency_percentile(percentile) {
+                properties.push((format!("latency_{}_ms", name), latency_ms.to_string()));
+            }
+        }
+
+        // Graph databases take lists, the series is split into one list per figure.
+        if !self.series.is_empty() {
+            let list = |values: Vec<String>| format!("[{}]", values.join(", "));
+            properties.push((
+                "series_starts".to_string(),
+                list(self.series.keys().map(i64::to_string).collect()),
+            ));
+            properties.push((
+                "series_counts".to_string(),
+                list(self.series.values().map(|bucket| bucket.count.to_string()).collect()),
+            ));
+            properties.push((
+                "series_error_counts".to_string(),
+                list(self.series.values().map(|bucket| bucket.error_count.to_string()).collect()),
+            ));
+        }
+
+        properties
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::metrics::{ RequestMetrics, RequestSample, SeriesOptions };
+
+    fn sample(status: u16, latency_ms: f64) -> RequestSample {
+        RequestSample {
+            timestamp: None,
+            status,
+            latency_ms: Some(latency_ms),
+            request_bytes: 100,
+            response_bytes: 1000,
+        }
+    }
+
+    #[test]
+    fn should_count_requests_and_errors_by_status_class() {
+        let mut metrics = RequestMetrics::default();
+        for status in [200, 200, 201, 404, 503] {
+            metrics.record(&sample(status, 20.0), &SeriesOptions::default());
+        }
+
+        assert_eq!(metrics.count, 5);
+        assert_eq!(metrics.status_classes["2xx"], 3);
+        assert_eq!(metrics.get_error_count(), 2);
+        assert_eq!(metrics.response_bytes, 5000);
+    }
+
+    #[test]
+    fn should_estimate_latency_percentiles() {
+        let mut metrics = RequestMetrics::default();
+        for _ in 0..90 {
+            metrics.record(&sample(200, 8.0), &SeriesOptions::default());
+        }
+        for _ in 0..10 {
+            metrics.record(&sample(200, 700.0), &SeriesOptions::default());
+        }
+
+        assert_eq!(metrics.get_latency_percentile(0.5), Some(10.0));
+        assert_eq!(metrics.get_latency_percentile(0.95), Some(700.0));
+        assert_eq!(RequestMetrics::default().get_latency_percentile(0.5), None);
+    }
+
+    #[test]
+    fn should_add_up_merged_metrics() {
+        let mut metrics = RequestMetrics::default();
+        metrics.record(&sample(200, 8.0), &SeriesOptions::default());
+        let mut other = RequestMetrics::default();
+        other.record(&sample(500, 300.0), &SeriesOptions::default());
+        other.record(&RequestSample { status: 200, ..Default::default() }, &SeriesOptions::default());
+
+        metrics.merge(&other);
+
+        assert_eq!(metrics.count, 3);
+        assert_eq!(metrics.get_error_count(), 1);
+        assert_eq!(metrics.latency_buckets.iter().sum::<u64>(), 2);
+        assert_eq!(metrics.max_latency_ms, 300.0);
+    }
+
+    #[test]
+    fn should_count_requests_per_interval_within_the_retention() {
+        let series_options = SeriesOptions { interval_secs: 3600, retention_secs: 2 * 3600 };
+        let mut metrics = RequestMetrics::default();
+        for (timestamp, status) in [(7200, 200), (7300, 500), (10900, 200), (18000, 200)] {
+            metrics.record(
+                &(RequestSample { timestamp: Some(timestamp), status, ..Default::default() }),
+                &series_options
+            );
+        }
+
+        // The 2am bucket is more than two hours older than the 5am one.
+        assert_eq!(metrics.series.keys().copied().collect::<Vec<i64>>(), vec![10800, 18000]);
+        assert_eq!(metrics.series[&10800].count, 1);
+        assert_eq!(metrics.count, 4);
+
+        let mut other = RequestMetrics::default();
+        other.record(
+            &(RequestSample { timestamp: Some(18500), status: 503, ..Default::default() }),
+            &series_options
+        );
+        metrics.merge(&other);
+        assert_eq!(metrics.series[&18000].count, 2);
+        assert_eq!(metrics.series[&18000].error_count, 1);
+    }
+}
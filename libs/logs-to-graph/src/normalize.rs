@@ -1,9 +1,15 @@
 use anyhow::{ bail, Result, Ok };
-use logs_to_graph::service_node_graph::{ HttpMethod, HttpPath, ServiceName };
 use url::Url;
 use regex::Regex;
 
-use crate::{ consts::PATH_NORMALIZE_PATTERNS };
+/// Segment types replaced by default when normalizing paths.
+pub const PATH_NORMALIZE_PATTERNS: [(&str, &str); 2] = [
+    ("id", r"^\d+$"),
+    (
+        "uuid",
+        r"^[0-9a-fA-F]{8}-[0-9a-fA-F]{4}-[1-5][0-9a-fA-F]{3}-[89abAB][0-9a-fA-F]{3}-[0-9a-fA-F]{12}$",
+    ),
+];
 
 /// Normalizes a path by replacing id's and uuid's
 pub fn normalize_path(
@@ -16,14 +22,28 @@ pub fn normalize_path(
         None => bail!("Cannot extract segments from URL path"),
     };
 
-    let mut normalized_segments = Vec::new();
-    let mut segment_iter = path_segments.iter().peekable();
+    Ok(normalize_segments(path_segments, &path_normalize_regexes))
+}
+
+/// Same as [`normalize_path`], for a bare request path rather than a full URL.
+/// The query string, if any, is dropped.
+pub fn normalize_request_path(path: &str, path_normalize_regexes: &[(String, Vec<Regex>)]) -> String {
+    let path = path.split(['?', '#']).next().unwrap_or_default();
+    let path_segments: Vec<&str> = path.trim_start_matches('/').split('/').collect();
+
+    normalize_segments(path_segments, path_normalize_regexes)
+}
 
-    while let Some(segment) = segment_iter.next() {
+fn normalize_segments(
+    path_segments: Vec<&str>,
+    path_normalize_regexes: &[(String, Vec<Regex>)]
+) -> String {
+    let mut normalized_segments: Vec<String> = Vec::new();
+    for segment in path_segments.iter() {
         let mut matched = false;
-        for (r#type, regexes) in path_normalize_regexes.clone() {
+        for (r#type, regexes) in path_normalize_regexes.iter() {
             for regex in regexes {
-                if !regex.is_match(&segment) {
+                if !regex.is_match(segment) {
                     continue;
                 }
 
@@ -46,7 +66,7 @@ pub fn normalize_path(
         }
     }
 
-    Ok(format!("/{}", normalized_segments.join("/")))
+    format!("/{}", normalized_segments.join("/"))
 }
 
 pub fn get_default_path_normalize_regexes() -> Vec<(String, Vec<Regex>)> {
@@ -57,11 +77,31 @@ pub fn get_default_path_normalize_regexes() -> Vec<(String, Vec<Regex>)> {
     path_normalize_regexes
 }
 
+/// The default regexes, followed by the user provided patterns registered as `custom_id`.
+pub fn build_path_normalize_regexes(
+    custom_path_normalize_patterns: &[String]
+) -> Result<Vec<(String, Vec<Regex>)>> {
+    let mut path_normalize_regexes = get_default_path_normalize_regexes();
+
+    if !custom_path_normalize_patterns.is_empty() {
+        let custom_path_regexes = custom_path_normalize_patterns
+            .iter()
+            .map(|pattern| Regex::new(pattern))
+            .collect::<std::result::Result<Vec<Regex>, _>>()?;
+
+        path_normalize_regexes.push(("custom_id".into(), custom_path_regexes));
+    }
+
+    Ok(path_normalize_regexes)
+}
+
 #[cfg(test)]
 mod test {
     use regex::Regex;
 
-    use crate::{ normalize::{ get_default_path_normalize_regexes, normalize_path } };
+    use crate::{
+        normalize::{ get_default_path_normalize_regexes, normalize_path, normalize_request_path },
+    };
 
     #[test]
     fn should_replace_ids() {
@@ -100,9 +140,17 @@ mod test {
             "https://test.com/users/91366bf0-4c97-4832-af68-452c51ca38eb/books/12345/car/prefix-12345";
         let expect = "/users/{users_uuid}/books/{books_id}/car/{car_custom_id}".to_string();
         let mut path_normalize_regexes = get_default_path_normalize_regexes();
-        path_normalize_regexes.push(("custom".into(), vec![Regex::new("prefix-\\d+").unwrap()]));
+        path_normalize_regexes.push(("custom_id".into(), vec![Regex::new("prefix-\\d+").unwrap()]));
         let res = normalize_path(url, path_normalize_regexes);
         assert!(res.is_ok());
         assert_eq!(res.unwrap(), expect);
     }
+
+    #[test]
+    fn should_replace_ids_in_a_bare_path() {
+        let path = "/users/12345/books?page=2";
+        let expect = "/users/{users_id}/books".to_string();
+        let path_normalize_regexes = get_default_path_normalize_regexes();
+        assert_eq!(normalize_request_path(path, &path_normalize_regexes), expect);
+    }
 }
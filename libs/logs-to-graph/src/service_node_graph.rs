@@ -1,7 +1,7 @@
 use std::collections::{ HashMap, HashSet };
 
 use serde::{ Deserialize, Serialize };
-use crate::hash;
+use crate::{ hash, metrics::{ RequestMetrics, RequestSample, SeriesOptions } };
 
 pub type ServiceName = String;
 
@@ -17,6 +17,32 @@ pub enum Operation {
         method: HttpMethod,
         path: HttpPath,
     },
+    /// A gRPC method, e.g. `books.v1.Books/GetBook`.
+    Grpc {
+        service: String,
+        method: String,
+    },
+    /// A GraphQL operation, e.g. `mutation login`.
+    GraphQL {
+        operation_type: String,
+        name: String,
+    },
+    /// An access to a database or a cache, e.g. `SELECT` on the `users` table of a PostgreSQL instance.
+    Datastore {
+        system: String,
+        instance: String,
+        /// The table or the collection, when known.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        collection: Option<String>,
+        /// The kind of statement, e.g. `SELECT`, `GET` or `RunQuery`, when known.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        command: Option<String>,
+    },
+    /// Messages sent to a topic or a queue, exposed by the destination node.
+    Message {
+        system: String,
+        destination: String,
+    },
 }
 
 impl Operation {
@@ -24,6 +50,20 @@ impl Operation {
         match self {
             Operation::Http { method, path } =>
                 format!("http_{}_{}", method.trim().to_lowercase(), path.trim().to_lowercase()),
+            Operation::Grpc { service, method } =>
+                format!("grpc_{}_{}", service.trim(), method.trim()),
+            Operation::GraphQL { operation_type, name } =>
+                format!("graphql_{}_{}", operation_type.trim().to_lowercase(), name.trim()),
+            Operation::Datastore { system, instance, collection, command } =>
+                format!(
+                    "datastore_{}_{}_{}_{}",
+                    system.trim().to_lowercase(),
+                    instance.trim(),
+                    collection.as_deref().unwrap_or_default().trim(),
+                    command.as_deref().unwrap_or_default().trim().to_lowercase()
+                ),
+            Operation::Message { system, destination } =>
+                format!("message_{}_{}", system.trim().to_lowercase(), destination.trim()),
         }
     }
 
@@ -31,6 +71,122 @@ impl Operation {
         match self {
             Operation::Http { method, path } =>
                 format!("{} {}", method.trim().to_uppercase(), path.trim().to_lowercase()),
+            Operation::Grpc { service, method } => format!("{}/{}", service.trim(), method.trim()),
+            Operation::GraphQL { operation_type, name } => {
+                // e.g. `Mutation login`.
+                let operation_type = operation_type.trim().to_lowercase();
+                let mut chars = operation_type.chars();
+                let operation_type = chars
+                    .next()
+                    .map(|first| first.to_uppercase().chain(chars).collect::<String>())
+                    .unwrap_or_default();
+                format!("{} {}", operation_type, name.trim())
+            }
+            Operation::Datastore { system, instance, collection, command } => {
+                // e.g. `postgresql SELECT users`, or `redis 10.0.0.3` when nothing else is known.
+                let details: Vec<&str> = [command.as_deref(), collection.as_deref()]
+                    .into_iter()
+                    .flatten()
+                    .map(str::trim)
+                    .collect();
+                match details.is_empty() {
+                    true => format!("{} {}", system.trim().to_lowercase(), instance.trim()),
+                    false => format!("{} {}", system.trim().to_lowercase(), details.join(" ")),
+                }
+            }
+            Operation::Message { system, destination } =>
+                format!("{} {}", system.trim().to_lowercase(), destination.trim()),
+        }
+    }
+
+    /// The protocol of the operation, e.g. `http` or `grpc`.
+    pub fn get_kind(&self) -> &'static str {
+        match self {
+            Operation::Http { .. } => "http",
+            Operation::Grpc { .. } => "grpc",
+            Operation::GraphQL { .. } => "graphql",
+            Operation::Datastore { .. } => "datastore",
+            Operation::Message { .. } => "message",
+        }
+    }
+
+    /// The fields of the operation, prefixed by its kind, e.g. `grpc_service`.
+    pub fn get_properties(&self) -> Vec<(&'static str, String)> {
+        match self {
+            Operation::Http { method, path } =>
+                vec![("http_method", method.trim().to_uppercase()), ("http_path", path.clone())],
+            Operation::Grpc { service, method } =>
+                vec![("grpc_service", service.clone()), ("grpc_method", method.clone())],
+            Operation::GraphQL { operation_type, name } =>
+                vec![
+                    ("graphql_operation_type", operation_type.trim().to_lowercase()),
+                    ("graphql_operation_name", name.clone())
+                ],
+            Operation::Datastore { system, instance, collection, command } => {
+                let mut properties = vec![
+                    ("datastore_system", system.clone()),
+                    ("datastore_instance", instance.clone())
+                ];
+                if let Some(collection) = collection {
+                    properties.push(("datastore_collection", collection.clone()));
+                }
+                if let Some(command) = command {
+                    properties.push(("datastore_command", command.clone()));
+                }
+                properties
+            }
+            Operation::Message { system, destination } =>
+                vec![("message_system", system.clone()), ("message_destination", destination.clone())],
+        }
+    }
+
+    pub fn is_message(&self) -> bool {
+        matches!(self, Operation::Message { .. })
+    }
+}
+
+/// Generates a unique id for an operation across all services
+fn get_service_operation_id(service_name: &str, operation: &Operation) -> ServiceOperationId {
+    // Generate an operation id that is uniquely associated to the service.
+    let raw_operation_id = format!("{}_{}", service_name, operation.get_id());
+    hash!(raw_operation_id.as_str())
+}
+
+/// What a node of the graph stands for, most of them are services.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum ServiceKind {
+    #[default]
+    Service,
+    /// A public entrypoint, such as a load balancer hostname.
+    Entrypoint,
+    /// A dependency that does not log its requests, e.g. a third-party API or a database.
+    External,
+    /// A Pub/Sub topic or subscription, or a Cloud Tasks queue, between publishers and consumers.
+    Destination,
+    /// A database or a cache, such as a Cloud SQL instance, a Firestore database or Redis.
+    Datastore,
+    /// A caller of entry operations that does not log its requests, such as Cloud Scheduler,
+    /// uptime checks, browsers or mobile apps.
+    Client,
+    /// A page of one of our frontends, from the referer of the requests it makes.
+    Page,
+}
+
+impl ServiceKind {
+    pub fn is_service(&self) -> bool {
+        *self == ServiceKind::Service
+    }
+
+    pub fn get_label(&self) -> &'static str {
+        match self {
+            ServiceKind::Service => "service",
+            ServiceKind::Entrypoint => "entrypoint",
+            ServiceKind::External => "external",
+            ServiceKind::Destination => "destination",
+            ServiceKind::Datastore => "datastore",
+            ServiceKind::Client => "client",
+            ServiceKind::Page => "page",
         }
     }
 }
@@ -38,34 +194,103 @@ impl Operation {
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ServiceNode {
     pub name: ServiceName,
+    #[serde(default, skip_serializing_if = "ServiceKind::is_service")]
+    pub kind: ServiceKind,
     pub operations: HashMap<ServiceOperationId, Operation>,
     pub invokes: HashMap<ServiceName, HashSet<ServiceOperationId>>,
+    /// Destinations the service receives messages from, with their message operations.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub consumes: HashMap<ServiceName, HashSet<ServiceOperationId>>,
+    /// Figures of the requests served, by operation.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub metrics: HashMap<ServiceOperationId, RequestMetrics>,
+    /// Figures of the requests made, by invoked operation.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub invoke_metrics: HashMap<ServiceOperationId, RequestMetrics>,
+    /// When the service itself was first and last seen.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seen: Option<SeenRange>,
+    /// When the operations served were first and last seen, by operation.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub operations_seen: HashMap<ServiceOperationId, SeenRange>,
+    /// When the operations invoked, or published to, were first and last seen, by operation.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub invokes_seen: HashMap<ServiceOperationId, SeenRange>,
+    /// When the destinations consumed were first and last seen, by message operation.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub consumes_seen: HashMap<ServiceOperationId, SeenRange>,
+    /// Most spans left unmapped between the service and the operations it invoked,
+    /// e.g. HTTP client or middleware spans, by invoked operation.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub invokes_skipped_hops: HashMap<ServiceOperationId, usize>,
+}
+
+/// When something was first and last seen in logs, as unix times in seconds.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SeenRange {
+    pub first_seen: i64,
+    pub last_seen: i64,
+}
+
+impl SeenRange {
+    fn at(timestamp: i64) -> Self {
+        Self { first_seen: timestamp, last_seen: timestamp }
+    }
+
+    fn merge(&mut self, other: &SeenRange) {
+        self.first_seen = self.first_seen.min(other.first_seen);
+        self.last_seen = self.last_seen.max(other.last_seen);
+    }
+}
+
+/// Widens the range kept for `key` to `observed_at`, if the time of the observation is known.
+fn observe(
+    seen: &mut HashMap<ServiceOperationId, SeenRange>,
+    key: ServiceOperationId,
+    observed_at: Option<i64>
+) {
+    if let Some(timestamp) = observed_at {
+        seen.entry(key).or_insert(SeenRange::at(timestamp)).merge(&SeenRange::at(timestamp));
+    }
+}
+
+fn merge_seen(
+    seen: &mut HashMap<ServiceOperationId, SeenRange>,
+    other: &HashMap<ServiceOperationId, SeenRange>
+) {
+    for (operation_id, other_seen) in other.iter() {
+        seen.entry(operation_id.clone()).or_insert(*other_seen).merge(other_seen);
+    }
 }
 
 impl ServiceNode {
-    fn new(name: String) -> Self {
+    pub(crate) fn new(name: String) -> Self {
         Self {
             name,
+            kind: ServiceKind::default(),
             operations: Default::default(),
             invokes: Default::default(),
+            consumes: Default::default(),
+            metrics: Default::default(),
+            invoke_metrics: Default::default(),
+            seen: None,
+            operations_seen: Default::default(),
+            invokes_seen: Default::default(),
+            consumes_seen: Default::default(),
+            invokes_skipped_hops: Default::default(),
         }
     }
 
-    /// Generates a unique id for an operation across all services
-    fn get_service_operation_id(&self, operation: &Operation) -> String {
-        // Generate an operation id that is uniquely associated to the service.
-        let raw_operation_id = format!("{}_{}", self.name.clone(), operation.get_id());
-        let service_operation_id = hash!(raw_operation_id.as_str());
-        service_operation_id
+    fn add_operation(&mut self, operation: Operation, observed_at: Option<i64>) {
+        let operation_id = get_service_operation_id(&self.name, &operation);
+        observe(&mut self.operations_seen, operation_id.clone(), observed_at);
+        self.operations.insert(operation_id, operation);
     }
 
-    fn add_operation(&mut self, operation: Operation) {
-        let operation_id = self.get_service_operation_id(&operation);
-        self.operations.insert(operation_id.clone(), operation);
-    }
-
-    fn add_target(&mut self, name: ServiceName, operation: Operation) {
-        let service_operation_id = self.get_service_operation_id(&operation);
+    /// The invoked operation is identified as exposed by the target service.
+    fn add_target(&mut self, name: ServiceName, operation: Operation, observed_at: Option<i64>) {
+        let service_operation_id = get_service_operation_id(&name, &operation);
+        observe(&mut self.invokes_seen, service_operation_id.clone(), observed_at);
 
         self.invokes
             .entry(name)
@@ -76,34 +301,49 @@ impl ServiceNode {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct ServiceNodeGraph {
     pub services: HashMap<ServiceName, ServiceNode>,
+    /// Time of the log entry being mapped, what gets added is seen at that time.
+    #[serde(skip)]
+    pub observed_at: Option<i64>,
 }
 
-impl Default for ServiceNodeGraph {
-    fn default() -> Self {
-        Self {
-            services: Default::default(),
+impl ServiceNodeGraph {
+    /// Sets the time of the next observations, e.g. the timestamp of a log entry,
+    /// `None` when unknown.
+    pub fn set_observed_at(&mut self, observed_at: Option<i64>) {
+        self.observed_at = observed_at;
+    }
+
+    /// The node named `name`, created if needed, seen at the time of the observation.
+    fn observe_service(&mut self, name: ServiceName) -> &mut ServiceNode {
+        let observed_at = self.observed_at;
+        let service = self.services.entry(name.clone()).or_insert_with(|| ServiceNode::new(name));
+
+        if let Some(timestamp) = observed_at {
+            let seen = service.seen.get_or_insert(SeenRange::at(timestamp));
+            seen.merge(&SeenRange::at(timestamp));
         }
+
+        service
     }
-}
 
-impl ServiceNodeGraph {
+    /// Adds a ServiceNode, unless one with the same name already exists.
     pub fn add_service(&mut self, name: ServiceName) {
-        self.services.insert(name.clone(), ServiceNode::new(name.clone()));
+        self.observe_service(name);
+    }
+
+    /// Adds a node of a specific kind, or changes the kind of the existing one.
+    pub fn add_node(&mut self, name: ServiceName, kind: ServiceKind) {
+        self.observe_service(name).kind = kind;
     }
 
     /// Method that adds an operation to an existing ServiceNode.
     /// If the ServiceNode does not exist, then it'll create one on the fly and add the operation.
     pub fn add_operation_to_service(&mut self, name: ServiceName, operation: Operation) {
-        self.services
-            .entry(name.clone())
-            .and_modify(|service| {
-                service.add_operation(operation.clone());
-            })
-            .or_insert(ServiceNode::new(name.clone()))
-            .add_operation(operation);
+        let observed_at = self.observed_at;
+        self.observe_service(name).add_operation(operation, observed_at);
     }
 
     pub fn add_target_to_service(
@@ -112,22 +352,152 @@ impl ServiceNodeGraph {
         to: ServiceName,
         using_operation: Operation
     ) {
-        self.services
-            .entry(from.clone())
-            .and_modify(|service| {
-                service.add_target(to.clone(), using_operation.clone());
-            })
-            .or_insert(ServiceNode::new(from.clone()))
-            .add_target(to, using_operation);
+        let observed_at = self.observed_at;
+        self.observe_service(from).add_target(to, using_operation, observed_at);
+    }
+
+    /// Accounts for a request served by an operation of a service.
+    pub fn record_operation_request(
+        &mut self,
+        name: ServiceName,
+        operation: Operation,
+        sample: &RequestSample,
+        series_options: &SeriesOptions
+    ) {
+        let observed_at = self.observed_at;
+        let service_operation_id = get_service_operation_id(&name, &operation);
+        let service = self.observe_service(name);
+
+        service.add_operation(operation, observed_at);
+        service.metrics.entry(service_operation_id).or_default().record(sample, series_options);
+    }
+
+    /// Accounts for a request made by a service to an operation of another one.
+    pub fn record_invocation_request(
+        &mut self,
+        from: ServiceName,
+        to: ServiceName,
+        using_operation: Operation,
+        sample: &RequestSample,
+        series_options: &SeriesOptions
+    ) {
+        let observed_at = self.observed_at;
+        let service_operation_id = get_service_operation_id(&to, &using_operation);
+        let service = self.observe_service(from);
+
+        service.add_target(to, using_operation, observed_at);
+        service.invoke_metrics
+            .entry(service_operation_id)
+            .or_default()
+            .record(sample, series_options);
+    }
+
+    /// Keeps the most unmapped spans found between a service and an operation it invoked.
+    pub fn record_skipped_hops(
+        &mut self,
+        from: ServiceName,
+        to: &ServiceName,
+        using_operation: &Operation,
+        skipped_hops: usize
+    ) {
+        let service_operation_id = get_service_operation_id(to, using_operation);
+        let skipped = self.observe_service(from)
+            .invokes_skipped_hops
+            .entry(service_operation_id)
+            .or_default();
+        *skipped = (*skipped).max(skipped_hops);
+    }
+
+    /// Adds a destination node exposing its message operation, if not already there.
+    fn add_destination(&mut self, system: &str, destination: &str) -> (ServiceName, Operation) {
+        let name = format!("{}:{}", system, destination);
+        let operation = Operation::Message { system: system.into(), destination: destination.into() };
+
+        self.add_node(name.clone(), ServiceKind::Destination);
+        self.add_operation_to_service(name.clone(), operation.clone());
+
+        (name, operation)
+    }
+
+    /// Links a service to the topic or queue it sends messages to.
+    pub fn add_publisher(&mut self, publisher: ServiceName, system: &str, destination: &str) {
+        let (name, operation) = self.add_destination(system, destination);
+        self.add_target_to_service(publisher, name, operation);
+    }
+
+    /// Links a service to the topic, subscription or queue it receives messages from.
+    pub fn add_consumer(&mut self, consumer: ServiceName, system: &str, destination: &str) {
+        let (name, operation) = self.add_destination(system, destination);
+        let service_operation_id = get_service_operation_id(&name, &operation);
+        let observed_at = self.observed_at;
+        let service = self.observe_service(consumer);
+
+        observe(&mut service.consumes_seen, service_operation_id.clone(), observed_at);
+        service.consumes.entry(name).or_default().insert(service_operation_id);
+    }
+
+    /// Adds the services, operations and invokes of another graph to this one.
+    /// Operation ids only depend on the service name and the operation, so
+    /// graphs built by different sources line up. Their figures add up.
+    pub fn merge(&mut self, other: &ServiceNodeGraph) {
+        for (name, other_service) in other.services.iter() {
+            let service = self.services
+                .entry(name.clone())
+                .or_insert(ServiceNode::new(name.clone()));
+
+            if !other_service.kind.is_service() {
+                service.kind = other_service.kind;
+            }
+
+            service.operations.extend(
+                other_service.operations
+                    .iter()
+                    .map(|(id, operation)| (id.clone(), operation.clone()))
+            );
+
+            for (target, operation_ids) in other_service.invokes.iter() {
+                service.invokes
+                    .entry(target.clone())
+                    .or_default()
+                    .extend(operation_ids.iter().cloned());
+            }
+
+            for (destination, operation_ids) in other_service.consumes.iter() {
+                service.consumes
+                    .entry(destination.clone())
+                    .or_default()
+                    .extend(operation_ids.iter().cloned());
+            }
+
+            for (operation_id, metrics) in other_service.metrics.iter() {
+                service.metrics.entry(operation_id.clone()).or_default().merge(metrics);
+            }
+
+            for (operation_id, metrics) in other_service.invoke_metrics.iter() {
+                service.invoke_metrics.entry(operation_id.clone()).or_default().merge(metrics);
+            }
+
+            if let Some(other_seen) = other_service.seen.as_ref() {
+                service.seen.get_or_insert(*other_seen).merge(other_seen);
+            }
+            merge_seen(&mut service.operations_seen, &other_service.operations_seen);
+            merge_seen(&mut service.invokes_seen, &other_service.invokes_seen);
+            merge_seen(&mut service.consumes_seen, &other_service.consumes_seen);
+
+            for (operation_id, other_skipped) in other_service.invokes_skipped_hops.iter() {
+                let skipped = service.invokes_skipped_hops.entry(operation_id.clone()).or_default();
+                *skipped = (*skipped).max(*other_skipped);
+            }
+        }
     }
 }
 
-/**
- * @todo Tests incomplete
- */
 #[cfg(test)]
 mod test {
-    use crate::service_node_graph::{ Operation, ServiceNodeGraph };
+    use crate::{
+        metrics::{ RequestSample, SeriesOptions },
+        service_node_graph::{ Operation, SeenRange, ServiceKind, ServiceNodeGraph },
+    };
 
     #[test]
     fn should_be_able_to_add_a_service() {
@@ -150,11 +520,13 @@ mod test {
             method: "post".into(),
             path: "/users".into(),
         });
-        let json_string = serde_json::to_string(&graph).expect("Failed to serialize graph");
+        let json_value = serde_json::to_value(&graph).expect("Failed to serialize graph");
 
         assert_eq!(
-            json_string,
-            "{\"services\":{\"users-service\":{\"name\":\"users-service\",\"operations\":{\"13e8e5c0b41a85cfcf1f7b34ab159aa29be20f6c7631fcaff120d07c923322aa\":{\"Http\":[\"post\",\"/users\"]}},\"invokes\":{}}}}".to_string()
+            json_value,
+            serde_json::from_str::<serde_json::Value>(
+                "{\"services\":{\"users-service\":{\"name\":\"users-service\",\"operations\":{\"13e8e5c0b41a85cfcf1f7b34ab159aa29be20f6c7631fcaff120d07c923322aa\":{\"Http\":{\"method\":\"post\",\"path\":\"/users\"}}},\"invokes\":{}}}}"
+            ).unwrap()
         );
     }
 
@@ -171,11 +543,13 @@ mod test {
             method: "get".into(),
             path: "/users/{user_id}".into(),
         });
-        let json_string = serde_json::to_string(&graph).expect("Failed to serialize graph");
+        let json_value = serde_json::to_value(&graph).expect("Failed to serialize graph");
 
         assert_eq!(
-            json_string,
-            "{\"services\":{\"users-service\":{\"name\":\"users-service\",\"operations\":{\"13e8e5c0b41a85cfcf1f7b34ab159aa29be20f6c7631fcaff120d07c923322aa\":{\"Http\":[\"post\",\"/users\"]},\"8c4475ac37e66057f4a7304fd76a46fe7f0313b7626b6123cae82f024c728fba\":{\"Http\":[\"get\",\"/users/{user_id}\"]}},\"invokes\":{}}}}".to_string()
+            json_value,
+            serde_json::from_str::<serde_json::Value>(
+                "{\"services\":{\"users-service\":{\"name\":\"users-service\",\"operations\":{\"13e8e5c0b41a85cfcf1f7b34ab159aa29be20f6c7631fcaff120d07c923322aa\":{\"Http\":{\"method\":\"post\",\"path\":\"/users\"}},\"8c4475ac37e66057f4a7304fd76a46fe7f0313b7626b6123cae82f024c728fba\":{\"Http\":{\"method\":\"get\",\"path\":\"/users/{user_id}\"}}},\"invokes\":{}}}}"
+            ).unwrap()
         );
     }
 
@@ -195,7 +569,7 @@ mod test {
 
         assert_eq!(
             json_string,
-            "{\"services\":{\"users-service\":{\"name\":\"users-service\",\"operations\":{},\"invokes\":{\"books-service\":[\"3dff280d8c0d7beed2f82e8f2ffc2bcf65608fd62579ba9047137f9ec884e984\"]}}}}".to_string()
+            "{\"services\":{\"users-service\":{\"name\":\"users-service\",\"operations\":{},\"invokes\":{\"books-service\":[\"913cdb4f2bba4c9cd5f1725e42f23a45ea2860d2f7f955381961fd8cb8a8f432\"]}}}}".to_string()
         );
     }
 
@@ -212,11 +586,165 @@ mod test {
             method: "get".into(),
             path: "/users/{user_id}".into(),
         });
-        let json_string = serde_json::to_string(&graph).expect("Failed to serialize graph");
+        let json_value = serde_json::to_value(&graph).expect("Failed to serialize graph");
 
         assert_eq!(
-            json_string,
-            "{\"services\":{\"users-service\":{\"name\":\"users-service\",\"operations\":{\"13e8e5c0b41a85cfcf1f7b34ab159aa29be20f6c7631fcaff120d07c923322aa\":{\"Http\":[\"post\",\"/users\"]},\"8c4475ac37e66057f4a7304fd76a46fe7f0313b7626b6123cae82f024c728fba\":{\"Http\":[\"get\",\"/users/{user_id}\"]}},\"invokes\":{}}}}".to_string()
+            json_value,
+            serde_json::from_str::<serde_json::Value>(
+                "{\"services\":{\"users-service\":{\"name\":\"users-service\",\"operations\":{\"13e8e5c0b41a85cfcf1f7b34ab159aa29be20f6c7631fcaff120d07c923322aa\":{\"Http\":{\"method\":\"post\",\"path\":\"/users\"}},\"8c4475ac37e66057f4a7304fd76a46fe7f0313b7626b6123cae82f024c728fba\":{\"Http\":{\"method\":\"get\",\"path\":\"/users/{user_id}\"}}},\"invokes\":{}}}}"
+            ).unwrap()
+        );
+    }
+
+    #[test]
+    fn should_invoke_the_operation_exposed_by_the_target() {
+        let mut graph = ServiceNodeGraph::default();
+        let operation = Operation::Http { method: "post".into(), path: "/books".into() };
+        graph.add_operation_to_service("books-service".into(), operation.clone());
+        graph.add_target_to_service("users-service".into(), "books-service".into(), operation);
+
+        let exposed = graph.services["books-service"].operations.keys().next().unwrap();
+        assert!(graph.services["users-service"].invokes["books-service"].contains(exposed));
+    }
+
+    #[test]
+    fn should_merge_graphs_from_different_sources() {
+        let get_user = Operation::Http { method: "get".into(), path: "/users/{user_id}".into() };
+
+        let mut from_logs = ServiceNodeGraph::default();
+        from_logs.add_operation_to_service("users-service".into(), get_user.clone());
+
+        let mut from_traces = ServiceNodeGraph::default();
+        from_traces.add_operation_to_service("users-service".into(), get_user.clone());
+        from_traces.add_target_to_service("graphql-service".into(), "users-service".into(), get_user);
+
+        from_logs.merge(&from_traces);
+
+        assert_eq!(from_logs.services.len(), 2);
+        assert_eq!(from_logs.services["users-service"].operations.len(), 1);
+        let exposed = from_logs.services["users-service"].operations.keys().next().unwrap();
+        assert!(from_logs.services["graphql-service"].invokes["users-service"].contains(exposed));
+    }
+
+    #[test]
+    fn should_link_publishers_and_consumers_through_their_destination() {
+        let mut graph = ServiceNodeGraph::default();
+        graph.add_publisher("orders-service".into(), "pubsub", "orders");
+        graph.add_consumer("emails-service".into(), "pubsub", "orders");
+
+        let destination = &graph.services["pubsub:orders"];
+        assert_eq!(destination.kind, ServiceKind::Destination);
+        let exposed = destination.operations.keys().next().unwrap();
+        assert!(graph.services["orders-service"].invokes["pubsub:orders"].contains(exposed));
+        assert!(graph.services["emails-service"].consumes["pubsub:orders"].contains(exposed));
+    }
+
+    #[test]
+    fn should_add_up_the_figures_of_merged_graphs() {
+        let get_user = Operation::Http { method: "get".into(), path: "/users/{user_id}".into() };
+        let sample = RequestSample { status: 500, latency_ms: Some(12.0), ..Default::default() };
+        let series_options = SeriesOptions::default();
+
+        let mut graph = ServiceNodeGraph::default();
+        graph.record_operation_request(
+            "users-service".into(),
+            get_user.clone(),
+            &sample,
+            &series_options
+        );
+        let mut other = ServiceNodeGraph::default();
+        other.record_operation_request(
+            "users-service".into(),
+            get_user.clone(),
+            &sample,
+            &series_options
+        );
+        other.record_invocation_request(
+            "graphql-service".into(),
+            "users-service".into(),
+            get_user.clone(),
+            &sample,
+            &series_options
         );
+        graph.record_skipped_hops("graphql-service".into(), &"users-service".into(), &get_user, 1);
+        other.record_skipped_hops("graphql-service".into(), &"users-service".into(), &get_user, 3);
+
+        graph.merge(&other);
+
+        let users_service = &graph.services["users-service"];
+        let exposed = users_service.operations.keys().next().unwrap();
+        assert_eq!(users_service.metrics[exposed].count, 2);
+        assert!(graph.services["graphql-service"].invokes["users-service"].contains(exposed));
+        assert_eq!(graph.services["graphql-service"].invoke_metrics[exposed].get_error_count(), 1);
+        assert_eq!(graph.services["graphql-service"].invokes_skipped_hops[exposed], 3);
+    }
+
+    #[test]
+    fn should_keep_when_services_and_calls_were_first_and_last_seen() {
+        let get_user = Operation::Http { method: "get".into(), path: "/users/{user_id}".into() };
+
+        let mut graph = ServiceNodeGraph::default();
+        graph.set_observed_at(Some(200));
+        graph.add_target_to_service("graphql-service".into(), "users-service".into(), get_user.clone());
+        graph.set_observed_at(Some(100));
+        graph.add_target_to_service("graphql-service".into(), "users-service".into(), get_user.clone());
+
+        let mut other = ServiceNodeGraph::default();
+        other.set_observed_at(Some(300));
+        other.add_operation_to_service("users-service".into(), get_user);
+        graph.merge(&other);
+
+        let graphql_service = &graph.services["graphql-service"];
+        let invoked = graphql_service.invokes["users-service"].iter().next().unwrap();
+        assert_eq!(graphql_service.invokes_seen[invoked], SeenRange { first_seen: 100, last_seen: 200 });
+        let users_service = &graph.services["users-service"];
+        assert_eq!(users_service.seen, Some(SeenRange { first_seen: 300, last_seen: 300 }));
+        assert_eq!(users_service.operations_seen[invoked].last_seen, 300);
+    }
+
+    #[test]
+    fn should_identify_and_label_each_kind_of_operation() {
+        let operations = [
+            (
+                Operation::Grpc { service: " books.v1.Books".into(), method: "GetBook ".into() },
+                "grpc_books.v1.Books_GetBook",
+                "books.v1.Books/GetBook",
+            ),
+            (
+                Operation::GraphQL { operation_type: "Mutation".into(), name: "login".into() },
+                "graphql_mutation_login",
+                "Mutation login",
+            ),
+            (
+                Operation::Datastore {
+                    system: "PostgreSQL".into(),
+                    instance: "10.0.0.3".into(),
+                    collection: Some("users".into()),
+                    command: Some("SELECT".into()),
+                },
+                "datastore_postgresql_10.0.0.3_users_select",
+                "postgresql SELECT users",
+            ),
+            (
+                Operation::Datastore {
+                    system: "redis".into(),
+                    instance: "10.0.0.4".into(),
+                    collection: None,
+                    command: None,
+                },
+                "datastore_redis_10.0.0.4__",
+                "redis 10.0.0.4",
+            ),
+            (
+                Operation::Message { system: "GCP_PubSub".into(), destination: "orders".into() },
+                "message_gcp_pubsub_orders",
+                "gcp_pubsub orders",
+            ),
+        ];
+
+        for (operation, id, label) in operations {
+            assert_eq!(operation.get_id(), id);
+            assert_eq!(operation.get_label(), label);
+        }
     }
 }
@@ -0,0 +1,351 @@
+use std::collections::{ HashMap, VecDeque };
+
+use regex::Regex;
+
+use crate::{
+    graphql::graphql_operation_from_attributes,
+    grpc::{ GRPC_CONTENT_TYPE, grpc_operation },
+    normalize::normalize_request_path,
+    service_node_graph::{ Operation, ServiceName, ServiceNodeGraph },
+};
+
+/// Request content type, as recorded by the OpenTelemetry and OpenTracing instrumentations.
+const CONTENT_TYPE_ATTRIBUTES: [&str; 2] = [
+    "http.request.header.content-type",
+    "http.request.header.content_type",
+];
+
+/// Max number of traces kept in memory while waiting for the other side of a span relationship.
+const MAX_BUFFERED_TRACES: usize = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpanKind {
+    Server,
+    Client,
+    Producer,
+    Consumer,
+    Internal,
+}
+
+impl SpanKind {
+    /// Parses the kind names used by Jaeger and Zipkin, e.g. "server" or "CLIENT".
+    pub fn from_name(name: &str) -> Self {
+        match name.to_ascii_lowercase().as_str() {
+            "server" => SpanKind::Server,
+            "client" => SpanKind::Client,
+            "producer" => SpanKind::Producer,
+            "consumer" => SpanKind::Consumer,
+            _ => SpanKind::Internal,
+        }
+    }
+}
+
+fn first_attribute<'a>(attributes: &'a HashMap<String, String>, keys: &[&str]) -> Option<&'a String> {
+    keys.iter().find_map(|key| attributes.get(*key))
+}
+
+/// Derives the operation from GraphQL, HTTP and RPC span attributes, covering the current
+/// OpenTelemetry semantic conventions as well as the older OpenTracing and Zipkin tags.
+pub fn operation_from_attributes(
+    attributes: &HashMap<String, String>,
+    path_normalize_regexes: &[(String, Vec<Regex>)]
+) -> Option<Operation> {
+    if let Some(operation) = graphql_operation_from_attributes(attributes, None) {
+        return Some(operation);
+    }
+
+    let content_type = first_attribute(attributes, &CONTENT_TYPE_ATTRIBUTES).map(|value| value.as_str());
+
+    // `rpc.*` attributes are set for every RPC system, e.g. `java_rmi` or `apache_dubbo`,
+    // the others are left to their HTTP attributes, if any.
+    let is_grpc = match attributes.get("rpc.system") {
+        Some(rpc_system) => rpc_system == "grpc",
+        None => content_type.is_some_and(|content_type| content_type.starts_with(GRPC_CONTENT_TYPE)),
+    };
+    if
+        let (true, Some(rpc_service), Some(rpc_method)) = (
+            is_grpc,
+            attributes.get("rpc.service"),
+            attributes.get("rpc.method"),
+        )
+    {
+        return Some(Operation::Grpc { service: rpc_service.clone(), method: rpc_method.clone() });
+    }
+
+    let method = first_attribute(attributes, &["http.request.method", "http.method"])?;
+
+    // The route is already templated by the instrumentation, no need to normalize it.
+    if let Some(route) = attributes.get("http.route") {
+        return Some(
+            grpc_operation(method, route, content_type).unwrap_or(Operation::Http {
+                method: method.clone(),
+                path: route.clone(),
+            })
+        );
+    }
+
+    let path = match first_attribute(attributes, &["url.path", "http.target", "http.path"]) {
+        Some(path) => path.clone(),
+        None => {
+            let url = first_attribute(attributes, &["url.full", "http.url"])?;
+            let without_scheme = url
+                .split_once("://")
+                .map(|(_, rest)| rest)
+                .unwrap_or(url);
+            without_scheme
+                .find('/')
+                .map(|index| without_scheme[index..].to_string())
+                .unwrap_or("/".into())
+        }
+    };
+
+    if let Some(operation) = grpc_operation(method, &path, content_type) {
+        return Some(operation);
+    }
+
+    Some(Operation::Http {
+        method: method.clone(),
+        path: normalize_request_path(&path, path_normalize_regexes),
+    })
+}
+
+/// A span reduced to what is needed to map services, regardless of the tracing format it came from.
+#[derive(Debug, Clone)]
+pub struct GraphSpan {
+    pub trace_id: String,
+    pub span_id: String,
+    pub parent_span_id: Option<String>,
+    pub service: ServiceName,
+    pub kind: SpanKind,
+    /// The operation handled by this span, if it could be derived from its attributes.
+    pub operation: Option<Operation>,
+    /// Unix time in seconds at which the span started, what it maps is seen at that time.
+    pub start_time: Option<i64>,
+}
+
+impl GraphSpan {
+    /// Server and consumer spans describe an operation exposed by their own service.
+    fn exposes_operation(&self) -> bool {
+        matches!(self.kind, SpanKind::Server | SpanKind::Consumer)
+    }
+}
+
+/// Builds a [`ServiceNodeGraph`] out of spans, in whatever order they arrive.
+///
+/// Spans are buffered per trace, an edge is created as soon as both a span and its
+/// parent are known and belong to different services.
+///
+/// Zipkin B3 instrumentations report the client and the server half of a call
+/// under the same span id, those halves are linked to each other and a child
+/// of such an id is attached to the half of its own service when possible.
+#[derive(Debug, Default)]
+pub struct SpanGraphBuilder {
+    pub service_node_graph: ServiceNodeGraph,
+    traces: HashMap<String, HashMap<String, Vec<GraphSpan>>>,
+    trace_order: VecDeque<String>,
+}
+
+/// Picks which span, out of the ones sharing an id, a child belongs to.
+fn select_parent<'a>(candidates: &'a [GraphSpan], child: &GraphSpan) -> Option<&'a GraphSpan> {
+    candidates
+        .iter()
+        .find(|candidate| candidate.service == child.service)
+        .or(candidates.iter().find(|candidate| candidate.exposes_operation()))
+        .or(candidates.first())
+}
+
+impl SpanGraphBuilder {
+    pub fn add_span(&mut self, span: GraphSpan) {
+        self.service_node_graph.set_observed_at(span.start_time);
+        self.service_node_graph.add_service(span.service.clone());
+
+        if span.exposes_operation() && let Some(operation) = span.operation.clone() {
+            self.service_node_graph.add_operation_to_service(span.service.clone(), operation);
+        }
+
+        if !self.traces.contains_key(&span.trace_id) {
+            self.trace_order.push_back(span.trace_id.clone());
+            if self.trace_order.len() > MAX_BUFFERED_TRACES && let Some(oldest) = self.trace_order.pop_front() {
+                self.traces.remove(&oldest);
+            }
+        }
+
+        let spans = self.traces.entry(span.trace_id.clone()).or_default();
+
+        // The other half of a shared span.
+        for shared in spans.get(&span.span_id).into_iter().flatten() {
+            match (shared.kind, span.kind) {
+                (SpanKind::Client, SpanKind::Server) => {
+                    Self::link(&mut self.service_node_graph, shared, &span);
+                }
+                (SpanKind::Server, SpanKind::Client) => {
+                    Self::link(&mut self.service_node_graph, &span, shared);
+                }
+                _ => {}
+            }
+        }
+
+        // The parent arrived first.
+        if
+            let Some(parent) = span.parent_span_id
+                .as_ref()
+                .and_then(|id| spans.get(id))
+                .and_then(|candidates| select_parent(candidates, &span))
+        {
+            Self::link(&mut self.service_node_graph, parent, &span);
+        }
+
+        spans.entry(span.span_id.clone()).or_default().push(span.clone());
+
+        // Children that arrived before their parent.
+        let candidates = &spans[&span.span_id];
+        let added = &candidates[candidates.len() - 1];
+        for child in spans.values().flatten() {
+            if
+                child.parent_span_id.as_ref() == Some(&span.span_id) &&
+                select_parent(candidates, child).is_some_and(|parent| std::ptr::eq(parent, added))
+            {
+                Self::link(&mut self.service_node_graph, &span, child);
+            }
+        }
+    }
+
+    fn link(service_node_graph: &mut ServiceNodeGraph, parent: &GraphSpan, child: &GraphSpan) {
+        if parent.service == child.service {
+            return;
+        }
+
+        let Some(operation) = child.operation.clone() else {
+            return;
+        };
+
+        // The call was made when the child started, the parent may not record a start time.
+        service_node_graph.set_observed_at(child.start_time.or(parent.start_time));
+        service_node_graph.add_target_to_service(
+            parent.service.clone(),
+            child.service.clone(),
+            operation
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use crate::{
+        service_node_graph::{ Operation, SeenRange },
+        span_graph::{ GraphSpan, SpanGraphBuilder, SpanKind, operation_from_attributes },
+    };
+
+    fn span(
+        span_id: &str,
+        parent_span_id: Option<&str>,
+        service: &str,
+        kind: SpanKind,
+        operation: Option<Operation>
+    ) -> GraphSpan {
+        GraphSpan {
+            trace_id: "trace".into(),
+            span_id: span_id.into(),
+            parent_span_id: parent_span_id.map(String::from),
+            service: service.into(),
+            kind,
+            operation,
+            start_time: Some(1_700_000_000),
+        }
+    }
+
+    fn get_users() -> Operation {
+        Operation::Http { method: "GET".into(), path: "/users/{users_id}".into() }
+    }
+
+    #[test]
+    fn should_link_a_client_span_to_the_server_span_of_another_service() {
+        let mut builder = SpanGraphBuilder::default();
+        builder.add_span(span("1", None, "graphql-service", SpanKind::Client, Some(get_users())));
+        builder.add_span(span("2", Some("1"), "users-service", SpanKind::Server, Some(get_users())));
+
+        let graph = builder.service_node_graph;
+        assert_eq!(graph.services["users-service"].operations.len(), 1);
+        assert!(graph.services["graphql-service"].operations.is_empty());
+        assert_eq!(graph.services["graphql-service"].invokes["users-service"].len(), 1);
+    }
+
+    #[test]
+    fn should_link_spans_received_out_of_order() {
+        let mut builder = SpanGraphBuilder::default();
+        builder.add_span(span("2", Some("1"), "users-service", SpanKind::Server, Some(get_users())));
+        builder.add_span(span("1", None, "graphql-service", SpanKind::Client, Some(get_users())));
+
+        let graph = builder.service_node_graph;
+        assert_eq!(graph.services["graphql-service"].invokes["users-service"].len(), 1);
+    }
+
+    #[test]
+    fn should_not_link_spans_of_the_same_service() {
+        let mut builder = SpanGraphBuilder::default();
+        builder.add_span(span("1", None, "users-service", SpanKind::Server, Some(get_users())));
+        builder.add_span(span("2", Some("1"), "users-service", SpanKind::Internal, None));
+
+        let graph = builder.service_node_graph;
+        assert!(graph.services["users-service"].invokes.is_empty());
+    }
+
+    #[test]
+    fn should_link_the_halves_of_a_shared_span() {
+        let mut builder = SpanGraphBuilder::default();
+        builder.add_span(span("1", None, "users-service", SpanKind::Server, Some(get_users())));
+        builder.add_span(span("1", None, "graphql-service", SpanKind::Client, Some(get_users())));
+        // A call made by users-service while handling the shared span.
+        builder.add_span(span("2", Some("1"), "books-service", SpanKind::Server, Some(get_users())));
+
+        let graph = builder.service_node_graph;
+        assert_eq!(graph.services["graphql-service"].invokes["users-service"].len(), 1);
+        assert_eq!(graph.services["users-service"].invokes["books-service"].len(), 1);
+        assert!(!graph.services["graphql-service"].invokes.contains_key("books-service"));
+    }
+
+    #[test]
+    fn should_see_services_and_calls_when_their_spans_started() {
+        let mut builder = SpanGraphBuilder::default();
+        let mut client = span("1", None, "graphql-service", SpanKind::Client, Some(get_users()));
+        client.start_time = Some(100);
+        let mut server = span("2", Some("1"), "users-service", SpanKind::Server, Some(get_users()));
+        server.start_time = Some(101);
+        builder.add_span(server);
+        builder.add_span(client);
+
+        let graph = builder.service_node_graph;
+        // Still running when its call reached users-service.
+        assert_eq!(graph.services["graphql-service"].seen, Some(SeenRange { first_seen: 100, last_seen: 101 }));
+        assert_eq!(graph.services["users-service"].seen, Some(SeenRange { first_seen: 101, last_seen: 101 }));
+        let invoked = graph.services["graphql-service"].invokes["users-service"].iter().next().unwrap();
+        assert_eq!(
+            graph.services["graphql-service"].invokes_seen[invoked],
+            SeenRange { first_seen: 101, last_seen: 101 }
+        );
+    }
+
+    #[test]
+    fn should_only_take_grpc_rpc_spans_for_grpc() {
+        let rpc = |extra: &[(&str, &str)]| {
+            let attributes: HashMap<String, String> = [("rpc.service", "books.Books"), ("rpc.method", "GetBook")]
+                .iter()
+                .chain(extra)
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect();
+            operation_from_attributes(&attributes, &[])
+        };
+        let get_book = Operation::Grpc { service: "books.Books".into(), method: "GetBook".into() };
+
+        assert_eq!(rpc(&[("rpc.system", "grpc")]), Some(get_book.clone()));
+        assert_eq!(rpc(&[("http.request.header.content-type", "application/grpc")]), Some(get_book));
+        assert_eq!(rpc(&[]), None);
+        assert_eq!(rpc(&[("rpc.system", "java_rmi")]), None);
+        assert_eq!(
+            rpc(&[("rpc.system", "connect_rpc"), ("http.request.method", "POST"), ("url.path", "/books")]),
+            Some(Operation::Http { method: "POST".into(), path: "/books".into() })
+        );
+    }
+}
@@ -0,0 +1,164 @@
+use std::collections::HashMap;
+
+use logs_to_graph::span_graph::{ GraphSpan, SpanKind, operation_from_attributes };
+use opentelemetry_proto::tonic::{
+    common::v1::{ KeyValue, any_value::Value },
+    trace::v1::{ ResourceSpans, Span, span::SpanKind as OtlpSpanKind },
+};
+use regex::Regex;
+
+/// Service name the OpenTelemetry SDKs fall back to when none is configured.
+const UNKNOWN_SERVICE: &str = "unknown_service";
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+fn attributes_map(attributes: &[KeyValue]) -> HashMap<String, String> {
+    attributes
+        .iter()
+        .filter_map(|attribute| {
+            let value = match attribute.value.as_ref()?.value.as_ref()? {
+                Value::StringValue(value) => value.clone(),
+                Value::BoolValue(value) => value.to_string(),
+                Value::IntValue(value) => value.to_string(),
+                Value::DoubleValue(value) => value.to_string(),
+                _ => {
+                    return None;
+                }
+            };
+            Some((attribute.key.clone(), value))
+        })
+        .collect()
+}
+
+fn span_kind(kind: i32) -> SpanKind {
+    match OtlpSpanKind::try_from(kind) {
+        Ok(OtlpSpanKind::Server) => SpanKind::Server,
+        Ok(OtlpSpanKind::Client) => SpanKind::Client,
+        Ok(OtlpSpanKind::Producer) => SpanKind::Producer,
+        Ok(OtlpSpanKind::Consumer) => SpanKind::Consumer,
+        _ => SpanKind::Internal,
+    }
+}
+
+fn convert_span(
+    span: &Span,
+    service: &str,
+    path_normalize_regexes: &[(String, Vec<Regex>)]
+) -> GraphSpan {
+    let attributes = attributes_map(&span.attributes);
+
+    GraphSpan {
+        trace_id: to_hex(&span.trace_id),
+        span_id: to_hex(&span.span_id),
+        parent_span_id: (!span.parent_span_id.is_empty()).then(|| to_hex(&span.parent_span_id)),
+        service: service.to_string(),
+        kind: span_kind(span.kind),
+        operation: operation_from_attributes(&attributes, path_normalize_regexes),
+        // Left at zero when unknown.
+        start_time: (span.start_time_unix_nano > 0).then_some(
+            (span.start_time_unix_nano / 1_000_000_000) as i64
+        ),
+    }
+}
+
+/// Flattens OTLP resource spans, the service comes from the `service.name` resource attribute.
+pub fn convert_resource_spans(
+    resource_spans: &[ResourceSpans],
+    path_normalize_regexes: &[(String, Vec<Regex>)]
+) -> Vec<GraphSpan> {
+    let mut spans = vec![];
+
+    for resource_span in resource_spans {
+        let resource_attributes = resource_span.resource
+            .as_ref()
+            .map(|resource| attributes_map(&resource.attributes))
+            .unwrap_or_default();
+        let service = resource_attributes
+            .get("service.name")
+            .map(|name| name.as_str())
+            .unwrap_or(UNKNOWN_SERVICE);
+
+        for scope_span in resource_span.scope_spans.iter() {
+            for span in scope_span.spans.iter() {
+                spans.push(convert_span(span, service, path_normalize_regexes));
+            }
+        }
+    }
+
+    spans
+}
+
+#[cfg(test)]
+mod test {
+    use logs_to_graph::{
+        normalize::get_default_path_normalize_regexes,
+        service_node_graph::Operation,
+        span_graph::SpanKind,
+    };
+    use opentelemetry_proto::tonic::collector::trace::v1::ExportTraceServiceRequest;
+
+    use crate::convert::convert_resource_spans;
+
+    const REQUEST: &str =
+        r#"{
+        "resourceSpans": [{
+            "resource": { "attributes": [{ "key": "service.name", "value": { "stringValue": "users-service" } }] },
+            "scopeSpans": [{
+                "spans": [
+                    {
+                        "traceId": "5b8efff798038103d269b633813fc60c",
+                        "spanId": "eee19b7ec3c1b174",
+                        "parentSpanId": "eee19b7ec3c1b173",
+                        "name": "GET /users/{id}",
+                        "kind": 2,
+                        "attributes": [
+                            { "key": "http.request.method", "value": { "stringValue": "GET" } },
+                            { "key": "url.path", "value": { "stringValue": "/users/42" } }
+                        ]
+                    },
+                    {
+                        "traceId": "5b8efff798038103d269b633813fc60c",
+                        "spanId": "eee19b7ec3c1b175",
+                        "parentSpanId": "eee19b7ec3c1b174",
+                        "name": "books.Books/GetBook",
+                        "kind": 3,
+                        "attributes": [
+                            { "key": "rpc.system", "value": { "stringValue": "grpc" } },
+                            { "key": "rpc.service", "value": { "stringValue": "books.Books" } },
+                            { "key": "rpc.method", "value": { "stringValue": "GetBook" } }
+                        ]
+                    }
+                ]
+            }]
+        }]
+    }"#;
+
+    #[test]
+    fn should_convert_otlp_json_spans() {
+        let request: ExportTraceServiceRequest = serde_json::from_str(REQUEST).unwrap();
+        let spans = convert_resource_spans(
+            &request.resource_spans,
+            &get_default_path_normalize_regexes()
+        );
+
+        assert_eq!(spans.len(), 2);
+        assert_eq!(spans[0].service, "users-service");
+        assert_eq!(spans[0].trace_id, "5b8efff798038103d269b633813fc60c");
+        assert_eq!(spans[0].parent_span_id, Some("eee19b7ec3c1b173".into()));
+        assert_eq!(spans[0].kind, SpanKind::Server);
+        assert_eq!(
+            spans[0].operation,
+            Some(Operation::Http { method: "GET".into(), path: "/users/{users_id}".into() })
+        );
+        assert_eq!(spans[1].kind, SpanKind::Client);
+        assert_eq!(
+            spans[1].operation,
+            Some(Operation::Grpc { service: "books.Books".into(), method: "GetBook".into() })
+        );
+    }
+}
@@ -0,0 +1,2 @@
+mod convert;
+pub mod otlp_service_log;
@@ -0,0 +1,342 @@
+use std::{ net::SocketAddr, sync::Arc };
+
+use anyhow::{ Context, Ok, Result };
+use async_trait::async_trait;
+use axum::{
+    Router,
+    body::Bytes,
+    extract::State,
+    http::{ HeaderMap, StatusCode, header::{ CONTENT_ENCODING, CONTENT_TYPE } },
+    response::{ IntoResponse, Response },
+    routing::post,
+};
+use common::{ encoding::{ BodyError, MAX_DECODED_BODY, decode_body }, marc, types::MArc };
+use logs_to_graph::{
+    normalize::build_path_normalize_regexes,
+    service_logs::ServiceLogs,
+    service_node_graph::ServiceNodeGraph,
+    span_graph::SpanGraphBuilder,
+};
+use opentelemetry_proto::tonic::collector::trace::v1::{
+    ExportTraceServiceRequest,
+    ExportTraceServiceResponse,
+    trace_service_server::{ TraceService, TraceServiceServer },
+};
+use prost::Message;
+use regex::Regex;
+use tokio::{ net::TcpListener, sync::mpsc::Sender };
+use tonic::codec::CompressionEncoding;
+use tracing::{ debug, error, info };
+
+use crate::convert::convert_resource_spans;
+
+const TRACES_PATH: &str = "/v1/traces";
+const JSON_CONTENT_TYPE: &str = "application/json";
+const PROTOBUF_CONTENT_TYPE: &str = "application/x-protobuf";
+
+/// Receives spans pushed by OpenTelemetry SDKs and collectors, over OTLP/HTTP and OTLP/gRPC.
+pub struct OtlpServiceLogs {
+    http_address: SocketAddr,
+    grpc_address: SocketAddr,
+    path_normalize_regexes: Vec<(String, Vec<Regex>)>,
+}
+
+impl OtlpServiceLogs {
+    pub fn new(
+        http_address: SocketAddr,
+        grpc_address: SocketAddr,
+        custom_path_normalize_patterns: Vec<String>
+    ) -> Result<Self> {
+        let path_normalize_regexes = build_path_normalize_regexes(
+            &custom_path_normalize_patterns
+        )?;
+
+        Ok(Self {
+            http_address,
+            grpc_address,
+            path_normalize_regexes,
+        })
+    }
+}
+
+/// State shared by both receivers, spans of a trace may come through either of them.
+#[derive(Clone)]
+struct Collector {
+    span_graph_builder: MArc<SpanGraphBuilder>,
+    path_normalize_regexes: Arc<Vec<(String, Vec<Regex>)>>,
+    sender: Sender<ServiceNodeGraph>,
+}
+
+impl Collector {
+    async fn export(&self, request: ExportTraceServiceRequest) {
+        let spans = convert_resource_spans(&request.resource_spans, &self.path_normalize_regexes);
+        if spans.is_empty() {
+            return;
+        }
+
+        debug!("Received {} spans", spans.len());
+
+        let service_node_graph = {
+            let mut span_graph_builder = self.span_graph_builder.lock().await;
+            for span in spans {
+                span_graph_builder.add_span(span);
+            }
+            span_graph_builder.service_node_graph.clone()
+        };
+
+        if self.sender.send(service_node_graph).await.is_err() {
+            error!("Failed to send service node graph from the Service Logs to the Service Graph.");
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl TraceService for Collector {
+    async fn export(
+        &self,
+        request: tonic::Request<ExportTraceServiceRequest>
+    ) -> std::result::Result<tonic::Response<ExportTraceServiceResponse>, tonic::Status> {
+        Collector::export(self, request.into_inner()).await;
+        std::result::Result::Ok(tonic::Response::new(ExportTraceServiceResponse::default()))
+    }
+}
+
+fn is_json(headers: &HeaderMap) -> bool {
+    headers
+        .get(CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .is_some_and(|content_type| content_type.starts_with(JSON_CONTENT_TYPE))
+}
+
+/// OTLP/HTTP answers in the encoding of the request.
+async fn export_traces(
+    State(collector): State<Collector>,
+    headers: HeaderMap,
+    body: Bytes
+) -> Response {
+    let json = is_json(&headers);
+
+    let content_encoding = headers
+        .get(CONTENT_ENCODING)
+        .and_then(|content_encoding| content_encoding.to_str().ok());
+    let body = match decode_body(content_encoding, &body) {
+        std::result::Result::Ok(body) => body,
+        Err(BodyError::UnsupportedEncoding(encoding)) => {
+            let message = format!("Unsupported content encoding: {}", encoding);
+            return (StatusCode::UNSUPPORTED_MEDIA_TYPE, message).into_response();
+        }
+        Err(BodyError::Malformed(e)) => {
+            return (StatusCode::BAD_REQUEST, e.to_string()).into_response();
+        }
+        Err(BodyError::TooLarge) => {
+            let message = format!("Decompressed body larger than {} bytes", MAX_DECODED_BODY);
+            return (StatusCode::PAYLOAD_TOO_LARGE, message).into_response();
+        }
+    };
+
+    let request = if json {
+        serde_json::from_slice::<ExportTraceServiceRequest>(&body).map_err(|e| e.to_string())
+    } else {
+        ExportTraceServiceRequest::decode(body.as_slice()).map_err(|e| e.to_string())
+    };
+
+    let request = match request {
+        std::result::Result::Ok(request) => request,
+        Err(e) => {
+            return (StatusCode::BAD_REQUEST, e).into_response();
+        }
+    };
+
+    collector.export(request).await;
+
+    let response = ExportTraceServiceResponse::default();
+    if json {
+        ([(CONTENT_TYPE, JSON_CONTENT_TYPE)], "{}").into_response()
+    } else {
+        ([(CONTENT_TYPE, PROTOBUF_CONTENT_TYPE)], response.encode_to_vec()).into_response()
+    }
+}
+
+#[async_trait]
+impl ServiceLogs for OtlpServiceLogs {
+    async fn run(&self, sender: Sender<ServiceNodeGraph>) -> Result<()> {
+        let collector = Collector {
+            span_graph_builder: marc!(SpanGraphBuilder::default()),
+            path_normalize_regexes: Arc::new(self.path_normalize_regexes.clone()),
+            sender,
+        };
+
+        let http_listener = TcpListener::bind(self.http_address).await.with_context(||
+            format!("Failed binding OTLP/HTTP receiver on {}", self.http_address)
+        )?;
+        let router = Router::new()
+            .route(TRACES_PATH, post(export_traces))
+            .with_state(collector.clone());
+
+        let trace_service = TraceServiceServer::new(collector)
+            .accept_compressed(CompressionEncoding::Gzip)
+            .send_compressed(CompressionEncoding::Gzip);
+        let grpc_server = tonic::transport::Server
+            ::builder()
+            .add_service(trace_service)
+            .serve(self.grpc_address);
+
+        info!(
+            "Receiving OTLP traces on {} (HTTP) and {} (gRPC)",
+            self.http_address,
+            self.grpc_address
+        );
+
+        tokio::try_join!(
+            async { axum::serve(http_listener, router).await.context("OTLP/HTTP receiver failed") },
+            async { grpc_server.await.context("OTLP/gRPC receiver failed") }
+        )?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{ io::Write, net::{ SocketAddr, TcpListener }, time::Duration };
+
+    use flate2::{ Compression, write::GzEncoder };
+    use logs_to_graph::{ service_logs::ServiceLogs, service_node_graph::ServiceNodeGraph };
+    use opentelemetry_proto::tonic::collector::trace::v1::{
+        ExportTraceServiceRequest,
+        trace_service_client::TraceServiceClient,
+    };
+    use tokio::sync::mpsc::{ Receiver, channel };
+
+    use crate::otlp_service_log::OtlpServiceLogs;
+
+    const CLIENT_SPANS: &str =
+        r#"{
+        "resourceSpans": [{
+            "resource": { "attributes": [{ "key": "service.name", "value": { "stringValue": "graphql-service" } }] },
+            "scopeSpans": [{ "spans": [{
+                "traceId": "5b8efff798038103d269b633813fc60c",
+                "spanId": "eee19b7ec3c1b173",
+                "name": "GET",
+                "kind": 3,
+                "attributes": [
+                    { "key": "http.request.method", "value": { "stringValue": "GET" } },
+                    { "key": "url.full", "value": { "stringValue": "http://users/users/42" } }
+                ]
+            }] }]
+        }]
+    }"#;
+
+    const SERVER_SPANS: &str =
+        r#"{
+        "resourceSpans": [{
+            "resource": { "attributes": [{ "key": "service.name", "value": { "stringValue": "users-service" } }] },
+            "scopeSpans": [{ "spans": [{
+                "traceId": "5b8efff798038103d269b633813fc60c",
+                "spanId": "eee19b7ec3c1b174",
+                "parentSpanId": "eee19b7ec3c1b173",
+                "name": "GET /users/{id}",
+                "kind": 2,
+                "attributes": [
+                    { "key": "http.request.method", "value": { "stringValue": "GET" } },
+                    { "key": "http.route", "value": { "stringValue": "/users/{id}" } }
+                ]
+            }] }]
+        }]
+    }"#;
+
+    fn free_address() -> SocketAddr {
+        TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap()
+    }
+
+    fn start() -> (SocketAddr, SocketAddr, Receiver<ServiceNodeGraph>) {
+        let http_address = free_address();
+        let grpc_address = free_address();
+        let service_logs = OtlpServiceLogs::new(http_address, grpc_address, vec![]).unwrap();
+
+        let (sender, receiver) = channel(32);
+        tokio::spawn(async move { service_logs.run(sender).await });
+
+        (http_address, grpc_address, receiver)
+    }
+
+    async fn post(
+        client: &reqwest::Client,
+        http_address: SocketAddr,
+        body: Vec<u8>,
+        content_encoding: &str
+    ) -> reqwest::StatusCode {
+        // The receiver is started in the background, retry until it is listening.
+        for _ in 0..50 {
+            let response = client
+                .post(format!("http://{}/v1/traces", http_address))
+                .header("content-type", "application/json")
+                .header("content-encoding", content_encoding)
+                .body(body.clone())
+                .send().await;
+
+            if let Ok(response) = response {
+                return response.status();
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        panic!("OTLP/HTTP receiver never started");
+    }
+
+    async fn post_json(client: &reqwest::Client, http_address: SocketAddr, body: &str) {
+        let status = post(client, http_address, body.as_bytes().to_vec(), "identity").await;
+        assert!(status.is_success());
+    }
+
+    #[tokio::test]
+    async fn should_link_services_from_spans_exported_over_http() {
+        let (http_address, _, mut receiver) = start();
+        let client = reqwest::Client::new();
+
+        post_json(&client, http_address, CLIENT_SPANS).await;
+        post_json(&client, http_address, SERVER_SPANS).await;
+
+        receiver.recv().await.unwrap();
+        let graph = receiver.recv().await.unwrap();
+        assert_eq!(graph.services["users-service"].operations.len(), 1);
+        assert_eq!(graph.services["graphql-service"].invokes["users-service"].len(), 1);
+    }
+
+    #[tokio::test]
+    async fn should_decompress_gzipped_exports() {
+        let (http_address, _, mut receiver) = start();
+        let client = reqwest::Client::new();
+
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(SERVER_SPANS.as_bytes()).unwrap();
+        let status = post(&client, http_address, encoder.finish().unwrap(), "gzip").await;
+        assert!(status.is_success());
+
+        let graph = receiver.recv().await.unwrap();
+        assert_eq!(graph.services["users-service"].operations.len(), 1);
+
+        let status = post(&client, http_address, SERVER_SPANS.as_bytes().to_vec(), "br").await;
+        assert_eq!(status, reqwest::StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    }
+
+    #[tokio::test]
+    async fn should_receive_spans_exported_over_grpc() {
+        let (_, grpc_address, mut receiver) = start();
+
+        let mut client = None;
+        for _ in 0..50 {
+            if let Ok(connected) = TraceServiceClient::connect(format!("http://{}", grpc_address)).await {
+                client = Some(connected);
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        let mut client = client.expect("OTLP/gRPC receiver never started");
+
+        let request: ExportTraceServiceRequest = serde_json::from_str(SERVER_SPANS).unwrap();
+        client.export(request).await.unwrap();
+
+        let graph = receiver.recv().await.unwrap();
+        assert_eq!(graph.services["users-service"].operations.len(), 1);
+    }
+}
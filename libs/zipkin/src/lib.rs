@@ -0,0 +1,3 @@
+pub mod model;
+pub mod zipkin_collector_service_log;
+pub mod zipkin_file_service_log;
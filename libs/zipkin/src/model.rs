@@ -0,0 +1,69 @@
+use std::collections::HashMap;
+
+use logs_to_graph::span_graph::{ GraphSpan, SpanKind, operation_from_attributes };
+use regex::Regex;
+use serde::Deserialize;
+
+/// Service name used by Zipkin when a span has no local endpoint.
+const UNKNOWN_SERVICE: &str = "unknown";
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Endpoint {
+    pub service_name: Option<String>,
+}
+
+/// A span in the Zipkin v2 JSON format.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ZipkinSpan {
+    pub trace_id: String,
+    pub id: String,
+    pub parent_id: Option<String>,
+    pub name: Option<String>,
+    pub kind: Option<String>,
+    pub local_endpoint: Option<Endpoint>,
+    #[serde(default)]
+    pub tags: HashMap<String, String>,
+    /// Unix time in microseconds at which the span started.
+    pub timestamp: Option<i64>,
+}
+
+/// Zipkin dumps are either a list of spans or, as returned by `/api/v2/traces`, a list of traces.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum ZipkinDocument {
+    Trace(Vec<ZipkinSpan>),
+    Span(ZipkinSpan),
+}
+
+impl ZipkinDocument {
+    pub fn into_spans(self) -> Vec<ZipkinSpan> {
+        match self {
+            ZipkinDocument::Trace(spans) => spans,
+            ZipkinDocument::Span(span) => vec![span],
+        }
+    }
+}
+
+impl ZipkinSpan {
+    /// Shared client/server halves keep their common id, [`SpanGraphBuilder`] pairs them up.
+    ///
+    /// [`SpanGraphBuilder`]: logs_to_graph::span_graph::SpanGraphBuilder
+    pub fn to_graph_span(&self, path_normalize_regexes: &[(String, Vec<Regex>)]) -> GraphSpan {
+        let service = self.local_endpoint
+            .as_ref()
+            .and_then(|endpoint| endpoint.service_name.clone())
+            .unwrap_or(UNKNOWN_SERVICE.into());
+
+        GraphSpan {
+            trace_id: self.trace_id.clone(),
+            span_id: self.id.clone(),
+            parent_span_id: self.parent_id.clone(),
+            service,
+            kind: self.kind.as_deref().map(SpanKind::from_name).unwrap_or(SpanKind::Internal),
+            operation: operation_from_attributes(&self.tags, path_normalize_regexes),
+            start_time: self.timestamp.map(|timestamp| timestamp / 1_000_000),
+        }
+    }
+}
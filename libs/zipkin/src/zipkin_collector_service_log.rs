@@ -0,0 +1,226 @@
+use std::{ net::SocketAddr, sync::Arc };
+
+use anyhow::{ Context, Ok, Result };
+use async_trait::async_trait;
+use axum::{
+    Router,
+    body::Bytes,
+    extract::State,
+    http::{ HeaderMap, StatusCode, header::CONTENT_ENCODING },
+    routing::post,
+};
+use common::{ encoding::{ BodyError, MAX_DECODED_BODY, decode_body }, marc, types::MArc };
+use logs_to_graph::{
+    normalize::build_path_normalize_regexes,
+    service_logs::ServiceLogs,
+    service_node_graph::ServiceNodeGraph,
+    span_graph::SpanGraphBuilder,
+};
+use regex::Regex;
+use tokio::{ net::TcpListener, sync::mpsc::Sender };
+use tracing::{ debug, error, info, warn };
+
+use crate::model::ZipkinSpan;
+
+const SPANS_PATH: &str = "/api/v2/spans";
+
+/// Exposes the Zipkin v2 collector API, for instrumentations that can only report to Zipkin.
+pub struct ZipkinCollectorServiceLogs {
+    address: SocketAddr,
+    path_normalize_regexes: Vec<(String, Vec<Regex>)>,
+}
+
+impl ZipkinCollectorServiceLogs {
+    pub fn new(address: SocketAddr, custom_path_normalize_patterns: Vec<String>) -> Result<Self> {
+        let path_normalize_regexes = build_path_normalize_regexes(
+            &custom_path_normalize_patterns
+        )?;
+
+        Ok(Self { address, path_normalize_regexes })
+    }
+}
+
+#[derive(Clone)]
+struct Collector {
+    span_graph_builder: MArc<SpanGraphBuilder>,
+    path_normalize_regexes: Arc<Vec<(String, Vec<Regex>)>>,
+    sender: Sender<ServiceNodeGraph>,
+}
+
+/// Zipkin reporters only expect a 202, the spans are processed before answering
+/// so that a reporter is slowed down when the graph consumer applies backpressure.
+async fn collect_spans(
+    State(collector): State<Collector>,
+    headers: HeaderMap,
+    body: Bytes
+) -> StatusCode {
+    let content_encoding = headers
+        .get(CONTENT_ENCODING)
+        .and_then(|content_encoding| content_encoding.to_str().ok());
+    let body = match decode_body(content_encoding, &body) {
+        std::result::Result::Ok(body) => body,
+        Err(BodyError::UnsupportedEncoding(encoding)) => {
+            warn!("Rejected Zipkin spans with an unsupported content encoding: {}", encoding);
+            return StatusCode::UNSUPPORTED_MEDIA_TYPE;
+        }
+        Err(BodyError::Malformed(e)) => {
+            warn!("Rejected malformed Zipkin spans: {}", e);
+            return StatusCode::BAD_REQUEST;
+        }
+        Err(BodyError::TooLarge) => {
+            warn!("Rejected Zipkin spans larger than {} bytes once decompressed", MAX_DECODED_BODY);
+            return StatusCode::PAYLOAD_TOO_LARGE;
+        }
+    };
+
+    let spans = match serde_json::from_slice::<Vec<ZipkinSpan>>(&body) {
+        std::result::Result::Ok(spans) => spans,
+        Err(e) => {
+            warn!("Rejected malformed Zipkin spans: {}", e);
+            return StatusCode::BAD_REQUEST;
+        }
+    };
+
+    if spans.is_empty() {
+        return StatusCode::ACCEPTED;
+    }
+
+    debug!("Received {} Zipkin spans", spans.len());
+
+    let service_node_graph = {
+        let mut span_graph_builder = collector.span_graph_builder.lock().await;
+        for span in spans.iter() {
+            span_graph_builder.add_span(span.to_graph_span(&collector.path_normalize_regexes));
+        }
+        span_graph_builder.service_node_graph.clone()
+    };
+
+    if collector.sender.send(service_node_graph).await.is_err() {
+        error!("Failed to send service node graph from the Service Logs to the Service Graph.");
+    }
+
+    StatusCode::ACCEPTED
+}
+
+#[async_trait]
+impl ServiceLogs for ZipkinCollectorServiceLogs {
+    async fn run(&self, sender: Sender<ServiceNodeGraph>) -> Result<()> {
+        let collector = Collector {
+            span_graph_builder: marc!(SpanGraphBuilder::default()),
+            path_normalize_regexes: Arc::new(self.path_normalize_regexes.clone()),
+            sender,
+        };
+
+        let listener = TcpListener::bind(self.address).await.with_context(||
+            format!("Failed binding Zipkin collector on {}", self.address)
+        )?;
+        let router = Router::new().route(SPANS_PATH, post(collect_spans)).with_state(collector);
+
+        info!("Receiving Zipkin spans on {}{}", self.address, SPANS_PATH);
+        axum::serve(listener, router).await.context("Zipkin collector failed")?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{ io::Write, net::{ SocketAddr, TcpListener }, time::Duration };
+
+    use common::encoding::MAX_DECODED_BODY;
+    use flate2::{ Compression, write::GzEncoder };
+    use logs_to_graph::service_logs::ServiceLogs;
+    use tokio::sync::mpsc::channel;
+
+    use crate::zipkin_collector_service_log::ZipkinCollectorServiceLogs;
+
+    const CLIENT_SPANS: &str =
+        r#"[{
+        "traceId": "5af7183fb1d4cf5f",
+        "id": "6b221d5bc9e6496c",
+        "kind": "CLIENT",
+        "localEndpoint": { "serviceName": "graphql-service" },
+        "tags": { "http.method": "GET", "http.path": "/users/42" }
+    }]"#;
+
+    const SERVER_SPANS: &str =
+        r#"[{
+        "traceId": "5af7183fb1d4cf5f",
+        "id": "6b221d5bc9e6496c",
+        "kind": "SERVER",
+        "shared": true,
+        "localEndpoint": { "serviceName": "users-service" },
+        "tags": { "http.method": "GET", "http.path": "/users/42" }
+    }]"#;
+
+    async fn post(
+        client: &reqwest::Client,
+        address: SocketAddr,
+        body: Vec<u8>,
+        content_encoding: &str
+    ) -> reqwest::StatusCode {
+        // The collector is started in the background, retry until it is listening.
+        for _ in 0..50 {
+            let response = client
+                .post(format!("http://{}/api/v2/spans", address))
+                .header("content-type", "application/json")
+                .header("content-encoding", content_encoding)
+                .body(body.clone())
+                .send().await;
+
+            if let Ok(response) = response {
+                return response.status();
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        panic!("Zipkin collector never started");
+    }
+
+    async fn post_spans(client: &reqwest::Client, address: SocketAddr, body: &str) {
+        let status = post(client, address, body.as_bytes().to_vec(), "identity").await;
+        assert_eq!(status, reqwest::StatusCode::ACCEPTED);
+    }
+
+    #[tokio::test]
+    async fn should_link_spans_reported_separately() {
+        let address = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let service_logs = ZipkinCollectorServiceLogs::new(address, vec![]).unwrap();
+        let (sender, mut receiver) = channel(32);
+        tokio::spawn(async move { service_logs.run(sender).await });
+
+        let client = reqwest::Client::new();
+        post_spans(&client, address, CLIENT_SPANS).await;
+        post_spans(&client, address, SERVER_SPANS).await;
+
+        receiver.recv().await.unwrap();
+        let graph = receiver.recv().await.unwrap();
+        assert_eq!(graph.services["users-service"].operations.len(), 1);
+        assert_eq!(graph.services["graphql-service"].invokes["users-service"].len(), 1);
+    }
+
+    #[tokio::test]
+    async fn should_decompress_gzipped_spans() {
+        let address = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let service_logs = ZipkinCollectorServiceLogs::new(address, vec![]).unwrap();
+        let (sender, mut receiver) = channel(32);
+        tokio::spawn(async move { service_logs.run(sender).await });
+
+        let client = reqwest::Client::new();
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(SERVER_SPANS.as_bytes()).unwrap();
+        let status = post(&client, address, encoder.finish().unwrap(), "gzip").await;
+        assert_eq!(status, reqwest::StatusCode::ACCEPTED);
+
+        let graph = receiver.recv().await.unwrap();
+        assert_eq!(graph.services["users-service"].operations.len(), 1);
+
+        let status = post(&client, address, SERVER_SPANS.as_bytes().to_vec(), "br").await;
+        assert_eq!(status, reqwest::StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+        // A small body expanding beyond the limit.
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(&vec![b' '; (MAX_DECODED_BODY as usize) + 1]).unwrap();
+        let status = post(&client, address, encoder.finish().unwrap(), "gzip").await;
+        assert_eq!(status, reqwest::StatusCode::PAYLOAD_TOO_LARGE);
+    }
+}
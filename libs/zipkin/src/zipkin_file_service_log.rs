@@ -0,0 +1,124 @@
+use std::path::PathBuf;
+
+use anyhow::{ Ok, Result };
+use async_trait::async_trait;
+use logs_to_graph::{
+    json_files::read_json_documents,
+    normalize::build_path_normalize_regexes,
+    service_logs::ServiceLogs,
+    service_node_graph::ServiceNodeGraph,
+    span_graph::SpanGraphBuilder,
+};
+use regex::Regex;
+use tokio::sync::mpsc::Sender;
+use tracing::{ error, info };
+
+use crate::model::ZipkinDocument;
+
+/// Rebuilds the graph from Zipkin v2 JSON span archives.
+pub struct ZipkinFileServiceLogs {
+    paths: Vec<PathBuf>,
+    path_normalize_regexes: Vec<(String, Vec<Regex>)>,
+}
+
+impl ZipkinFileServiceLogs {
+    pub fn new(paths: Vec<PathBuf>, custom_path_normalize_patterns: Vec<String>) -> Result<Self> {
+        let path_normalize_regexes = build_path_normalize_regexes(
+            &custom_path_normalize_patterns
+        )?;
+
+        Ok(Self { paths, path_normalize_regexes })
+    }
+}
+
+#[async_trait]
+impl ServiceLogs for ZipkinFileServiceLogs {
+    async fn run(&self, sender: Sender<ServiceNodeGraph>) -> Result<()> {
+        let spans = read_json_documents::<ZipkinDocument>(&self.paths)?
+            .into_iter()
+            .flat_map(ZipkinDocument::into_spans)
+            .collect::<Vec<_>>();
+        info!("Processing {} Zipkin spans", spans.len());
+
+        let mut span_graph_builder = SpanGraphBuilder::default();
+        for span in spans.iter() {
+            span_graph_builder.add_span(span.to_graph_span(&self.path_normalize_regexes));
+        }
+
+        if sender.send(span_graph_builder.service_node_graph).await.is_err() {
+            error!("Failed to send service node graph from the Service Logs to the Service Graph.");
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use logs_to_graph::{ service_logs::ServiceLogs, service_node_graph::Operation };
+    use tokio::sync::mpsc::channel;
+
+    use crate::zipkin_file_service_log::ZipkinFileServiceLogs;
+
+    const TRACES: &str =
+        r#"[[
+        {
+            "traceId": "5af7183fb1d4cf5f",
+            "id": "6b221d5bc9e6496c",
+            "name": "get",
+            "kind": "CLIENT",
+            "localEndpoint": { "serviceName": "graphql-service" },
+            "tags": { "http.method": "GET", "http.path": "/users/42" }
+        },
+        {
+            "traceId": "5af7183fb1d4cf5f",
+            "id": "6b221d5bc9e6496c",
+            "name": "get /users/{id}",
+            "kind": "SERVER",
+            "shared": true,
+            "localEndpoint": { "serviceName": "users-service" },
+            "tags": { "http.method": "GET", "http.path": "/users/42" }
+        },
+        {
+            "traceId": "5af7183fb1d4cf5f",
+            "id": "352bff9a74ca9ad2",
+            "parentId": "6b221d5bc9e6496c",
+            "name": "get",
+            "kind": "CLIENT",
+            "localEndpoint": { "serviceName": "users-service" },
+            "tags": { "http.method": "GET", "http.url": "http://books/books" }
+        },
+        {
+            "traceId": "5af7183fb1d4cf5f",
+            "id": "352bff9a74ca9ad2",
+            "name": "get",
+            "kind": "SERVER",
+            "shared": true,
+            "localEndpoint": { "serviceName": "books-service" },
+            "tags": { "http.method": "GET", "http.route": "/books" }
+        }
+    ]]"#;
+
+    #[tokio::test]
+    async fn should_rebuild_the_graph_from_zipkin_traces() {
+        let path = std::env::temp_dir().join(format!("rtc-zipkin-{}.json", std::process::id()));
+        fs::write(&path, TRACES).unwrap();
+
+        let service_logs = ZipkinFileServiceLogs::new(vec![path.clone()], vec![]).unwrap();
+        let (sender, mut receiver) = channel(32);
+        service_logs.run(sender).await.unwrap();
+        fs::remove_file(path).unwrap();
+
+        let graph = receiver.recv().await.unwrap();
+        assert_eq!(graph.services.len(), 3);
+        assert_eq!(
+            graph.services["users-service"].operations.values().next(),
+            Some(&(Operation::Http { method: "GET".into(), path: "/users/{users_id}".into() }))
+        );
+        assert_eq!(graph.services["graphql-service"].invokes["users-service"].len(), 1);
+        assert_eq!(graph.services["users-service"].invokes["books-service"].len(), 1);
+        assert!(!graph.services["graphql-service"].invokes.contains_key("books-service"));
+    }
+}